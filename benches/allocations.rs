@@ -0,0 +1,118 @@
+//! Allocations per full machine run for each pattern
+//!
+//! Run with `cargo bench --bench allocations`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Global allocator that counts every allocation before forwarding it to the system allocator
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Allocations and bytes allocated while running `run` once
+fn count<F: FnOnce()>(run: F) -> (usize, usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+
+    run();
+
+    (
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+    )
+}
+
+fn report<F: FnOnce()>(name: &str, run: F) {
+    let (allocations, bytes) = count(run);
+    println!("{name:<24} {allocations:>12} {bytes:>12}");
+}
+
+fn main() {
+    println!("{:<24} {:>12} {:>12}", "pattern", "allocations", "bytes");
+
+    report("enum", state_machine::internal_enum::run_full_state_machine);
+
+    // the events are created outside of the measured run
+    let events = state_machine::external_enum::full_state_machine_events();
+    report("external enum", || {
+        state_machine::external_enum::run_full_state_machine(events)
+    });
+
+    report(
+        "dyn trait",
+        state_machine::dyn_trait::run_full_state_machine,
+    );
+    report(
+        "dyn trait (inline)",
+        state_machine::dyn_trait::run_full_state_machine_inline,
+    );
+
+    #[cfg(feature = "bumpalo")]
+    {
+        // warm up the arena, so only the allocations of the run itself are counted
+        let mut arena = bumpalo::Bump::new();
+        state_machine::dyn_trait::run_full_state_machine_in(&arena);
+        arena.reset();
+        report("dyn trait (arena)", || {
+            state_machine::dyn_trait::run_full_state_machine_in(&arena)
+        });
+    }
+
+    report(
+        "enum dispatch",
+        state_machine::enum_dispatch::run_full_state_machine,
+    );
+    #[cfg(feature = "heapless")]
+    report(
+        "heapless enum",
+        state_machine::heapless_enum::run_full_state_machine,
+    );
+    report(
+        "table driven",
+        state_machine::table_driven::run_full_state_machine,
+    );
+    report("fn table", state_machine::fn_table::run_full_state_machine);
+    report(
+        "compose",
+        state_machine::compose_trait::run_full_state_machine,
+    );
+
+    #[cfg(feature = "bench-support")]
+    {
+        use state_machine::bench_support::states_100;
+
+        println!();
+        println!("{:<24} {:>12} {:>12}", "100 states", "allocations", "bytes");
+        report("enum", states_100::internal_enum::run);
+        let events = states_100::external_enum::events();
+        report("external enum", || states_100::external_enum::run(events));
+        report("dyn trait", states_100::dyn_trait::run);
+        report("enum dispatch", states_100::enum_dispatch::run);
+        report("compose", states_100::compose_trait::run);
+    }
+}
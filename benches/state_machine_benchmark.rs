@@ -1,4 +1,11 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use std::time::Duration;
+
+#[cfg(feature = "bench-support")]
+use criterion::BenchmarkId;
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BatchSize, BenchmarkGroup, Criterion,
+};
+use state_machine::machine::{Composed, ExternallyDriven, InternallyDriven, Machine};
 
 fn bench_fibs(c: &mut Criterion) {
     let mut group = c.benchmark_group("Comparison");
@@ -6,10 +13,50 @@ fn bench_fibs(c: &mut Criterion) {
         b.iter(state_machine::internal_enum::run_full_state_machine)
     });
 
+    group.bench_function("external enum", |b| {
+        b.iter_batched(
+            state_machine::external_enum::full_state_machine_events,
+            state_machine::external_enum::run_full_state_machine,
+            BatchSize::SmallInput,
+        )
+    });
+
     group.bench_function("dyn trait", |b| {
         b.iter(state_machine::dyn_trait::run_full_state_machine)
     });
 
+    group.bench_function("dyn trait (inline)", |b| {
+        b.iter(state_machine::dyn_trait::run_full_state_machine_inline)
+    });
+
+    #[cfg(feature = "bumpalo")]
+    {
+        let mut arena = bumpalo::Bump::new();
+        group.bench_function("dyn trait (arena)", |b| {
+            b.iter(|| {
+                arena.reset();
+                state_machine::dyn_trait::run_full_state_machine_in(&arena)
+            })
+        });
+    }
+
+    group.bench_function("enum dispatch", |b| {
+        b.iter(state_machine::enum_dispatch::run_full_state_machine)
+    });
+
+    #[cfg(feature = "heapless")]
+    group.bench_function("heapless enum", |b| {
+        b.iter(state_machine::heapless_enum::run_full_state_machine)
+    });
+
+    group.bench_function("table driven", |b| {
+        b.iter(state_machine::table_driven::run_full_state_machine)
+    });
+
+    group.bench_function("fn table", |b| {
+        b.iter(state_machine::fn_table::run_full_state_machine)
+    });
+
     group.bench_function("compose", |b| {
         b.iter(state_machine::compose_trait::run_full_state_machine)
     });
@@ -17,5 +64,188 @@ fn bench_fibs(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_fibs);
+/// Run any pattern through the [Machine] trait, `machine` builds a new machine for every iteration
+fn bench_machine<M, F>(group: &mut BenchmarkGroup<'_, WallTime>, name: &str, mut machine: F)
+where
+    M: Machine,
+    F: FnMut() -> M,
+{
+    group.bench_function(name, |b| {
+        b.iter_batched(
+            &mut machine,
+            |machine| machine.run().unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+/// The four original patterns, driven through the generic [Machine] trait
+fn bench_machines(c: &mut Criterion) {
+    use state_machine::{compose_trait, dyn_trait, external_enum, internal_enum};
+
+    let mut group = c.benchmark_group("Machine");
+    bench_machine(&mut group, "enum", || {
+        InternallyDriven(internal_enum::FullStateMachine::DiscoverNodes(
+            Default::default(),
+        ))
+    });
+    bench_machine(&mut group, "external enum", || {
+        ExternallyDriven::new(
+            external_enum::FullStateMachine::DiscoverNodes(Default::default()),
+            external_enum::full_state_machine_events(),
+        )
+    });
+    bench_machine(&mut group, "dyn trait", || {
+        Box::<dyn_trait::DiscoverNodes>::default() as Box<dyn dyn_trait::State>
+    });
+    bench_machine(&mut group, "compose", || {
+        Composed(compose_trait::full_state_machine())
+    });
+    group.finish();
+}
+
+/// Same comparison, but the mock I/O of each state spins for a few microseconds
+fn bench_latency(c: &mut Criterion) {
+    for latency in [1, 10] {
+        state_machine::set_simulated_latency(Duration::from_micros(latency));
+
+        let mut group = c.benchmark_group(format!("Simulated latency {latency}us"));
+        group.bench_function("enum", |b| {
+            b.iter(state_machine::internal_enum::run_full_state_machine)
+        });
+
+        group.bench_function("external enum", |b| {
+            b.iter_batched(
+                state_machine::external_enum::full_state_machine_events,
+                state_machine::external_enum::run_full_state_machine,
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.bench_function("dyn trait", |b| {
+            b.iter(state_machine::dyn_trait::run_full_state_machine)
+        });
+
+        group.bench_function("enum dispatch", |b| {
+            b.iter(state_machine::enum_dispatch::run_full_state_machine)
+        });
+
+        group.bench_function("compose", |b| {
+            b.iter(state_machine::compose_trait::run_full_state_machine)
+        });
+
+        group.finish();
+    }
+
+    state_machine::set_simulated_latency(Duration::ZERO);
+}
+
+/// Sync executor against the async executor on single and multi threaded tokio runtimes
+fn bench_async(c: &mut Criterion) {
+    #[cfg(feature = "tokio")]
+    {
+        let mut group = c.benchmark_group("Async");
+        group.bench_function("sync", |b| {
+            b.iter_batched(
+                state_machine::external_enum::full_state_machine_events,
+                state_machine::external_enum::run_full_state_machine,
+                BatchSize::SmallInput,
+            )
+        });
+
+        let current_thread = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        group.bench_function("tokio current thread", |b| {
+            b.iter_batched(
+                state_machine::external_enum::full_state_machine_events_async,
+                |events| {
+                    current_thread.block_on(
+                        state_machine::external_enum::run_full_state_machine_async(events),
+                    )
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        let multi_thread = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+        group.bench_function("tokio multi thread", |b| {
+            b.iter_batched(
+                state_machine::external_enum::full_state_machine_events_async,
+                |events| {
+                    // spawned, so the machine runs on a worker thread instead of the bench thread
+                    multi_thread
+                        .block_on(multi_thread.spawn(
+                            state_machine::external_enum::run_full_state_machine_async(events),
+                        ))
+                        .unwrap()
+                },
+                BatchSize::SmallInput,
+            )
+        });
+
+        group.finish();
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    let _ = c;
+}
+
+/// Add the benchmarks of the generated machines with `$size` states
+#[cfg(feature = "bench-support")]
+macro_rules! bench_size {
+    ($group:ident, $size:literal, $module:ident) => {
+        use state_machine::bench_support::$module;
+
+        $group.bench_with_input(BenchmarkId::new("enum", $size), &$size, |b, _| {
+            b.iter($module::internal_enum::run)
+        });
+        $group.bench_with_input(BenchmarkId::new("external enum", $size), &$size, |b, _| {
+            b.iter_batched(
+                $module::external_enum::events,
+                $module::external_enum::run,
+                BatchSize::SmallInput,
+            )
+        });
+        $group.bench_with_input(BenchmarkId::new("dyn trait", $size), &$size, |b, _| {
+            b.iter($module::dyn_trait::run)
+        });
+        $group.bench_with_input(BenchmarkId::new("enum dispatch", $size), &$size, |b, _| {
+            b.iter($module::enum_dispatch::run)
+        });
+        $group.bench_with_input(BenchmarkId::new("compose", $size), &$size, |b, _| {
+            b.iter($module::compose_trait::run)
+        });
+    };
+}
+
+/// How dispatch cost scales with the number of states, requires the `bench-support` feature
+fn bench_sizes(c: &mut Criterion) {
+    #[cfg(feature = "bench-support")]
+    {
+        let mut group = c.benchmark_group("Scaling");
+        {
+            bench_size!(group, 10, states_10);
+        }
+        {
+            bench_size!(group, 100, states_100);
+        }
+        {
+            bench_size!(group, 1000, states_1000);
+        }
+        group.finish();
+    }
+
+    #[cfg(not(feature = "bench-support"))]
+    let _ = c;
+}
+
+criterion_group!(
+    benches,
+    bench_fibs,
+    bench_machines,
+    bench_latency,
+    bench_async,
+    bench_sizes
+);
 criterion_main!(benches);
@@ -0,0 +1,321 @@
+use std::{env, fmt::Write, fs, path::Path};
+
+/// Number of states of the generated benchmark machines
+const SIZES: [usize; 3] = [10, 100, 1000];
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if env::var_os("CARGO_FEATURE_BENCH_SUPPORT").is_none() {
+        return;
+    }
+
+    let mut code = String::new();
+    for size in SIZES {
+        writeln!(code, "/// Machines with {size} states").unwrap();
+        writeln!(code, "pub mod states_{size} {{").unwrap();
+        internal_enum(&mut code, size);
+        external_enum(&mut code, size);
+        dyn_trait(&mut code, size);
+        enum_dispatch(&mut code, size);
+        compose_trait(&mut code, size);
+        writeln!(code, "}}").unwrap();
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("bench_machines.rs"), code).unwrap();
+}
+
+fn internal_enum(code: &mut String, size: usize) {
+    code.push_str(
+        "pub mod internal_enum {
+    use std::error::Error;
+
+    use crate::internal_enum::{internally_driven_executor, InternallyDrivenTransition};
+
+    pub fn run() {
+        internally_driven_executor(Machine::S0(0)).unwrap();
+    }
+
+",
+    );
+    machine_enum(code, size);
+    code.push_str(
+        "
+    impl InternallyDrivenTransition for Machine {
+        fn execute(self) -> Result<Self, Box<dyn Error>> {
+            match self {
+",
+    );
+    for i in 0..size {
+        writeln!(
+            code,
+            "                Machine::S{i}(value) => Ok(Machine::{}(value + 1)),",
+            next_variant(i, size)
+        )
+        .unwrap();
+    }
+    code.push_str(
+        "                Machine::Done(_) => unreachable!(),
+            }
+        }
+
+        fn is_terminal_state(&self) -> bool {
+            matches!(self, Machine::Done(_))
+        }
+    }
+}
+",
+    );
+}
+
+fn external_enum(code: &mut String, size: usize) {
+    writeln!(
+        code,
+        "pub mod external_enum {{
+    use std::{{collections::VecDeque, error::Error}};
+
+    use crate::external_enum::{{
+        externally_driven_executor, EventContext, ExternallyDrivenTransition,
+    }};
+
+    /// Events needed by [run]
+    pub fn events() -> VecDeque<()> {{
+        VecDeque::from(vec![(); {size}])
+    }}
+
+    pub fn run(events: VecDeque<()>) {{
+        externally_driven_executor(Machine::S0(0), events).unwrap();
+    }}
+"
+    )
+    .unwrap();
+    machine_enum(code, size);
+    code.push_str(
+        "
+    impl ExternallyDrivenTransition for Machine {
+        type EventType = ();
+
+        fn execute(
+            &mut self,
+            _input: Self::EventType,
+            _context: &mut EventContext<Self::EventType>,
+        ) -> Result<(), Box<dyn Error>> {
+            match self {
+                Machine::Done(_) => unreachable!(),
+",
+    );
+    for i in 0..size {
+        writeln!(code, "                Machine::S{i}(value) => *value += 1,").unwrap();
+    }
+    code.push_str(
+        "            }
+
+            Ok(())
+        }
+
+        fn is_terminal_state(&self) -> bool {
+            matches!(self, Machine::Done(_))
+        }
+
+        fn transition(self) -> Self {
+            match self {
+",
+    );
+    for i in 0..size {
+        writeln!(
+            code,
+            "                Machine::S{i}(value) => Machine::{}(value),",
+            next_variant(i, size)
+        )
+        .unwrap();
+    }
+    code.push_str(
+        "                Machine::Done(_) => unreachable!(),
+            }
+        }
+    }
+}
+",
+    );
+}
+
+fn dyn_trait(code: &mut String, size: usize) {
+    code.push_str(
+        "pub mod dyn_trait {
+    use std::error::Error;
+
+    use crate::dyn_trait::{executor, State};
+
+    pub fn run() {
+        executor(Box::new(S0(0))).unwrap();
+    }
+",
+    );
+    for i in 0..size {
+        let next = if i + 1 == size {
+            "None".to_string()
+        } else {
+            format!("Some(Box::new(S{}(self.0 + 1)))", i + 1)
+        };
+        writeln!(
+            code,
+            "
+    pub struct S{i}(pub u64);
+
+    impl State for S{i} {{
+        fn execute(self: Box<Self>) -> Result<Option<Box<dyn State>>, Box<dyn Error>> {{
+            Ok({next})
+        }}
+    }}"
+        )
+        .unwrap();
+    }
+    code.push_str("}\n");
+}
+
+fn enum_dispatch(code: &mut String, size: usize) {
+    code.push_str(
+        "pub mod enum_dispatch {
+    use std::error::Error;
+
+    pub fn run() {
+        let mut current_state = Some(Machine::S0(S0(0)));
+        while let Some(state) = current_state {
+            current_state = state.execute().unwrap();
+        }
+    }
+
+    pub trait State {
+        fn execute(self) -> Result<Option<Machine>, Box<dyn Error>>;
+    }
+
+    pub enum Machine {
+",
+    );
+    for i in 0..size {
+        writeln!(code, "        S{i}(S{i}),").unwrap();
+    }
+    code.push_str(
+        "    }
+
+    impl State for Machine {
+        fn execute(self) -> Result<Option<Machine>, Box<dyn Error>> {
+            match self {
+",
+    );
+    for i in 0..size {
+        writeln!(
+            code,
+            "                Machine::S{i}(state) => state.execute(),"
+        )
+        .unwrap();
+    }
+    code.push_str(
+        "            }
+        }
+    }
+",
+    );
+    for i in 0..size {
+        let next = if i + 1 == size {
+            "None".to_string()
+        } else {
+            format!("Some(Machine::S{0}(S{0}(self.0 + 1)))", i + 1)
+        };
+        writeln!(
+            code,
+            "
+    pub struct S{i}(pub u64);
+
+    impl State for S{i} {{
+        fn execute(self) -> Result<Option<Machine>, Box<dyn Error>> {{
+            Ok({next})
+        }}
+    }}"
+        )
+        .unwrap();
+    }
+    code.push_str("}\n");
+}
+
+fn compose_trait(code: &mut String, size: usize) {
+    code.push_str(
+        "pub mod compose_trait {
+    use std::error::Error;
+
+    use crate::compose_trait::{State, StateComposer};
+",
+    );
+
+    // Chains are split in groups of at most 10 states behind `impl State` functions, otherwise
+    // the nested `AndThen` types overflow the compiler recursion limit
+    let mut items: Vec<String> = (0..size).map(|i| format!("S{i}")).collect();
+    let mut level = 0;
+    while items.len() > 1 {
+        let mut groups = Vec::new();
+        for (group, chunk) in items.chunks(10).enumerate() {
+            let name = format!("chain_{level}_{group}");
+            write!(
+                code,
+                "
+    fn {name}(value: u64) -> impl State<Output = u64> {{
+        {}(value)",
+                chunk[0]
+            )
+            .unwrap();
+            for item in &chunk[1..] {
+                write!(code, "\n            .and_then({item})").unwrap();
+            }
+            code.push_str("\n    }\n");
+            groups.push(name);
+        }
+        items = groups;
+        level += 1;
+    }
+
+    writeln!(
+        code,
+        "
+    pub fn run() {{
+        {}(0).execute().unwrap();
+    }}",
+        items[0]
+    )
+    .unwrap();
+
+    for i in 0..size {
+        writeln!(
+            code,
+            "
+    pub struct S{i}(pub u64);
+
+    impl State for S{i} {{
+        type Output = u64;
+
+        fn execute(self) -> Result<Self::Output, Box<dyn Error>> {{
+            Ok(self.0 + 1)
+        }}
+    }}"
+        )
+        .unwrap();
+    }
+    code.push_str("}\n");
+}
+
+/// Enum with one variant per state, plus the terminal state
+fn machine_enum(code: &mut String, size: usize) {
+    code.push_str("    pub enum Machine {\n");
+    for i in 0..size {
+        writeln!(code, "        S{i}(u64),").unwrap();
+    }
+    code.push_str("        Done(u64),\n    }\n");
+}
+
+fn next_variant(state: usize, size: usize) -> String {
+    if state + 1 == size {
+        "Done".to_string()
+    } else {
+        format!("S{}", state + 1)
+    }
+}
@@ -0,0 +1,80 @@
+//! Drive the external enum machine with events decoded from the input, every transition must be
+//! one of the machine
+//!
+//! Run with `cargo fuzz run external_events`
+
+#![no_main]
+
+use std::{
+    collections::VecDeque,
+    net::{IpAddr, Ipv4Addr},
+};
+
+use libfuzzer_sys::fuzz_target;
+use state_machine::{
+    discovery::Discovery,
+    external_enum::{externally_driven_executor, DiscoverNodes, ExternalEvent, FullStateMachine},
+    transition_log::{InMemoryLog, Logged},
+};
+
+/// Longer inputs don't reach new states, they only make every run slower
+const MAX_EVENTS: usize = 64;
+
+/// Transitions of [FullStateMachine], `(from, to)`
+const TRANSITIONS: [(&str, &str); 14] = [
+    ("DiscoverNodes", "DiscoverNodes"),
+    ("DiscoverNodes", "ConnectNodes"),
+    ("ConnectNodes", "ConnectNodes"),
+    ("ConnectNodes", "InsufficientQuorum"),
+    ("ConnectNodes", "Consensus"),
+    ("Consensus", "Consensus"),
+    ("Consensus", "InsufficientQuorum"),
+    ("Consensus", "Leader"),
+    ("Consensus", "Follower"),
+    ("InsufficientQuorum", "DiscoverNodes"),
+    ("Leader", "Consensus"),
+    ("Leader", "Terminate"),
+    ("Follower", "Consensus"),
+    ("Follower", "Terminate"),
+];
+
+fuzz_target!(|data: &[u8]| {
+    let machine = FullStateMachine::DiscoverNodes(DiscoverNodes::new(Discovery::default()));
+    let logged = Logged::new(machine, InMemoryLog::new());
+
+    // the nodes can't be reached, failing to connect to them is part of the paths being fuzzed
+    if let Ok(logged) = externally_driven_executor(logged, decode(data)) {
+        let (_, log) = logged.finish().expect("in memory log can't fail");
+        for record in log.records() {
+            assert!(
+                TRANSITIONS.contains(&(record.from, record.to)),
+                "unexpected transition {} -> {} on {:?}",
+                record.from,
+                record.to,
+                record.event
+            );
+        }
+    }
+});
+
+/// Two bytes per event, the kind and the last octet of a loopback node, where nothing listens
+fn decode(data: &[u8]) -> VecDeque<ExternalEvent> {
+    data.chunks_exact(2)
+        .take(MAX_EVENTS)
+        .map(|chunk| {
+            let node = IpAddr::V4(Ipv4Addr::new(127, 0, 0, chunk[1].max(2)));
+            match chunk[0] % 7 {
+                0 => ExternalEvent::Step,
+                1 => ExternalEvent::PeerDown(node),
+                2 => ExternalEvent::NodeJoined(node),
+                3 => ExternalEvent::NodeLeft(node),
+                4 => ExternalEvent::NodesDiscovered(vec![node]),
+                5 => ExternalEvent::VoteReceived {
+                    from: node,
+                    term: u64::from(chunk[0] / 7),
+                },
+                _ => ExternalEvent::SyncData(chunk.to_vec()),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,91 @@
+//! Interop with `anyhow`, for applications that standardize on it
+//!
+//! Errors of the states are not `Send`, so they are converted to an [anyhow::Error] carrying the
+//! messages of their chain of sources. The executors attach the state that failed as context,
+//! like `while in ConnectNodes`
+
+use std::error::Error;
+
+use anyhow::Context;
+
+use crate::{
+    error::MachineError,
+    event_source::EventSource,
+    external_enum::{process_event, EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    StateName,
+};
+
+impl MachineError {
+    /// Convert to an [anyhow::Error], which `From` can't do since a [MachineError] isn't `Send`
+    pub fn into_anyhow(self) -> anyhow::Error {
+        to_anyhow(&self)
+    }
+}
+
+impl From<anyhow::Error> for MachineError {
+    fn from(err: anyhow::Error) -> Self {
+        MachineError::State(err.into())
+    }
+}
+
+/// Same as [crate::internal_enum::internally_driven_executor], but a failure carries the state
+/// that was executing as context
+pub fn internally_driven_executor_anyhow<T>(initial_state: T) -> anyhow::Result<T>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    let mut current_state = initial_state;
+
+    while !current_state.is_terminal_state() {
+        let state = current_state.state_name();
+        current_state = current_state
+            .execute()
+            .map_err(|err| to_anyhow(&*err))
+            .with_context(|| format!("while in {state}"))?;
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [crate::external_enum::externally_driven_executor], but a failure carries the state
+/// that was executing the event as context
+pub fn externally_driven_executor_anyhow<T, S>(initial_state: T, mut events: S) -> anyhow::Result<T>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        let state = current_state.state_name();
+        current_state = process_event(current_state, input, &mut context)
+            .map_err(|err| to_anyhow(&*err))
+            .with_context(|| format!("while in {state}"))?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Rebuild the chain of `err` with its messages, the outermost error being the last context
+fn to_anyhow(err: &(dyn Error + 'static)) -> anyhow::Error {
+    let mut messages = vec![err.to_string()];
+    let mut source = err.source();
+    while let Some(err) = source {
+        messages.push(err.to_string());
+        source = err.source();
+    }
+
+    let root = messages.pop().unwrap_or_default();
+    messages
+        .into_iter()
+        .rev()
+        .fold(anyhow::Error::msg(root), anyhow::Error::context)
+}
@@ -0,0 +1,5 @@
+//! Machines with 10, 100 and 1000 states for each pattern, generated by `build.rs`
+//!
+//! Each state only increments a counter, so the benchmarks measure how the dispatch cost scales
+//! with the size of the machine
+include!(concat!(env!("OUT_DIR"), "/bench_machines.rs"));
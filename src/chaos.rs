@@ -0,0 +1,285 @@
+//! Fault injection, to test that machines recover from failing discovery, connections and states
+//!
+//! Faults are driven by a seeded generator, so a failing run can be reproduced with the same seed
+
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    mem,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, SharedClock},
+    compose_trait,
+    discovery::NodeDiscovery,
+    dyn_trait,
+    error::InjectedFaultError,
+    sim::SimRng,
+    transport::{Transport, HEADER_LEN},
+};
+
+/// Failures injected by a [Chaos] layer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    /// Fail every Nth call, counting the calls of everything wrapped by the same layer
+    pub fail_every: Option<usize>,
+    /// Every call is delayed by a random duration up to this one
+    pub max_delay: Duration,
+    /// Chance, between 0 and 1, that a wrapped connection is dropped on each frame read or
+    /// written
+    pub drop_rate: f64,
+    pub seed: u64,
+}
+
+struct Injector {
+    faults: Faults,
+    calls: usize,
+    rng: SimRng,
+}
+
+/// Shared fault injector, clones inject faults from the same sequence
+#[derive(Clone)]
+pub struct Chaos {
+    injector: Arc<Mutex<Injector>>,
+    clock: SharedClock,
+}
+
+impl Chaos {
+    pub fn new(faults: Faults) -> Self {
+        Self {
+            injector: Arc::new(Mutex::new(Injector {
+                faults,
+                calls: 0,
+                rng: SimRng::new(faults.seed),
+            })),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Clock the calls are delayed on, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    pub fn wrap<S>(&self, state: S) -> ChaosState<S> {
+        ChaosState {
+            state,
+            chaos: self.clone(),
+        }
+    }
+
+    pub fn wrap_discovery<D>(&self, discovery: D) -> ChaosDiscovery<D> {
+        ChaosDiscovery {
+            discovery,
+            chaos: self.clone(),
+        }
+    }
+
+    /// Wrap the transport of a connection, use it with [crate::NodeConnection::new]
+    pub fn wrap_transport<T>(&self, transport: T) -> ChaosTransport<T> {
+        ChaosTransport {
+            transport,
+            chaos: self.clone(),
+            dropped: false,
+            outgoing: Vec::new(),
+            incoming_header: Vec::with_capacity(HEADER_LEN),
+            incoming_remaining: 0,
+        }
+    }
+
+    /// Calls made through this layer so far
+    pub fn calls(&self) -> usize {
+        self.injector.lock().unwrap().calls
+    }
+
+    /// Delays the call, then fails it if it is one of the failing calls
+    fn before_call(&self) -> Result<(), InjectedFaultError> {
+        let (delay, call, fail) = {
+            let mut injector = self.injector.lock().unwrap();
+            injector.calls += 1;
+
+            let max_delay = injector.faults.max_delay.as_nanos() as u64;
+            let delay = match max_delay {
+                0 => Duration::ZERO,
+                max_delay => Duration::from_nanos(injector.rng.next_u64() % (max_delay + 1)),
+            };
+            let fail = injector
+                .faults
+                .fail_every
+                .is_some_and(|every| every > 0 && injector.calls % every == 0);
+
+            (delay, injector.calls, fail)
+        };
+
+        // the other wrapped calls shouldn't wait for this one
+        if !delay.is_zero() {
+            self.clock.sleep(delay);
+        }
+
+        if fail {
+            Err(InjectedFaultError { call })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn should_drop(&self) -> bool {
+        let mut injector = self.injector.lock().unwrap();
+        let drop_rate = injector.faults.drop_rate;
+        drop_rate > 0.0 && injector.rng.next_f64() < drop_rate
+    }
+}
+
+/// Wrapper state that fails or delays its execution according to its [Chaos] layer
+pub struct ChaosState<S> {
+    state: S,
+    chaos: Chaos,
+}
+
+impl<S> compose_trait::State for ChaosState<S>
+where
+    S: compose_trait::State,
+{
+    type Output = S::Output;
+
+    fn describe(&self) -> String {
+        format!("chaos({})", self.state.describe())
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.chaos.before_call()?;
+        self.state.execute()
+    }
+}
+
+impl<S> dyn_trait::State for ChaosState<S>
+where
+    S: dyn_trait::State + 'static,
+{
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn dyn_trait::State>>, Box<dyn Error>> {
+        self.chaos.before_call()?;
+        Box::new(self.state).execute()
+    }
+}
+
+/// Wrapper discovery that fails or delays discoveries according to its [Chaos] layer
+pub struct ChaosDiscovery<D> {
+    discovery: D,
+    chaos: Chaos,
+}
+
+impl<D: NodeDiscovery> NodeDiscovery for ChaosDiscovery<D> {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        self.chaos.before_call()?;
+        self.discovery.discover()
+    }
+}
+
+/// Wrapper transport that fails, delays or drops the connection according to its [Chaos] layer
+///
+/// Faults hit whole frames, see [crate::transport::write_frame], so they don't desynchronize the
+/// framing. A frame being written is held until it is flushed and a failed one isn't sent at all,
+/// a frame being read can only fail before its first byte. A dropped connection fails every
+/// following read and write, like a reset TCP connection
+pub struct ChaosTransport<T> {
+    transport: T,
+    chaos: Chaos,
+    dropped: bool,
+    /// Frame written but not flushed yet
+    outgoing: Vec<u8>,
+    /// Header of the frame being read, empty between frames
+    incoming_header: Vec<u8>,
+    /// Message bytes left of the frame being read
+    incoming_remaining: usize,
+}
+
+impl<T> ChaosTransport<T> {
+    fn before_frame(&mut self) -> io::Result<()> {
+        if self.dropped || self.chaos.should_drop() {
+            self.dropped = true;
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        self.chaos.before_call().map_err(io::Error::other)
+    }
+
+    /// Follow the frame being read, so the next fault waits for the next frame
+    fn track_incoming(&mut self, read: &[u8]) {
+        if self.incoming_header.len() < HEADER_LEN {
+            self.incoming_header.extend_from_slice(read);
+            if self.incoming_header.len() == HEADER_LEN {
+                let len = &self.incoming_header[..4];
+                self.incoming_remaining =
+                    u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            }
+        } else {
+            self.incoming_remaining -= read.len();
+        }
+
+        if self.incoming_header.len() == HEADER_LEN && self.incoming_remaining == 0 {
+            self.incoming_header.clear();
+        }
+    }
+}
+
+impl<T: Read> Read for ChaosTransport<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.dropped {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        if self.incoming_header.is_empty() {
+            self.before_frame()?;
+        }
+
+        // a read never spans two frames
+        let limit = match self.incoming_header.len() {
+            len if len < HEADER_LEN => HEADER_LEN - len,
+            _ => self.incoming_remaining,
+        };
+        let limit = limit.min(buf.len());
+        let read = self.transport.read(&mut buf[..limit])?;
+        self.track_incoming(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<T: Write> Write for ChaosTransport<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.dropped {
+            return Err(io::ErrorKind::ConnectionReset.into());
+        }
+
+        self.outgoing.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    /// Send the frame written so far, or fail it as a whole
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.outgoing.is_empty() {
+            let frame = mem::take(&mut self.outgoing);
+            self.before_frame()?;
+            self.transport.write_all(&frame)?;
+        }
+
+        self.transport.flush()
+    }
+}
+
+impl<T: Transport> Transport for ChaosTransport<T> {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.transport.peer_addr()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.transport.set_read_timeout(timeout)
+    }
+}
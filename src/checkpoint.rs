@@ -0,0 +1,293 @@
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::MachineError,
+    event_source::EventSource,
+    external_enum::{externally_driven_executor, EventContext, ExternallyDrivenTransition},
+    internal_enum::{internally_driven_executor, InternallyDrivenTransition},
+};
+
+/// Contract for machines that can be checkpointed mid run.
+///
+/// Checkpoints are taken between transitions, so the machine (usually the state enum) must
+/// serialize every state with all the data needed to resume from it. Resources that can't be
+/// serialized, like connections, should be stored as whatever is needed to acquire them again
+pub trait Checkpoint: Serialize + DeserializeOwned {}
+
+impl<T> Checkpoint for T where T: Serialize + DeserializeOwned {}
+
+/// Write `machine` as JSON
+pub fn save_checkpoint<T, W>(machine: &T, writer: W) -> io::Result<()>
+where
+    T: Checkpoint,
+    W: Write,
+{
+    serde_json::to_writer(writer, machine).map_err(io::Error::from)
+}
+
+/// Read a machine written by [save_checkpoint]
+pub fn load_checkpoint<T, R>(reader: R) -> io::Result<T>
+where
+    T: Checkpoint,
+    R: Read,
+{
+    serde_json::from_reader(reader).map_err(io::Error::from)
+}
+
+/// Invoked by the checkpointing executors after each transition
+pub trait Checkpointer<T> {
+    fn checkpoint(&mut self, machine: &T) -> io::Result<()>;
+}
+
+/// Keeps the latest checkpoint in a file.
+///
+/// The checkpoint is written to a temporary file first and then renamed, so a crash never leaves
+/// a partially written checkpoint behind
+pub struct FileCheckpointer {
+    path: PathBuf,
+}
+
+impl FileCheckpointer {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Open the latest checkpoint, to be used with the `_from` executors
+    pub fn open(&self) -> io::Result<BufReader<File>> {
+        File::open(&self.path).map(BufReader::new)
+    }
+}
+
+impl<T> Checkpointer<T> for FileCheckpointer
+where
+    T: Checkpoint,
+{
+    fn checkpoint(&mut self, machine: &T) -> io::Result<()> {
+        let temp_path = self.path.with_extension("tmp");
+
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        save_checkpoint(machine, &mut writer)?;
+        writer.into_inner()?.sync_all()?;
+
+        fs::rename(temp_path, &self.path)
+    }
+}
+
+/// Same as [internally_driven_executor], but calls `checkpointer` after each transition
+pub fn internally_driven_executor_with_checkpoints<T, C>(
+    initial_state: T,
+    checkpointer: C,
+) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition,
+    C: Checkpointer<T>,
+{
+    let checkpointed = internally_driven_executor(Checkpointed::new(initial_state, checkpointer))?;
+    checkpointed.finish().map_err(MachineError::Checkpoint)
+}
+
+/// Resume a machine from `snapshot`, a checkpoint written by [save_checkpoint]
+pub fn internally_driven_executor_from<T, R, C>(
+    snapshot: R,
+    checkpointer: C,
+) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition + Checkpoint,
+    R: Read,
+    C: Checkpointer<T>,
+{
+    let state = load_checkpoint(snapshot).map_err(MachineError::Checkpoint)?;
+    internally_driven_executor_with_checkpoints(state, checkpointer)
+}
+
+/// Same as [externally_driven_executor], but calls `checkpointer` after each transition.
+///
+/// Events queued by the executor (internal and deferred events) are not part of the checkpoint
+pub fn externally_driven_executor_with_checkpoints<T, S, C>(
+    initial_state: T,
+    events: S,
+    checkpointer: C,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+    C: Checkpointer<T>,
+{
+    let checkpointed =
+        externally_driven_executor(Checkpointed::new(initial_state, checkpointer), events)?;
+    checkpointed.finish().map_err(MachineError::Checkpoint)
+}
+
+/// Resume a machine from `snapshot`, a checkpoint written by [save_checkpoint]
+pub fn externally_driven_executor_from<T, R, S, C>(
+    snapshot: R,
+    events: S,
+    checkpointer: C,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition + Checkpoint,
+    R: Read,
+    S: EventSource<T::EventType>,
+    C: Checkpointer<T>,
+{
+    let state = load_checkpoint(snapshot).map_err(MachineError::Checkpoint)?;
+    externally_driven_executor_with_checkpoints(state, events, checkpointer)
+}
+
+/// Wraps a state machine, calling the checkpointer after every transition
+struct Checkpointed<T, C> {
+    machine: T,
+    checkpointer: C,
+    error: Option<io::Error>,
+}
+
+impl<T, C> Checkpointed<T, C>
+where
+    C: Checkpointer<T>,
+{
+    fn new(machine: T, checkpointer: C) -> Self {
+        Self {
+            machine,
+            checkpointer,
+            error: None,
+        }
+    }
+
+    fn finish(self) -> io::Result<T> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok(self.machine),
+        }
+    }
+}
+
+impl<T, C> InternallyDrivenTransition for Checkpointed<T, C>
+where
+    T: InternallyDrivenTransition,
+    C: Checkpointer<T>,
+{
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let mut checkpointed = Self {
+            machine: self.machine.execute()?,
+            ..self
+        };
+
+        checkpointed
+            .checkpointer
+            .checkpoint(&checkpointed.machine)?;
+        Ok(checkpointed)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T, C> ExternallyDrivenTransition for Checkpointed<T, C>
+where
+    T: ExternallyDrivenTransition,
+    C: Checkpointer<T>,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        // transition can't fail, so checkpoint errors are reported here
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+
+        self.machine.execute(input, context)
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+
+        self.machine.execute_batch(inputs, context)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(self) -> Self {
+        let mut checkpointed = Self {
+            machine: self.machine.transition(),
+            ..self
+        };
+
+        if let Err(err) = checkpointed.checkpointer.checkpoint(&checkpointed.machine) {
+            checkpointed.error.get_or_insert(err);
+        }
+
+        checkpointed
+    }
+
+    /// Unlike [ExternallyDrivenTransition::transition], a failed checkpoint fails right away
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        let mut checkpointed = Self {
+            machine: self.machine.try_transition()?,
+            ..self
+        };
+
+        checkpointed
+            .checkpointer
+            .checkpoint(&checkpointed.machine)?;
+        Ok(checkpointed)
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.on_idle(context)
+    }
+}
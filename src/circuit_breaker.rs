@@ -0,0 +1,163 @@
+use std::{
+    error::Error,
+    mem,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, SharedClock},
+    compose_trait, dyn_trait,
+    error::CircuitOpenError,
+};
+
+/// Observable state of a [Breaker]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Executions go through
+    Closed,
+    /// Executions fail fast, until the reset timeout elapses
+    Open,
+    /// A single trial execution decides if the circuit closes or opens again
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum Circuit {
+    Closed { failures: usize },
+    Open { since: Instant },
+    HalfOpen,
+}
+
+/// Tracks consecutive failures across executions.
+///
+/// Clones share the same circuit, so every state calling the same service should be wrapped by
+/// the same breaker
+#[derive(Clone)]
+pub struct Breaker {
+    circuit: Arc<Mutex<Circuit>>,
+    failure_threshold: usize,
+    reset_timeout: Duration,
+    clock: SharedClock,
+}
+
+impl Breaker {
+    /// The circuit opens after `failure_threshold` consecutive failures, and allows a trial
+    /// execution after `reset_timeout`
+    pub fn new(failure_threshold: usize, reset_timeout: Duration) -> Self {
+        Self {
+            circuit: Arc::new(Mutex::new(Circuit::Closed { failures: 0 })),
+            failure_threshold,
+            reset_timeout,
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Clock measuring the reset timeout
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    pub fn wrap<S>(&self, state: S) -> CircuitBreaker<S> {
+        CircuitBreaker {
+            state,
+            breaker: self.clone(),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.circuit.lock().unwrap() {
+            Circuit::Closed { .. } => CircuitState::Closed,
+            Circuit::Open { .. } => CircuitState::Open,
+            Circuit::HalfOpen => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Checks if an execution can go through
+    fn acquire(&self) -> Result<(), CircuitOpenError> {
+        let mut circuit = self.circuit.lock().unwrap();
+        match *circuit {
+            Circuit::Closed { .. } => Ok(()),
+            Circuit::Open { since }
+                if self.clock.now().saturating_duration_since(since) >= self.reset_timeout =>
+            {
+                *circuit = Circuit::HalfOpen;
+                Ok(())
+            }
+            // Only the trial execution goes through while half open
+            Circuit::Open { .. } | Circuit::HalfOpen => Err(CircuitOpenError),
+        }
+    }
+
+    fn record(&self, succeeded: bool) {
+        let mut circuit = self.circuit.lock().unwrap();
+        *circuit = match (&*circuit, succeeded) {
+            (_, true) => Circuit::Closed { failures: 0 },
+            (Circuit::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                Circuit::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, false) => Circuit::Open {
+                since: self.clock.now(),
+            },
+        };
+    }
+
+    fn run<T>(
+        &self,
+        execute: impl FnOnce() -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>> {
+        self.acquire()?;
+
+        let unwinding = Unwinding(self);
+        let result = execute();
+        mem::forget(unwinding);
+
+        self.record(result.is_ok());
+        result
+    }
+}
+
+/// Records an execution that panicked as a failure, so a panicking trial opens the circuit again
+/// instead of leaving it half open
+struct Unwinding<'a>(&'a Breaker);
+
+impl Drop for Unwinding<'_> {
+    fn drop(&mut self) {
+        self.0.record(false);
+    }
+}
+
+/// Wrapper state that fails fast with [CircuitOpenError] while its [Breaker] is open
+pub struct CircuitBreaker<S> {
+    state: S,
+    breaker: Breaker,
+}
+
+impl<S> compose_trait::State for CircuitBreaker<S>
+where
+    S: compose_trait::State,
+{
+    type Output = S::Output;
+
+    fn describe(&self) -> String {
+        format!("circuit_breaker({})", self.state.describe())
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        let state = self.state;
+        self.breaker.run(|| state.execute())
+    }
+}
+
+impl<S> dyn_trait::State for CircuitBreaker<S>
+where
+    S: dyn_trait::State + 'static,
+{
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn dyn_trait::State>>, Box<dyn Error>> {
+        let state = Box::new(self.state);
+        self.breaker.run(|| state.execute())
+    }
+}
@@ -0,0 +1,110 @@
+//! Time source of the timeouts, backoff, circuit breaker, heartbeat, lease, election, sync and
+//! gossip features
+//!
+//! A [TestClock] only moves when the test advances it, so time dependent behavior can be tested
+//! without waiting
+
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn sleep(&self, duration: Duration);
+}
+
+/// Time of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Clock that stands still until it is advanced, sleeping advances it right away
+///
+/// Clones share the same time, so a test can keep a clone to advance the clock of a running
+/// machine
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl TestClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::default(),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Time advanced since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
+
+/// Count a wait of up to `timeout`, started at `started`, against `clock`
+///
+/// A wait that took no time on the clock, like on a [TestClock] nobody advances, sleeps `timeout`
+/// on it, so loops polling until a deadline of the clock still reach it
+pub(crate) fn count_wait(clock: &dyn Clock, started: Instant, timeout: Duration) {
+    if clock.now() == started {
+        clock.sleep(timeout);
+    }
+}
+
+/// Clock shared by the states and wrappers of a machine, the [SystemClock] by default
+#[derive(Clone)]
+pub struct SharedClock(Arc<dyn Clock>);
+
+impl SharedClock {
+    pub fn new<C: Clock + 'static>(clock: C) -> Self {
+        Self(Arc::new(clock))
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl Clock for SharedClock {
+    fn now(&self) -> Instant {
+        self.0.now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.0.sleep(duration)
+    }
+}
@@ -1,15 +1,42 @@
-use std::{error::Error, marker::PhantomData, net::IpAddr};
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::{error::Error, fmt::Debug, marker::PhantomData, net::IpAddr};
+#[cfg(feature = "std")]
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
 
-use crate::NodeConnection;
+#[cfg(feature = "std")]
+use crate::{
+    clock::{self, Clock, SharedClock},
+    error::{SendableError, TimeoutError},
+    retry::{BackoffPolicy, Retry},
+};
+use crate::{
+    discovery::{Discovery, NodeDiscovery},
+    error::MachineError,
+    NodeConnection,
+};
 
 /// Benchmark function
 pub fn run_full_state_machine() {
-    DiscoverNodes {}
+    full_state_machine().execute().unwrap()
+}
+
+/// The full chain of mock states
+pub fn full_state_machine() -> impl State<Output = ()> {
+    DiscoverNodes::default()
         .and_then(ConnectNodes::new)
         .and_then(Consensus::new)
-        .and_then(|(leader, connections)| LeaderOrFollower::new(leader, connections))
-        .execute()
-        .unwrap()
+        .if_then_else(
+            |(is_leader, _)| *is_leader,
+            |(_, connections)| Leader::new(connections),
+            |(_, connections)| Follower::new(connections),
+        )
 }
 
 /// Represent a task or state to be executed
@@ -17,6 +44,39 @@ pub trait State {
     type Output;
 
     fn execute(self) -> Result<Self::Output, Box<dyn Error>>;
+
+    /// Structure of the chain, like `DiscoverNodes -> ConnectNodes -> Consensus`
+    fn describe(&self) -> String {
+        short_type_name::<Self>()
+    }
+}
+
+/// State that is built directly from the output of the previous state
+pub trait FromInput: State {
+    type Input;
+
+    fn from_input(input: Self::Input) -> Self;
+}
+
+/// Type name without the module paths, `Cached<DiscoverNodes>` instead of
+/// `state_machine::compose_trait::Cached<state_machine::compose_trait::DiscoverNodes>`
+pub(crate) fn short_type_name<T: ?Sized>() -> String {
+    let full_name = core::any::type_name::<T>();
+    let mut name = String::with_capacity(full_name.len());
+    let mut segment = String::new();
+
+    for c in full_name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            segment.push(c);
+        } else {
+            name.push_str(segment.rsplit("::").next().unwrap_or_default());
+            segment.clear();
+            name.push(c);
+        }
+    }
+    name.push_str(segment.rsplit("::").next().unwrap_or_default());
+
+    name
 }
 
 /// Composer trait.
@@ -35,6 +95,236 @@ pub trait StateComposer {
             _marker: Default::default(),
         }
     }
+
+    /// Same as [StateComposer::and_then], but the next state is built with [FromInput], so no
+    /// closure is needed: `DiscoverNodes::default().then::<ConnectNodes>()`
+    fn then<T>(self) -> AndThen<Self, T, fn(Self::Output) -> T>
+    where
+        Self: State + Sized,
+        T: FromInput<Input = Self::Output>,
+    {
+        self.and_then(T::from_input as fn(Self::Output) -> T)
+    }
+
+    /// Branch into the state returned by `recover_fn` when this state fails
+    fn or_else<T, F>(self, recover_fn: F) -> OrElse<Self, T, F>
+    where
+        Self: State + Sized,
+        T: State<Output = Self::Output>,
+        F: FnOnce(Box<dyn Error>) -> T,
+    {
+        OrElse {
+            previous: self,
+            recover_fn,
+            _marker: Default::default(),
+        }
+    }
+
+    /// Transform the output of this state
+    fn map<T, F>(self, map_fn: F) -> Map<Self, F>
+    where
+        Self: State + Sized,
+        F: FnOnce(Self::Output) -> T,
+    {
+        Map {
+            previous: self,
+            map_fn,
+        }
+    }
+
+    /// Convert or enrich the error of this state
+    fn map_err<E, F>(self, map_fn: F) -> MapErr<Self, F>
+    where
+        Self: State + Sized,
+        E: Into<Box<dyn Error>>,
+        F: FnOnce(Box<dyn Error>) -> E,
+    {
+        MapErr {
+            previous: self,
+            map_fn,
+        }
+    }
+
+    /// Observe the output of this state without consuming it
+    fn inspect<F>(self, inspect_fn: F) -> Inspect<Self, F>
+    where
+        Self: State + Sized,
+        F: FnOnce(&Self::Output),
+    {
+        Inspect {
+            previous: self,
+            inspect_fn,
+        }
+    }
+
+    /// Observe the error of this state without consuming it
+    fn inspect_err<F>(self, inspect_fn: F) -> InspectErr<Self, F>
+    where
+        Self: State + Sized,
+        F: FnOnce(&dyn Error),
+    {
+        InspectErr {
+            previous: self,
+            inspect_fn,
+        }
+    }
+
+    /// Execute this state again while `predicate` holds for its output, returning the first
+    /// output that doesn't match.
+    ///
+    /// Every execution runs a fresh clone of the state
+    fn loop_while<F>(self, predicate: F) -> LoopWhile<Self, F>
+    where
+        Self: State + Clone + Sized,
+        F: FnMut(&Self::Output) -> bool,
+    {
+        LoopWhile {
+            state: self,
+            predicate,
+        }
+    }
+
+    /// Execute this state `times` times, returning the last output.
+    ///
+    /// Every execution runs a fresh clone of the state
+    ///
+    /// # Panics
+    ///
+    /// If `times` is 0, there would be no output to return
+    fn repeat(self, times: usize) -> Repeat<Self>
+    where
+        Self: State + Clone + Sized,
+    {
+        assert!(times > 0, "a state must be executed at least once");
+        Repeat { state: self, times }
+    }
+
+    /// Same as [StateComposer::and_then], but the next state is one of two states with the
+    /// same output
+    fn branch<A, B, F>(self, branch_fn: F) -> AndThen<Self, Either<A, B>, F>
+    where
+        Self: State + Sized,
+        A: State,
+        B: State<Output = A::Output>,
+        F: FnOnce(Self::Output) -> Either<A, B>,
+    {
+        self.and_then(branch_fn)
+    }
+
+    /// Continue with the state returned by `then_fn` if `predicate` holds for the output of this
+    /// state, or with the state returned by `else_fn` otherwise
+    fn if_then_else<A, B, P, TF, EF>(
+        self,
+        predicate: P,
+        then_fn: TF,
+        else_fn: EF,
+    ) -> IfThenElse<Self, P, TF, EF>
+    where
+        Self: State + Sized,
+        A: State,
+        B: State<Output = A::Output>,
+        P: FnOnce(&Self::Output) -> bool,
+        TF: FnOnce(Self::Output) -> A,
+        EF: FnOnce(Self::Output) -> B,
+    {
+        IfThenElse {
+            previous: self,
+            predicate,
+            then_fn,
+            else_fn,
+        }
+    }
+
+    /// Execute this state and `other` concurrently, `other` runs on a scoped thread
+    #[cfg(feature = "std")]
+    fn join<T>(self, other: T) -> Join<Self, T>
+    where
+        Self: State + Sized,
+        T: State + Send,
+        T::Output: Send,
+    {
+        Join {
+            first: self,
+            second: other,
+        }
+    }
+
+    /// Execute `cleanup` after this state, whether it succeeded or failed.
+    ///
+    /// The output of this state is returned afterwards. A failing cleanup is only reported when
+    /// this state succeeded, otherwise the original error is kept
+    fn finally<T>(self, cleanup: T) -> Finally<Self, T>
+    where
+        Self: State + Sized,
+        T: State,
+    {
+        Finally {
+            previous: self,
+            cleanup,
+        }
+    }
+
+    /// Erase the type of this state, so composed chains can be stored, returned from functions and
+    /// built conditionally
+    fn boxed(self) -> BoxedState<Self::Output>
+    where
+        Self: State + Sized + 'static,
+    {
+        BoxedState {
+            state: Box::new(self),
+        }
+    }
+
+    /// Execute this state again when it fails, according to `policy`
+    #[cfg(feature = "std")]
+    fn retry<P>(self, policy: P) -> Retry<Self, P>
+    where
+        Self: State + Clone + Sized,
+        P: BackoffPolicy,
+    {
+        Retry::new(self, policy)
+    }
+
+    /// Execute this state only once, clones of the returned [Cached] state share the output
+    #[cfg(feature = "std")]
+    fn cached(self) -> Cached<Self>
+    where
+        Self: State + Sized,
+        Self::Output: Clone,
+    {
+        Cached::new(self)
+    }
+
+    /// Execute a child state for every item of this state output, each one on its own scoped
+    /// thread, collecting the outputs in the same order
+    #[cfg(feature = "std")]
+    fn for_each_concurrent<T, F>(self, child_fn: F) -> ForEachConcurrent<Self, F>
+    where
+        Self: State + Sized,
+        Self::Output: IntoIterator,
+        <Self::Output as IntoIterator>::Item: Send,
+        T: State,
+        T::Output: Send,
+        F: Fn(<Self::Output as IntoIterator>::Item) -> T + Sync,
+    {
+        ForEachConcurrent {
+            previous: self,
+            child_fn,
+        }
+    }
+
+    /// Fail with a [TimeoutError] if the state takes longer than `timeout` to execute
+    #[cfg(feature = "std")]
+    fn with_timeout(self, timeout: Duration) -> WithTimeout<Self>
+    where
+        Self: State + Sized,
+    {
+        WithTimeout {
+            state: self,
+            timeout,
+            clock: SharedClock::default(),
+        }
+    }
 }
 
 impl<T> StateComposer for T where T: State {}
@@ -46,6 +336,15 @@ pub struct AndThen<T, U, F> {
     _marker: PhantomData<U>,
 }
 
+impl<T, U, F> Debug for AndThen<T, U, F>
+where
+    Self: State,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.describe())
+    }
+}
+
 impl<T, U, F> State for AndThen<T, U, F>
 where
     T: State,
@@ -54,6 +353,10 @@ where
 {
     type Output = U::Output;
 
+    fn describe(&self) -> String {
+        format!("{} -> {}", self.previous.describe(), short_type_name::<U>())
+    }
+
     fn execute(self) -> Result<Self::Output, Box<dyn Error>>
     where
         Self: Sized,
@@ -64,6 +367,549 @@ where
     }
 }
 
+/// Or Else recoverable state
+pub struct OrElse<T, U, F> {
+    previous: T,
+    recover_fn: F,
+    _marker: PhantomData<U>,
+}
+
+impl<T, U, F> State for OrElse<T, U, F>
+where
+    T: State,
+    U: State<Output = T::Output>,
+    F: FnOnce(Box<dyn Error>) -> U,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        format!(
+            "{} or_else {}",
+            self.previous.describe(),
+            short_type_name::<U>()
+        )
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        match self.previous.execute() {
+            Ok(output) => Ok(output),
+            Err(err) => (self.recover_fn)(err).execute(),
+        }
+    }
+}
+
+/// Map output state
+pub struct Map<T, F> {
+    previous: T,
+    map_fn: F,
+}
+
+impl<T, U, F> State for Map<T, F>
+where
+    T: State,
+    F: FnOnce(T::Output) -> U,
+{
+    type Output = U;
+
+    fn describe(&self) -> String {
+        self.previous.describe()
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.previous.execute().map(self.map_fn)
+    }
+}
+
+/// Map error state
+pub struct MapErr<T, F> {
+    previous: T,
+    map_fn: F,
+}
+
+impl<T, E, F> State for MapErr<T, F>
+where
+    T: State,
+    E: Into<Box<dyn Error>>,
+    F: FnOnce(Box<dyn Error>) -> E,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        self.previous.describe()
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.previous
+            .execute()
+            .map_err(|err| (self.map_fn)(err).into())
+    }
+}
+
+/// Inspect output state
+pub struct Inspect<T, F> {
+    previous: T,
+    inspect_fn: F,
+}
+
+impl<T, F> State for Inspect<T, F>
+where
+    T: State,
+    F: FnOnce(&T::Output),
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        self.previous.describe()
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.previous.execute().inspect(self.inspect_fn)
+    }
+}
+
+/// Inspect error state
+pub struct InspectErr<T, F> {
+    previous: T,
+    inspect_fn: F,
+}
+
+impl<T, F> State for InspectErr<T, F>
+where
+    T: State,
+    F: FnOnce(&dyn Error),
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        self.previous.describe()
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.previous
+            .execute()
+            .inspect_err(|err| (self.inspect_fn)(err.as_ref()))
+    }
+}
+
+/// Loop while state
+pub struct LoopWhile<T, F> {
+    state: T,
+    predicate: F,
+}
+
+impl<T, F> State for LoopWhile<T, F>
+where
+    T: State + Clone,
+    F: FnMut(&T::Output) -> bool,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        format!("loop_while({})", self.state.describe())
+    }
+
+    fn execute(mut self) -> Result<Self::Output, Box<dyn Error>> {
+        loop {
+            let output = self.state.clone().execute()?;
+            if !(self.predicate)(&output) {
+                return Ok(output);
+            }
+        }
+    }
+}
+
+/// Repeat state
+pub struct Repeat<T> {
+    state: T,
+    times: usize,
+}
+
+impl<T> State for Repeat<T>
+where
+    T: State + Clone,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        format!("repeat({}, {})", self.state.describe(), self.times)
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        for _ in 1..self.times {
+            self.state.clone().execute()?;
+        }
+
+        self.state.execute()
+    }
+}
+
+/// One of two states with the same output
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> State for Either<A, B>
+where
+    A: State,
+    B: State<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn describe(&self) -> String {
+        match self {
+            Either::Left(state) => state.describe(),
+            Either::Right(state) => state.describe(),
+        }
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        match self {
+            Either::Left(state) => state.execute(),
+            Either::Right(state) => state.execute(),
+        }
+    }
+}
+
+/// If Then Else branching state
+pub struct IfThenElse<T, P, TF, EF> {
+    previous: T,
+    predicate: P,
+    then_fn: TF,
+    else_fn: EF,
+}
+
+impl<T, A, B, P, TF, EF> State for IfThenElse<T, P, TF, EF>
+where
+    T: State,
+    A: State,
+    B: State<Output = A::Output>,
+    P: FnOnce(&T::Output) -> bool,
+    TF: FnOnce(T::Output) -> A,
+    EF: FnOnce(T::Output) -> B,
+{
+    type Output = A::Output;
+
+    fn describe(&self) -> String {
+        format!(
+            "{} -> ({} | {})",
+            self.previous.describe(),
+            short_type_name::<A>(),
+            short_type_name::<B>()
+        )
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        let previous_output = self.previous.execute()?;
+        if (self.predicate)(&previous_output) {
+            (self.then_fn)(previous_output).execute()
+        } else {
+            (self.else_fn)(previous_output).execute()
+        }
+    }
+}
+
+/// Join state, produces the outputs of both states
+#[cfg(feature = "std")]
+pub struct Join<T, U> {
+    first: T,
+    second: U,
+}
+
+#[cfg(feature = "std")]
+impl<T, U> State for Join<T, U>
+where
+    T: State,
+    U: State + Send,
+    U::Output: Send,
+{
+    type Output = (T::Output, U::Output);
+
+    fn describe(&self) -> String {
+        format!(
+            "join({}, {})",
+            self.first.describe(),
+            self.second.describe()
+        )
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        let second = self.second;
+        thread::scope(|scope| {
+            let second = scope.spawn(|| second.execute().map_err(SendableError::from_state));
+            let first = self.first.execute();
+
+            let second = second
+                .join()
+                .map_err(|_| "state panicked while executing")?;
+            Ok((first?, second.map_err(MachineError::from)?))
+        })
+    }
+}
+
+/// Async version of [StateComposer::join], both states run on the tokio blocking pool
+#[cfg(feature = "tokio")]
+pub async fn join_async<T, U>(first: T, second: U) -> Result<(T::Output, U::Output), Box<dyn Error>>
+where
+    T: State + Send + 'static,
+    T::Output: Send + 'static,
+    U: State + Send + 'static,
+    U::Output: Send + 'static,
+{
+    let first =
+        tokio::task::spawn_blocking(move || first.execute().map_err(SendableError::from_state));
+    let second =
+        tokio::task::spawn_blocking(move || second.execute().map_err(SendableError::from_state));
+
+    let first = first.await?;
+    let second = second.await?;
+    Ok((
+        first.map_err(MachineError::from)?,
+        second.map_err(MachineError::from)?,
+    ))
+}
+
+/// Finally cleanup state
+pub struct Finally<T, U> {
+    previous: T,
+    cleanup: U,
+}
+
+impl<T, U> State for Finally<T, U>
+where
+    T: State,
+    U: State,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        format!(
+            "{} finally {}",
+            self.previous.describe(),
+            self.cleanup.describe()
+        )
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        let result = self.previous.execute();
+        let cleanup_result = self.cleanup.execute();
+
+        let output = result?;
+        cleanup_result?;
+        Ok(output)
+    }
+}
+
+/// Fan out state
+#[cfg(feature = "std")]
+pub struct ForEachConcurrent<T, F> {
+    previous: T,
+    child_fn: F,
+}
+
+#[cfg(feature = "std")]
+impl<T, U, F> State for ForEachConcurrent<T, F>
+where
+    T: State,
+    T::Output: IntoIterator,
+    <T::Output as IntoIterator>::Item: Send,
+    U: State,
+    U::Output: Send,
+    F: Fn(<T::Output as IntoIterator>::Item) -> U + Sync,
+{
+    type Output = Vec<U::Output>;
+
+    fn describe(&self) -> String {
+        format!(
+            "{} -> for_each_concurrent({})",
+            self.previous.describe(),
+            short_type_name::<U>()
+        )
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        let items = self.previous.execute()?;
+        let child_fn = &self.child_fn;
+
+        thread::scope(|scope| {
+            let children: Vec<_> = items
+                .into_iter()
+                .map(|item| {
+                    scope.spawn(move || child_fn(item).execute().map_err(SendableError::from_state))
+                })
+                .collect();
+
+            children
+                .into_iter()
+                .map(|child| {
+                    let output = child.join().map_err(|_| "state panicked while executing")?;
+                    Ok(output.map_err(MachineError::from)?)
+                })
+                .collect()
+        })
+    }
+}
+
+/// Object safe version of [State], [State::execute] takes `self` by value
+trait DynState<O> {
+    fn execute_boxed(self: Box<Self>) -> Result<O, Box<dyn Error>>;
+    fn describe_boxed(&self) -> String;
+}
+
+impl<T> DynState<T::Output> for T
+where
+    T: State,
+{
+    fn execute_boxed(self: Box<Self>) -> Result<T::Output, Box<dyn Error>> {
+        (*self).execute()
+    }
+
+    fn describe_boxed(&self) -> String {
+        self.describe()
+    }
+}
+
+/// Type erased state, only the output type is known
+pub struct BoxedState<O> {
+    state: Box<dyn DynState<O>>,
+}
+
+impl<O> State for BoxedState<O> {
+    type Output = O;
+
+    fn describe(&self) -> String {
+        self.state.describe_boxed()
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.state.execute_boxed()
+    }
+}
+
+/// Memoized state, for expensive idempotent states needed by multiple branches.
+///
+/// The first execution stores the output, clones of this state return the stored output instead
+/// of executing again
+#[cfg(feature = "std")]
+pub struct Cached<T: State> {
+    state: T,
+    cache: Arc<Mutex<Option<T::Output>>>,
+}
+
+#[cfg(feature = "std")]
+impl<T> Cached<T>
+where
+    T: State,
+{
+    pub fn new(state: T) -> Self {
+        Self {
+            state,
+            cache: Default::default(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> Clone for Cached<T>
+where
+    T: State + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> State for Cached<T>
+where
+    T: State,
+    T::Output: Clone,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        format!("cached({})", self.state.describe())
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        // The lock is held while executing, so concurrent branches don't execute the state twice.
+        // A state that panicked stored nothing, so the next execution runs it again
+        let mut cache = self.cache.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(output) = cache.as_ref() {
+            return Ok(output.clone());
+        }
+
+        let output = self.state.execute()?;
+        *cache = Some(output.clone());
+        Ok(output)
+    }
+}
+
+/// State with a deadline.
+///
+/// The state is executed on a worker thread, which is left running in the background if the
+/// deadline is exceeded
+#[cfg(feature = "std")]
+pub struct WithTimeout<T> {
+    state: T,
+    timeout: Duration,
+    clock: SharedClock,
+}
+
+#[cfg(feature = "std")]
+impl<T> WithTimeout<T> {
+    /// Clock measuring the deadline, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> State for WithTimeout<T>
+where
+    T: State + Send + 'static,
+    T::Output: Send + 'static,
+{
+    type Output = T::Output;
+
+    fn describe(&self) -> String {
+        format!("with_timeout({})", self.state.describe())
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        let (sender, receiver) = mpsc::channel();
+        let state = self.state;
+        thread::spawn(move || {
+            let _ = sender.send(state.execute().map_err(SendableError::from_state));
+        });
+
+        let mut now = self.clock.now();
+        let deadline = now + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(now);
+            match receiver.recv_timeout(remaining) {
+                Ok(result) => return result.map_err(|err| MachineError::from(err).into()),
+                Err(RecvTimeoutError::Timeout) => {
+                    clock::count_wait(&self.clock, now, remaining);
+                    now = self.clock.now();
+                    if now >= deadline {
+                        return Err(Box::new(TimeoutError {
+                            timeout: self.timeout,
+                        }));
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err("state panicked while executing".into())
+                }
+            }
+        }
+    }
+}
+
 // Mock States
 // 1. Discover all nodes in the network
 // 2. Connect to all nodes
@@ -72,15 +918,28 @@ where
 //     1. Followers will wait for events
 //     2. The Leader will only send events
 
-pub struct DiscoverNodes {}
+#[derive(Clone, Default)]
+pub struct DiscoverNodes {
+    discovery: Discovery,
+}
+
+impl DiscoverNodes {
+    pub fn new(discovery: Discovery) -> Self {
+        Self { discovery }
+    }
+}
+
 impl State for DiscoverNodes {
     type Output = Vec<IpAddr>;
 
     fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
-        Ok(crate::get_service_nodes())
+        self.discovery
+            .discover()
+            .map_err(|err| MachineError::DiscoveryFailed(err).into())
     }
 }
 
+#[derive(Clone)]
 pub struct ConnectNodes {
     nodes: Vec<IpAddr>,
 }
@@ -95,50 +954,82 @@ impl State for ConnectNodes {
     type Output = Vec<NodeConnection>;
 
     fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
-        Ok(crate::connect_to_nodes(&self.nodes))
+        Ok(crate::connect_to_nodes(
+            &self.nodes,
+            &crate::ConnectPolicy::default(),
+        )?)
     }
 }
 
-pub struct Consensus {
-    connections: Vec<NodeConnection>,
+impl FromInput for ConnectNodes {
+    type Input = Vec<IpAddr>;
+
+    fn from_input(input: Self::Input) -> Self {
+        Self::new(input)
+    }
 }
 
-impl Consensus {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
-        Self { connections }
+/// Connect to a single node.
+///
+/// `DiscoverNodes::default().for_each_concurrent(ConnectNode::new)` is a concurrent version of
+/// `DiscoverNodes::default().and_then(ConnectNodes::new)`
+pub struct ConnectNode {
+    node: IpAddr,
+}
+
+impl ConnectNode {
+    pub fn new(node: IpAddr) -> Self {
+        Self { node }
     }
 }
-impl State for Consensus {
-    type Output = (bool, Vec<NodeConnection>);
+
+impl State for ConnectNode {
+    type Output = NodeConnection;
 
     fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
-        Ok((true, self.connections))
+        crate::ConnectPolicy::default()
+            .connect(self.node)
+            .map_err(|source| {
+                MachineError::ConnectFailed {
+                    addr: self.node,
+                    source,
+                }
+                .into()
+            })
     }
 }
 
-pub struct LeaderOrFollower {
-    is_leader: bool,
+impl FromInput for ConnectNode {
+    type Input = IpAddr;
+
+    fn from_input(input: Self::Input) -> Self {
+        Self::new(input)
+    }
+}
+
+pub struct Consensus {
     connections: Vec<NodeConnection>,
 }
 
-impl LeaderOrFollower {
-    pub fn new(is_leader: bool, connections: Vec<NodeConnection>) -> Self {
-        Self {
-            is_leader,
-            connections,
-        }
+impl Consensus {
+    pub fn new(connections: Vec<NodeConnection>) -> Self {
+        Self { connections }
     }
 }
+impl State for Consensus {
+    type Output = (bool, Vec<NodeConnection>);
 
-impl State for LeaderOrFollower {
-    type Output = ();
+    fn execute(mut self) -> Result<Self::Output, Box<dyn Error>> {
+        let is_leader = crate::elect_leader(&mut self.connections)?;
+        Ok((is_leader, self.connections))
+    }
+}
 
-    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
-        if self.is_leader {
-            Leader::new(self.connections).execute()
-        } else {
-            Follower::new(self.connections).execute()
-        }
+impl FromInput for Consensus {
+    type Input = Vec<NodeConnection>;
+
+    fn from_input(input: Self::Input) -> Self {
+        Self::new(input)
     }
 }
 
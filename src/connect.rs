@@ -0,0 +1,79 @@
+use std::{error::Error, net::IpAddr, thread};
+
+use crate::NodeConnection;
+
+/// Outcome of connecting to a set of nodes, the nodes that failed don't prevent connecting to the
+/// others
+#[derive(Default)]
+pub struct ConnectResults {
+    /// Successful connections, in the same order as the nodes
+    pub connections: Vec<NodeConnection>,
+    pub errors: Vec<(IpAddr, Box<dyn Error + Send + Sync>)>,
+}
+
+impl ConnectResults {
+    fn push(&mut self, node: IpAddr, result: Result<NodeConnection, Box<dyn Error + Send + Sync>>) {
+        match result {
+            Ok(connection) => self.connections.push(connection),
+            Err(err) => self.errors.push((node, err)),
+        }
+    }
+}
+
+/// Same as [crate::connect_to_nodes], but every node is connected on its own scoped thread
+pub fn connect_to_nodes_parallel(nodes: &[IpAddr]) -> ConnectResults {
+    crate::simulate_latency();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = nodes
+            .iter()
+            .map(|node| (*node, scope.spawn(|| NodeConnection::connect(*node))))
+            .collect();
+
+        let mut results = ConnectResults::default();
+        for (node, handle) in handles {
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err("connection thread panicked".into()));
+            results.push(node, result);
+        }
+
+        results
+    })
+}
+
+/// Async version of [connect_to_nodes_parallel], at most `max_concurrency` nodes are connected at
+/// the same time on the tokio blocking pool
+#[cfg(feature = "tokio")]
+pub async fn connect_to_nodes_async(nodes: &[IpAddr], max_concurrency: usize) -> ConnectResults {
+    use std::sync::Arc;
+
+    use tokio::sync::Semaphore;
+
+    crate::simulate_latency();
+
+    let permits = Arc::new(Semaphore::new(max_concurrency.max(1)));
+    let mut tasks = Vec::with_capacity(nodes.len());
+    for node in nodes.iter().copied() {
+        let permit = permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the semaphore is never closed");
+        let task = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            NodeConnection::connect(node)
+        });
+        tasks.push((node, task));
+    }
+
+    let mut results = ConnectResults::default();
+    for (node, task) in tasks {
+        results.push(
+            node,
+            task.await.map_err(Into::into).and_then(|result| result),
+        );
+    }
+
+    results
+}
@@ -0,0 +1,193 @@
+use std::{error::Error, time::Duration};
+
+use crate::{
+    error::AbortedError,
+    external_enum::{EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    primitives::{Arc, Condvar, Mutex},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Run,
+    Pause,
+    Abort,
+}
+
+/// Handle used to control a running machine from outside the executor.
+///
+/// Commands are processed between transitions
+#[derive(Clone)]
+pub struct ControlHandle {
+    shared: Arc<(Mutex<Command>, Condvar)>,
+}
+
+impl ControlHandle {
+    fn new() -> Self {
+        Self {
+            shared: Arc::new((Mutex::new(Command::Run), Condvar::new())),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(Command::Run);
+    }
+
+    pub fn abort(&self) {
+        self.send(Command::Abort);
+    }
+
+    fn send(&self, command: Command) {
+        let (lock, condvar) = &*self.shared;
+        let mut current = lock.lock().unwrap();
+        // Once aborted, the machine can't be resumed
+        if *current != Command::Abort {
+            *current = command;
+        }
+        condvar.notify_all();
+    }
+
+    /// Blocks while the machine is paused, fails if it was aborted
+    fn wait_for_run(&self) -> Result<(), AbortedError> {
+        let (lock, condvar) = &*self.shared;
+        let mut command = lock.lock().unwrap();
+        while *command == Command::Pause {
+            command = condvar.wait(command).unwrap();
+        }
+
+        match *command {
+            Command::Abort => Err(AbortedError),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Wraps a state machine, so it can be paused, resumed and aborted through a [ControlHandle].
+///
+/// An aborted machine fails with [AbortedError]
+pub struct Controlled<T> {
+    machine: T,
+    handle: ControlHandle,
+}
+
+/// Wrap `machine`, returning the handle used to control it
+pub fn controlled<T>(machine: T) -> (Controlled<T>, ControlHandle) {
+    let handle = ControlHandle::new();
+    (
+        Controlled {
+            machine,
+            handle: handle.clone(),
+        },
+        handle,
+    )
+}
+
+impl<T> Controlled<T> {
+    /// Unwrap the machine, usually the terminal state returned by the executor
+    pub fn into_inner(self) -> T {
+        self.machine
+    }
+}
+
+impl<T> InternallyDrivenTransition for Controlled<T>
+where
+    T: InternallyDrivenTransition,
+{
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        self.handle.wait_for_run()?;
+        Ok(Self {
+            machine: self.machine.execute()?,
+            handle: self.handle,
+        })
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T> ExternallyDrivenTransition for Controlled<T>
+where
+    T: ExternallyDrivenTransition,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.handle.wait_for_run()?;
+        self.machine.execute(input, context)
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.handle.wait_for_run()?;
+        self.machine.execute_batch(inputs, context)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(self) -> Self {
+        Self {
+            machine: self.machine.transition(),
+            handle: self.handle,
+        }
+    }
+
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            machine: self.machine.try_transition()?,
+            handle: self.handle,
+        })
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.handle.wait_for_run()?;
+        self.machine.on_idle(context)
+    }
+}
@@ -0,0 +1,451 @@
+#[cfg(any(feature = "mdns", feature = "dns"))]
+use alloc::string::String;
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+#[cfg(feature = "dns")]
+use core::net::SocketAddr;
+use core::{error::Error, net::IpAddr};
+#[cfg(feature = "serde")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::SystemTime,
+};
+#[cfg(feature = "std")]
+use std::{
+    io,
+    net::{Ipv4Addr, UdpSocket},
+    sync::atomic::{AtomicBool, Ordering},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+#[cfg(feature = "std")]
+use crate::clock::{self, Clock, SharedClock};
+#[cfg(feature = "serde")]
+use crate::error::DiscoveryNotSetError;
+
+/// Source of the nodes of the cluster, injected into the `DiscoverNodes` states
+pub trait NodeDiscovery: Send + Sync {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>>;
+}
+
+/// Fixed list of nodes, known upfront
+#[derive(Debug, Clone, Default)]
+pub struct StaticDiscovery {
+    nodes: Vec<IpAddr>,
+}
+
+impl StaticDiscovery {
+    pub fn new(nodes: Vec<IpAddr>) -> Self {
+        Self { nodes }
+    }
+}
+
+impl NodeDiscovery for StaticDiscovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        crate::simulate_latency();
+        Ok(self.nodes.clone())
+    }
+}
+
+/// Shared handle to a [NodeDiscovery], defaults to an empty [StaticDiscovery]
+#[derive(Clone)]
+pub struct Discovery(Arc<dyn NodeDiscovery>);
+
+impl Discovery {
+    pub fn new<D: NodeDiscovery + 'static>(discovery: D) -> Self {
+        Self(Arc::new(discovery))
+    }
+
+    /// Placeholder of a restored machine, fails every discovery until the real one is set again
+    #[cfg(feature = "serde")]
+    pub(crate) fn unset() -> Self {
+        Self::new(UnsetDiscovery)
+    }
+}
+
+#[cfg(feature = "serde")]
+struct UnsetDiscovery;
+
+#[cfg(feature = "serde")]
+impl NodeDiscovery for UnsetDiscovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        Err(Box::new(DiscoveryNotSetError))
+    }
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self::new(StaticDiscovery::default())
+    }
+}
+
+impl NodeDiscovery for Discovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        self.0.discover()
+    }
+}
+
+/// Browse the LAN with mDNS for instances of a service type, like `_state-machine._tcp.local.`
+///
+/// Every call browses for `browse_timeout` and returns the addresses resolved in that window
+#[cfg(feature = "mdns")]
+pub struct MdnsDiscovery {
+    service_type: String,
+    browse_timeout: Duration,
+    clock: SharedClock,
+}
+
+#[cfg(feature = "mdns")]
+impl MdnsDiscovery {
+    pub fn new(service_type: impl Into<String>) -> Self {
+        Self {
+            service_type: service_type.into(),
+            browse_timeout: Duration::from_secs(1),
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn with_browse_timeout(mut self, browse_timeout: Duration) -> Self {
+        self.browse_timeout = browse_timeout;
+        self
+    }
+
+    /// Clock measuring the browse timeout, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+}
+
+#[cfg(feature = "mdns")]
+impl NodeDiscovery for MdnsDiscovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        let daemon = mdns_sd::ServiceDaemon::new()?;
+        let events = daemon.browse(&self.service_type)?;
+        let deadline = self.clock.now() + self.browse_timeout;
+
+        let mut nodes = Vec::new();
+        let mut now = self.clock.now();
+        while let Some(remaining) = deadline.checked_duration_since(now) {
+            match events.recv_timeout(remaining) {
+                Ok(mdns_sd::ServiceEvent::ServiceResolved(info)) => {
+                    nodes.extend(info.get_addresses().iter().copied())
+                }
+                Ok(_) => {}
+                Err(_) => {
+                    clock::count_wait(&self.clock, now, remaining);
+                    break;
+                }
+            }
+            now = self.clock.now();
+        }
+
+        let _ = daemon.shutdown();
+        nodes.sort();
+        nodes.dedup();
+        Ok(nodes)
+    }
+}
+
+/// Resolve a DNS SRV name, like `_state-machine._tcp.example.com`, into the addresses of its targets
+///
+/// Uses the system resolver configuration
+#[cfg(feature = "dns")]
+pub struct DnsSrvDiscovery {
+    name: String,
+}
+
+#[cfg(feature = "dns")]
+impl DnsSrvDiscovery {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+
+    /// Addresses of every target with the port of its record, ordered by priority and weight
+    pub fn discover_endpoints(&self) -> Result<Vec<SocketAddr>, Box<dyn Error>> {
+        let resolver = hickory_resolver::Resolver::from_system_conf()?;
+        let mut records: Vec<_> = resolver
+            .srv_lookup(self.name.as_str())?
+            .iter()
+            .cloned()
+            .collect();
+        // lower priority first, higher weight first within the same priority
+        records.sort_by_key(|record| (record.priority(), core::cmp::Reverse(record.weight())));
+
+        let mut endpoints = Vec::new();
+        for record in records {
+            for ip in resolver.lookup_ip(record.target().clone())?.iter() {
+                endpoints.push(SocketAddr::new(ip, record.port()));
+            }
+        }
+
+        Ok(endpoints)
+    }
+}
+
+#[cfg(feature = "dns")]
+impl NodeDiscovery for DnsSrvDiscovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        let mut nodes = Vec::new();
+        for endpoint in self.discover_endpoints()? {
+            if !nodes.contains(&endpoint.ip()) {
+                nodes.push(endpoint.ip());
+            }
+        }
+
+        Ok(nodes)
+    }
+}
+
+/// Nodes listed in a JSON file, like `["10.0.0.1", "10.0.0.2"]`
+///
+/// The file is read again on every discovery, [StaticFileDiscovery::watch] reports changes while
+/// the machine is running
+#[cfg(feature = "serde")]
+pub struct StaticFileDiscovery {
+    path: PathBuf,
+    clock: SharedClock,
+}
+
+#[cfg(feature = "serde")]
+impl StaticFileDiscovery {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Clock the watcher sleeps on between polls, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Poll the file every `interval`, when it changes the new nodes are mapped with `to_event` and
+    /// sent to `events`, usually the source of an externally driven machine
+    ///
+    /// Watching stops when the returned [FileWatcher] is dropped or the receiver is gone
+    pub fn watch<E, F>(
+        &self,
+        interval: Duration,
+        events: mpsc::Sender<E>,
+        to_event: F,
+    ) -> FileWatcher
+    where
+        E: Send + 'static,
+        F: Fn(Vec<IpAddr>) -> E + Send + 'static,
+    {
+        let path = self.path.clone();
+        let clock = self.clock.clone();
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut last_modified = modified(&path);
+                while !stop.load(Ordering::Relaxed) {
+                    clock.sleep(interval);
+
+                    let current = modified(&path);
+                    if current == last_modified {
+                        continue;
+                    }
+                    last_modified = current;
+
+                    // a partially written file is picked up on the next change
+                    let Ok(nodes) = read_nodes(&path) else {
+                        continue;
+                    };
+                    if events.send(to_event(nodes)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        FileWatcher {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl NodeDiscovery for StaticFileDiscovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        read_nodes(&self.path)
+    }
+}
+
+#[cfg(feature = "serde")]
+fn read_nodes(path: &Path) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+#[cfg(feature = "serde")]
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Handle of a watched discovery file, watching stops when it is dropped
+#[cfg(feature = "serde")]
+pub struct FileWatcher {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "serde")]
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Datagram sent by a node looking for peers
+#[cfg(feature = "std")]
+const ANNOUNCE: &[u8] = b"state-machine/announce";
+
+/// Datagram sent back by the peers that heard an announcement
+#[cfg(feature = "std")]
+const REPLY: &[u8] = b"state-machine/reply";
+
+/// Zero infrastructure discovery, announces this node over UDP broadcast or multicast and collects
+/// the replies until the timeout
+///
+/// Peers only reply while they are running a [BroadcastDiscovery::respond] responder
+#[cfg(feature = "std")]
+pub struct BroadcastDiscovery {
+    address: IpAddr,
+    port: u16,
+    timeout: Duration,
+    clock: SharedClock,
+}
+
+#[cfg(feature = "std")]
+impl BroadcastDiscovery {
+    pub fn new(port: u16) -> Self {
+        Self {
+            address: IpAddr::V4(Ipv4Addr::BROADCAST),
+            port,
+            timeout: Duration::from_secs(1),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Broadcast or multicast address of the announcements, the limited broadcast address by default
+    pub fn with_address(mut self, address: IpAddr) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// How long to wait for replies
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Clock measuring the timeout, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Reply to the announcements of other nodes on a background thread, until the returned
+    /// [BroadcastResponder] is dropped
+    pub fn respond(&self) -> io::Result<BroadcastResponder> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, self.port))?;
+        if let IpAddr::V4(address) = self.address {
+            if address.is_multicast() {
+                socket.join_multicast_v4(&address, &Ipv4Addr::UNSPECIFIED)?;
+            }
+        }
+        // wake up regularly to check if the responder was dropped
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut buf = [0; 64];
+                while !stop.load(Ordering::Relaxed) {
+                    if let Ok((len, peer)) = socket.recv_from(&mut buf) {
+                        if &buf[..len] == ANNOUNCE {
+                            let _ = socket.send_to(REPLY, peer);
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(BroadcastResponder {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl NodeDiscovery for BroadcastDiscovery {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn Error>> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.send_to(ANNOUNCE, (self.address, self.port))?;
+
+        let deadline = self.clock.now() + self.timeout;
+        let mut nodes = Vec::new();
+        let mut buf = [0; 64];
+        let mut now = self.clock.now();
+        while let Some(remaining) = deadline.checked_duration_since(now) {
+            if remaining.is_zero() {
+                break;
+            }
+
+            socket.set_read_timeout(Some(remaining))?;
+            match socket.recv_from(&mut buf) {
+                Ok((len, peer)) if &buf[..len] == REPLY => {
+                    if !nodes.contains(&peer.ip()) {
+                        nodes.push(peer.ip());
+                    }
+                }
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    clock::count_wait(&self.clock, now, remaining);
+                    break;
+                }
+                Err(err) => return Err(err.into()),
+            }
+            now = self.clock.now();
+        }
+
+        Ok(nodes)
+    }
+}
+
+/// Handle of a running broadcast responder, it stops when dropped
+#[cfg(feature = "std")]
+pub struct BroadcastResponder {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "std")]
+impl Drop for BroadcastResponder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -1,19 +1,56 @@
-use std::{error::Error, net::IpAddr};
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, error::Error, net::IpAddr};
+#[cfg(feature = "std")]
+use std::thread::{self, JoinHandle};
 
-use crate::NodeConnection;
+#[cfg(feature = "bumpalo")]
+use bumpalo::Bump;
+
+#[cfg(feature = "std")]
+use crate::error::SendableError;
+use crate::{
+    discovery::{Discovery, NodeDiscovery},
+    error::MachineError,
+    small_state::{inline_executor, InlineState, SmallState},
+    NodeConnection,
+};
 
 /// Benchmark function
 pub fn run_full_state_machine() {
     executor(Box::<DiscoverNodes>::default()).unwrap();
 }
 
+/// Benchmark function, states are stored inline
+pub fn run_full_state_machine_inline() {
+    inline_executor(SmallState::new(DiscoverNodes::default())).unwrap();
+}
+
 /// Trait to be implemented by each state
-pub trait State {
+pub trait State: AsAny {
     fn execute(self: Box<Self>) -> Result<Option<Box<dyn State>>, Box<dyn Error>>;
+
+    /// Name of the concrete state, the full type name by default
+    fn name(&self) -> &'static str {
+        core::any::type_name::<Self>()
+    }
+}
+
+/// Access to the concrete state behind a `dyn State`, so it can be downcasted
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T> AsAny for T
+where
+    T: Any,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 /// State machine executor function
-pub fn executor(initial_state: Box<dyn State>) -> Result<(), Box<dyn Error>> {
+pub fn executor(initial_state: Box<dyn State>) -> Result<(), MachineError> {
     let mut current_state = Some(initial_state);
 
     while let Some(state) = current_state {
@@ -23,6 +60,145 @@ pub fn executor(initial_state: Box<dyn State>) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Same as [executor], but `observer` is called with every state before it is executed
+pub fn observed_executor<F>(
+    initial_state: Box<dyn State>,
+    mut observer: F,
+) -> Result<(), MachineError>
+where
+    F: FnMut(&dyn State),
+{
+    let mut current_state = Some(initial_state);
+
+    while let Some(state) = current_state {
+        observer(state.as_ref());
+        current_state = state.execute()?;
+    }
+
+    Ok(())
+}
+
+/// Same as [State], but every state has access to a context shared by the whole machine, like
+/// configuration, metrics or a connection registry
+pub trait StateWithContext<C> {
+    fn execute(self: Box<Self>, context: &mut C) -> Result<NextState<C>, Box<dyn Error>>;
+}
+
+/// Next state of a [StateWithContext], `None` finishes the machine
+pub type NextState<C> = Option<Box<dyn StateWithContext<C>>>;
+
+/// State machine executor function for [StateWithContext]
+///
+/// The executor owns `context` while the machine runs, it is returned once the machine finishes
+pub fn executor_with_context<C>(
+    initial_state: Box<dyn StateWithContext<C>>,
+    mut context: C,
+) -> Result<C, MachineError> {
+    let mut current_state = Some(initial_state);
+
+    while let Some(state) = current_state {
+        current_state = state.execute(&mut context)?;
+    }
+
+    Ok(context)
+}
+
+/// Same as [State], but the states can cross threads
+pub trait ThreadedState: Send {
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn ThreadedState>>, Box<dyn Error>>;
+}
+
+/// State machine executor function for [ThreadedState]
+pub fn threaded_executor(initial_state: Box<dyn ThreadedState>) -> Result<(), MachineError> {
+    let mut current_state = Some(initial_state);
+
+    while let Some(state) = current_state {
+        current_state = state.execute()?;
+    }
+
+    Ok(())
+}
+
+/// Run [threaded_executor] on a new thread
+#[cfg(feature = "std")]
+pub fn spawn_executor(initial_state: Box<dyn ThreadedState>) -> SpawnedExecutor {
+    SpawnedExecutor {
+        handle: thread::spawn(move || {
+            threaded_executor(initial_state).map_err(SendableError::from)
+        }),
+    }
+}
+
+/// Machine run by [spawn_executor]
+///
+/// The error crosses threads as a [SendableError], the sources that can't cross threads are kept
+/// as their message
+#[cfg(feature = "std")]
+pub struct SpawnedExecutor {
+    handle: JoinHandle<Result<(), SendableError>>,
+}
+
+#[cfg(feature = "std")]
+impl SpawnedExecutor {
+    /// Wait for the machine to finish, a panic of a state is resumed on this thread
+    pub fn join(self) -> Result<(), MachineError> {
+        match self.handle.join() {
+            Ok(result) => result.map_err(Into::into),
+            Err(payload) => std::panic::resume_unwind(payload),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+/// Box allocated in a [bumpalo::Bump] arena
+#[cfg(feature = "bumpalo")]
+pub type ArenaBox<'a> = bumpalo::boxed::Box<'a, dyn ArenaState + 'a>;
+
+/// Same as [State], but the next state is allocated in an arena instead of the heap
+///
+/// States are executed in place, so they move their data out with [core::mem::take]
+#[cfg(feature = "bumpalo")]
+pub trait ArenaState {
+    fn execute<'a>(&mut self, arena: &'a Bump) -> Result<Option<ArenaBox<'a>>, Box<dyn Error>>;
+}
+
+/// Allocate `state` in `arena`
+#[cfg(feature = "bumpalo")]
+pub fn arena_box<'a, S>(arena: &'a Bump, state: S) -> ArenaBox<'a>
+where
+    S: ArenaState + 'a,
+{
+    let raw = bumpalo::boxed::Box::into_raw(bumpalo::boxed::Box::new_in(state, arena));
+    // SAFETY: the pointer comes from a box allocated in `arena`, only its type is unsized
+    unsafe { bumpalo::boxed::Box::from_raw(raw as *mut (dyn ArenaState + 'a)) }
+}
+
+/// State machine executor function for [ArenaState]
+///
+/// The arena memory is only released when the arena is reset or dropped
+#[cfg(feature = "bumpalo")]
+pub fn arena_executor<'a>(
+    arena: &'a Bump,
+    initial_state: ArenaBox<'a>,
+) -> Result<(), MachineError> {
+    let mut current_state = Some(initial_state);
+
+    while let Some(mut state) = current_state {
+        current_state = state.execute(arena)?;
+    }
+
+    Ok(())
+}
+
+/// Benchmark function, states are allocated in `arena`
+#[cfg(feature = "bumpalo")]
+pub fn run_full_state_machine_in(arena: &Bump) {
+    arena_executor(arena, arena_box(arena, DiscoverNodes::default())).unwrap();
+}
+
 // Mock States
 // 1. Discover all nodes in the network
 // 2. Connect to all nodes
@@ -32,15 +208,58 @@ pub fn executor(initial_state: Box<dyn State>) -> Result<(), Box<dyn Error>> {
 //     2. The Leader will only send events
 
 #[derive(Default)]
-pub struct DiscoverNodes {}
+pub struct DiscoverNodes {
+    discovery: Discovery,
+}
+
+impl DiscoverNodes {
+    pub fn new(discovery: Discovery) -> Self {
+        Self { discovery }
+    }
+}
 
 impl State for DiscoverNodes {
     fn execute(self: Box<Self>) -> Result<Option<Box<dyn State>>, Box<dyn Error>> {
-        let nodes = crate::get_service_nodes();
+        let nodes = self
+            .discovery
+            .discover()
+            .map_err(MachineError::DiscoveryFailed)?;
         Ok(Some(Box::new(ConnectNodes::new(nodes))))
     }
 }
 
+impl ThreadedState for DiscoverNodes {
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn ThreadedState>>, Box<dyn Error>> {
+        let nodes = self
+            .discovery
+            .discover()
+            .map_err(MachineError::DiscoveryFailed)?;
+        Ok(Some(Box::new(ConnectNodes::new(nodes))))
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ArenaState for DiscoverNodes {
+    fn execute<'a>(&mut self, arena: &'a Bump) -> Result<Option<ArenaBox<'a>>, Box<dyn Error>> {
+        let nodes = self
+            .discovery
+            .discover()
+            .map_err(MachineError::DiscoveryFailed)?;
+        Ok(Some(arena_box(arena, ConnectNodes::new(nodes))))
+    }
+}
+
+impl InlineState for DiscoverNodes {
+    fn execute(self) -> Result<Option<SmallState>, Box<dyn Error>> {
+        let nodes = self
+            .discovery
+            .discover()
+            .map_err(MachineError::DiscoveryFailed)?;
+        Ok(Some(SmallState::new(ConnectNodes::new(nodes))))
+    }
+}
+
+#[derive(Clone)]
 pub struct ConnectNodes {
     nodes: Vec<IpAddr>,
 }
@@ -53,7 +272,15 @@ impl ConnectNodes {
 
 impl State for ConnectNodes {
     fn execute(self: Box<Self>) -> Result<Option<Box<dyn State>>, Box<dyn Error>> {
-        let nodes = crate::connect_to_nodes(&self.nodes);
+        let nodes = crate::connect_to_nodes(&self.nodes, &crate::ConnectPolicy::default())?;
+
+        Ok(Some(Box::new(Consensus::new(nodes))))
+    }
+}
+
+impl ThreadedState for ConnectNodes {
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn ThreadedState>>, Box<dyn Error>> {
+        let nodes = crate::connect_to_nodes(&self.nodes, &crate::ConnectPolicy::default())?;
 
         Ok(Some(Box::new(Consensus::new(nodes))))
     }
@@ -70,11 +297,67 @@ impl Consensus {
 }
 impl State for Consensus {
     fn execute(self: Box<Self>) -> Result<Option<Box<dyn State>>, Box<dyn Error>> {
-        let consensus_result = true;
-        let next: Box<dyn State> = if consensus_result {
-            Box::new(Leader::new(self.connections))
+        let mut connections = self.connections;
+        let next: Box<dyn State> = if crate::elect_leader(&mut connections)? {
+            Box::new(Leader::new(connections))
+        } else {
+            Box::new(Follower::new(connections))
+        };
+
+        Ok(Some(next))
+    }
+}
+
+impl ThreadedState for Consensus {
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn ThreadedState>>, Box<dyn Error>> {
+        let mut connections = self.connections;
+        let next: Box<dyn ThreadedState> = if crate::elect_leader(&mut connections)? {
+            Box::new(Leader::new(connections))
+        } else {
+            Box::new(Follower::new(connections))
+        };
+
+        Ok(Some(next))
+    }
+}
+
+impl InlineState for ConnectNodes {
+    fn execute(self) -> Result<Option<SmallState>, Box<dyn Error>> {
+        let nodes = crate::connect_to_nodes(&self.nodes, &crate::ConnectPolicy::default())?;
+
+        Ok(Some(SmallState::new(Consensus::new(nodes))))
+    }
+}
+
+impl InlineState for Consensus {
+    fn execute(mut self) -> Result<Option<SmallState>, Box<dyn Error>> {
+        let next = if crate::elect_leader(&mut self.connections)? {
+            SmallState::new(Leader::new(self.connections))
+        } else {
+            SmallState::new(Follower::new(self.connections))
+        };
+
+        Ok(Some(next))
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ArenaState for ConnectNodes {
+    fn execute<'a>(&mut self, arena: &'a Bump) -> Result<Option<ArenaBox<'a>>, Box<dyn Error>> {
+        let nodes = crate::connect_to_nodes(&self.nodes, &crate::ConnectPolicy::default())?;
+
+        Ok(Some(arena_box(arena, Consensus::new(nodes))))
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ArenaState for Consensus {
+    fn execute<'a>(&mut self, arena: &'a Bump) -> Result<Option<ArenaBox<'a>>, Box<dyn Error>> {
+        let mut connections = core::mem::take(&mut self.connections);
+        let next = if crate::elect_leader(&mut connections)? {
+            arena_box(arena, Leader::new(connections))
         } else {
-            Box::new(Follower::new(self.connections))
+            arena_box(arena, Follower::new(connections))
         };
 
         Ok(Some(next))
@@ -99,6 +382,25 @@ impl State for Leader {
     }
 }
 
+impl ThreadedState for Leader {
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn ThreadedState>>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ArenaState for Leader {
+    fn execute<'a>(&mut self, _arena: &'a Bump) -> Result<Option<ArenaBox<'a>>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+impl InlineState for Leader {
+    fn execute(self) -> Result<Option<SmallState>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
 pub struct Follower {
     _connections: Vec<NodeConnection>,
 }
@@ -116,3 +418,22 @@ impl State for Follower {
         Ok(None)
     }
 }
+
+impl ThreadedState for Follower {
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn ThreadedState>>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+impl InlineState for Follower {
+    fn execute(self) -> Result<Option<SmallState>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl ArenaState for Follower {
+    fn execute<'a>(&mut self, _arena: &'a Bump) -> Result<Option<ArenaBox<'a>>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,144 @@
+//! Bully election over the connections to the other nodes
+//!
+//! The node with the highest address that is still alive becomes the leader. Every node must be
+//! connected to every other node
+
+use std::{
+    io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{self, Clock},
+    NodeConnection,
+};
+
+/// How long a node waits for the answers of the higher nodes, waiting for the coordinator takes
+/// twice as long
+pub const TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Time spent waiting on each connection while polling all of them
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Sent to the higher nodes to start an election
+const ELECTION: &[u8] = b"bully/election";
+/// Sent back by a higher node, which takes over the election
+const ANSWER: &[u8] = b"bully/answer";
+/// Sent by the winner to every node
+const COORDINATOR: &[u8] = b"bully/coordinator";
+
+/// Run an election as `own` and return true if this node won, `timeout` is measured by `clock`
+///
+/// Connections that fail during the election are considered down. When a higher node answers but
+/// never announces itself, the election starts again, up to once per connection
+pub fn bully(
+    own: IpAddr,
+    connections: &mut [NodeConnection],
+    timeout: Duration,
+    clock: &dyn Clock,
+) -> io::Result<bool> {
+    let mut alive = vec![true; connections.len()];
+
+    for _ in 0..=connections.len() {
+        for (connection, alive) in connections.iter_mut().zip(alive.iter_mut()) {
+            if *alive && connection.addr() > own && connection.send(ELECTION).is_err() {
+                *alive = false;
+            }
+        }
+
+        let mut answered = false;
+        let deadline = clock.now() + timeout;
+        while let Some((from, message)) = poll(own, connections, &mut alive, deadline, clock)? {
+            match message {
+                Message::Answer => answered = true,
+                Message::Coordinator if connections[from].addr() > own => return Ok(false),
+                Message::Coordinator => {}
+            }
+        }
+
+        if !answered {
+            for (connection, alive) in connections.iter_mut().zip(alive.iter()) {
+                if *alive {
+                    // a node that can't be told is down anyway
+                    let _ = connection.send(COORDINATOR);
+                }
+            }
+
+            return Ok(true);
+        }
+
+        let deadline = clock.now() + timeout * 2;
+        while let Some((from, message)) = poll(own, connections, &mut alive, deadline, clock)? {
+            if message == Message::Coordinator && connections[from].addr() > own {
+                return Ok(false);
+            }
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "no coordinator was elected",
+    ))
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Message {
+    Answer,
+    Coordinator,
+}
+
+/// Wait for the next answer or coordinator message, until `deadline`
+///
+/// Elections started by lower nodes are answered while waiting
+fn poll(
+    own: IpAddr,
+    connections: &mut [NodeConnection],
+    alive: &mut [bool],
+    deadline: Instant,
+    clock: &dyn Clock,
+) -> io::Result<Option<(usize, Message)>> {
+    while clock.now() < deadline {
+        if !alive.iter().any(|alive| *alive) {
+            return Ok(None);
+        }
+
+        let started = clock.now();
+        for (index, connection) in connections.iter_mut().enumerate() {
+            if !alive[index] {
+                continue;
+            }
+
+            connection.set_read_timeout(Some(POLL_INTERVAL))?;
+            let message = match connection.recv() {
+                Ok(message) => message,
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(_) => {
+                    alive[index] = false;
+                    continue;
+                }
+            };
+
+            if message == ELECTION && connection.addr() < own && connection.send(ANSWER).is_err() {
+                alive[index] = false;
+            }
+
+            match message.as_slice() {
+                ANSWER => return Ok(Some((index, Message::Answer))),
+                COORDINATOR => return Ok(Some((index, Message::Coordinator))),
+                // elections are answered above, other protocols are not expected meanwhile
+                _ => {}
+            }
+        }
+        clock::count_wait(clock, started, POLL_INTERVAL);
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,181 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{error::Error, net::IpAddr};
+
+use crate::{
+    discovery::{Discovery, NodeDiscovery},
+    error::MachineError,
+    NodeConnection,
+};
+
+/// Benchmark function
+pub fn run_full_state_machine() {
+    executor(DiscoverNodes::default().into()).unwrap();
+}
+
+/// Trait to be implemented by each state, dispatched statically through [AnyState]
+pub trait State {
+    fn execute(self) -> Result<Option<AnyState>, Box<dyn Error>>;
+}
+
+/// Generate an enum with one variant per state, a `From` impl for each state and a [State]
+/// impl that forwards to the wrapped state
+macro_rules! dispatch_enum {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($state:ident),+ $(,)? }) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $($state($state)),+
+        }
+
+        $(
+            impl From<$state> for $name {
+                fn from(state: $state) -> Self {
+                    $name::$state(state)
+                }
+            }
+        )+
+
+        impl State for $name {
+            fn execute(self) -> Result<Option<AnyState>, Box<dyn Error>> {
+                match self {
+                    $($name::$state(state) => state.execute()),+
+                }
+            }
+        }
+
+        impl crate::StateName for $name {
+            fn state_name(&self) -> &'static str {
+                match self {
+                    $($name::$state(_) => stringify!($state)),+
+                }
+            }
+        }
+    };
+}
+
+dispatch_enum! {
+    /// Any of the states of the machine
+    pub enum AnyState {
+        DiscoverNodes,
+        ConnectNodes,
+        Consensus,
+        Leader,
+        Follower,
+    }
+}
+
+/// State machine executor function
+pub fn executor(initial_state: AnyState) -> Result<(), MachineError> {
+    let mut current_state = Some(initial_state);
+
+    while let Some(state) = current_state {
+        current_state = state.execute()?;
+    }
+
+    Ok(())
+}
+
+// Mock States
+// 1. Discover all nodes in the network
+// 2. Connect to all nodes
+// 3. Elect a leader
+// 4. Start a sync process
+//     1. Followers will wait for events
+//     2. The Leader will only send events
+
+#[derive(Default)]
+pub struct DiscoverNodes {
+    discovery: Discovery,
+}
+
+impl DiscoverNodes {
+    pub fn new(discovery: Discovery) -> Self {
+        Self { discovery }
+    }
+}
+
+impl State for DiscoverNodes {
+    fn execute(self) -> Result<Option<AnyState>, Box<dyn Error>> {
+        let nodes = self
+            .discovery
+            .discover()
+            .map_err(MachineError::DiscoveryFailed)?;
+        Ok(Some(ConnectNodes::new(nodes).into()))
+    }
+}
+
+pub struct ConnectNodes {
+    nodes: Vec<IpAddr>,
+}
+
+impl ConnectNodes {
+    pub fn new(nodes: Vec<IpAddr>) -> Self {
+        Self { nodes }
+    }
+}
+
+impl State for ConnectNodes {
+    fn execute(self) -> Result<Option<AnyState>, Box<dyn Error>> {
+        let nodes = crate::connect_to_nodes(&self.nodes, &crate::ConnectPolicy::default())?;
+
+        Ok(Some(Consensus::new(nodes).into()))
+    }
+}
+
+pub struct Consensus {
+    connections: Vec<NodeConnection>,
+}
+
+impl Consensus {
+    pub fn new(connections: Vec<NodeConnection>) -> Self {
+        Self { connections }
+    }
+}
+
+impl State for Consensus {
+    fn execute(mut self) -> Result<Option<AnyState>, Box<dyn Error>> {
+        let is_leader = crate::elect_leader(&mut self.connections)?;
+        let next: AnyState = if is_leader {
+            Leader::new(self.connections).into()
+        } else {
+            Follower::new(self.connections).into()
+        };
+
+        Ok(Some(next))
+    }
+}
+
+pub struct Leader {
+    _connections: Vec<NodeConnection>,
+}
+
+impl Leader {
+    pub fn new(connections: Vec<NodeConnection>) -> Self {
+        Self {
+            _connections: connections,
+        }
+    }
+}
+
+impl State for Leader {
+    fn execute(self) -> Result<Option<AnyState>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
+
+pub struct Follower {
+    _connections: Vec<NodeConnection>,
+}
+
+impl Follower {
+    pub fn new(connections: Vec<NodeConnection>) -> Self {
+        Self {
+            _connections: connections,
+        }
+    }
+}
+
+impl State for Follower {
+    fn execute(self) -> Result<Option<AnyState>, Box<dyn Error>> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,362 @@
+#[cfg(feature = "std")]
+use alloc::string::ToString;
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{error::Error, fmt::Display, net::IpAddr, time::Duration};
+
+/// Failure of a machine, returned by the executors of [crate::internal_enum] and
+/// `external_enum`
+///
+/// Errors returned by the states are kept as they are when they are already a [MachineError],
+/// otherwise they become [MachineError::State]
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum MachineError {
+    #[error("node discovery failed")]
+    DiscoveryFailed(#[source] Box<dyn Error>),
+    #[error("connection to {addr} failed")]
+    ConnectFailed {
+        addr: IpAddr,
+        #[source]
+        source: Box<dyn Error + Send + Sync>,
+    },
+    #[error(transparent)]
+    NotEnoughConnections(#[from] NotEnoughConnectionsError),
+    #[error("no leader was elected in time")]
+    ConsensusTimeout,
+    #[error("leader election failed")]
+    ConsensusFailed(#[source] Box<dyn Error>),
+    #[error(transparent)]
+    Timeout(#[from] TimeoutError),
+    #[error(transparent)]
+    Aborted(#[from] AbortedError),
+    #[error(transparent)]
+    BudgetExhausted(#[from] BudgetExhaustedError),
+    #[error(transparent)]
+    StatePanicked(#[from] StatePanickedError),
+    #[error(transparent)]
+    UnhandledEvent(#[from] UnhandledEventError),
+    #[error(transparent)]
+    CapacityExceeded(#[from] CapacityExceededError),
+    #[error(transparent)]
+    NoTransition(#[from] NoTransitionError),
+    /// A checkpoint couldn't be saved or loaded
+    #[cfg(feature = "std")]
+    #[error("checkpoint failed")]
+    Checkpoint(#[source] std::io::Error),
+    /// Any other error of a state
+    #[error(transparent)]
+    State(Box<dyn Error>),
+}
+
+impl From<Box<dyn Error>> for MachineError {
+    fn from(err: Box<dyn Error>) -> Self {
+        match err.downcast::<MachineError>() {
+            Ok(err) => *err,
+            Err(err) => MachineError::State(err),
+        }
+    }
+}
+
+/// [MachineError] moved to another thread, it becomes a [MachineError] again with [From]
+///
+/// The sources that can't cross threads, of [MachineError::DiscoveryFailed],
+/// [MachineError::ConsensusFailed] and [MachineError::State], are kept as their message
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub(crate) enum SendableError {
+    DiscoveryFailed(String),
+    ConnectFailed {
+        addr: IpAddr,
+        source: Box<dyn Error + Send + Sync>,
+    },
+    NotEnoughConnections(NotEnoughConnectionsError),
+    ConsensusTimeout,
+    ConsensusFailed(String),
+    Timeout(TimeoutError),
+    Aborted(AbortedError),
+    BudgetExhausted(BudgetExhaustedError),
+    StatePanicked(StatePanickedError),
+    UnhandledEvent(UnhandledEventError),
+    CapacityExceeded(CapacityExceededError),
+    NoTransition(NoTransitionError),
+    Checkpoint(std::io::Error),
+    State(String),
+}
+
+#[cfg(feature = "std")]
+impl SendableError {
+    /// Error of a state, a [MachineError] keeps its variant
+    pub(crate) fn from_state(err: Box<dyn Error>) -> Self {
+        MachineError::from(err).into()
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<MachineError> for SendableError {
+    fn from(err: MachineError) -> Self {
+        match err {
+            MachineError::DiscoveryFailed(source) => Self::DiscoveryFailed(source.to_string()),
+            MachineError::ConnectFailed { addr, source } => Self::ConnectFailed { addr, source },
+            MachineError::NotEnoughConnections(err) => Self::NotEnoughConnections(err),
+            MachineError::ConsensusTimeout => Self::ConsensusTimeout,
+            MachineError::ConsensusFailed(source) => Self::ConsensusFailed(source.to_string()),
+            MachineError::Timeout(err) => Self::Timeout(err),
+            MachineError::Aborted(err) => Self::Aborted(err),
+            MachineError::BudgetExhausted(err) => Self::BudgetExhausted(err),
+            MachineError::StatePanicked(err) => Self::StatePanicked(err),
+            MachineError::UnhandledEvent(err) => Self::UnhandledEvent(err),
+            MachineError::CapacityExceeded(err) => Self::CapacityExceeded(err),
+            MachineError::NoTransition(err) => Self::NoTransition(err),
+            MachineError::Checkpoint(err) => Self::Checkpoint(err),
+            MachineError::State(err) => Self::State(err.to_string()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<SendableError> for MachineError {
+    fn from(err: SendableError) -> Self {
+        match err {
+            SendableError::DiscoveryFailed(message) => {
+                MachineError::DiscoveryFailed(message.into())
+            }
+            SendableError::ConnectFailed { addr, source } => {
+                MachineError::ConnectFailed { addr, source }
+            }
+            SendableError::NotEnoughConnections(err) => MachineError::NotEnoughConnections(err),
+            SendableError::ConsensusTimeout => MachineError::ConsensusTimeout,
+            SendableError::ConsensusFailed(message) => {
+                MachineError::ConsensusFailed(message.into())
+            }
+            SendableError::Timeout(err) => MachineError::Timeout(err),
+            SendableError::Aborted(err) => MachineError::Aborted(err),
+            SendableError::BudgetExhausted(err) => MachineError::BudgetExhausted(err),
+            SendableError::StatePanicked(err) => MachineError::StatePanicked(err),
+            SendableError::UnhandledEvent(err) => MachineError::UnhandledEvent(err),
+            SendableError::CapacityExceeded(err) => MachineError::CapacityExceeded(err),
+            SendableError::NoTransition(err) => MachineError::NoTransition(err),
+            SendableError::Checkpoint(err) => MachineError::Checkpoint(err),
+            SendableError::State(message) => MachineError::State(message.into()),
+        }
+    }
+}
+
+/// A state waited longer than its timeout
+#[derive(Debug)]
+pub struct TimeoutError {
+    pub timeout: Duration,
+}
+
+impl Display for TimeoutError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "state timed out after {:?}", self.timeout)
+    }
+}
+
+impl Error for TimeoutError {}
+
+/// The machine was aborted through its control handle
+#[derive(Debug)]
+pub struct AbortedError;
+
+impl Display for AbortedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "state machine was aborted")
+    }
+}
+
+impl Error for AbortedError {}
+
+/// The machine took more steps than allowed by its budget, it is likely stuck in a cycle
+#[derive(Debug)]
+pub struct BudgetExhaustedError {
+    pub budget: usize,
+}
+
+impl Display for BudgetExhaustedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "state machine exhausted its budget of {} steps",
+            self.budget
+        )
+    }
+}
+
+impl Error for BudgetExhaustedError {}
+
+/// The current state can't handle the event and the executor policy is to fail, see
+/// `external_enum::UnhandledEvent`
+#[derive(Debug)]
+pub struct UnhandledEventError;
+
+impl Display for UnhandledEventError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "event can't be handled by the current state")
+    }
+}
+
+impl Error for UnhandledEventError {}
+
+/// An event couldn't be queued by `ingress::IngressSender::send`, the event is given back
+#[derive(Debug)]
+pub enum IngressError<E> {
+    /// The queue is full and its policy is `Overflow::Error`
+    Full(E),
+    /// The machine side of the queue was dropped
+    Closed(E),
+}
+
+impl<E> Display for IngressError<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            IngressError::Full(_) => write!(f, "event queue is full"),
+            IngressError::Closed(_) => write!(f, "event queue is closed"),
+        }
+    }
+}
+
+impl<E: core::fmt::Debug> Error for IngressError<E> {}
+
+/// A fixed capacity collection is full
+#[derive(Debug)]
+pub struct CapacityExceededError {
+    pub capacity: usize,
+}
+
+impl Display for CapacityExceededError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "capacity of {} items exceeded", self.capacity)
+    }
+}
+
+impl Error for CapacityExceededError {}
+
+/// The transition table has no row for the event produced by the state
+#[derive(Debug)]
+pub struct NoTransitionError {
+    pub state: &'static str,
+    pub event: String,
+}
+
+impl Display for NoTransitionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "no transition from {} on {}", self.state, self.event)
+    }
+}
+
+impl Error for NoTransitionError {}
+
+/// The machine was restored without its discovery, which is never checkpointed
+#[derive(Debug)]
+pub struct DiscoveryNotSetError;
+
+impl Display for DiscoveryNotSetError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "no discovery set for the restored machine")
+    }
+}
+
+impl Error for DiscoveryNotSetError {}
+
+/// Less nodes than required by the [crate::ConnectPolicy] could be connected
+#[derive(Debug)]
+pub struct NotEnoughConnectionsError {
+    pub connected: usize,
+    pub nodes: usize,
+}
+
+impl Display for NotEnoughConnectionsError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "connected to {} of {} nodes, not enough to proceed",
+            self.connected, self.nodes
+        )
+    }
+}
+
+impl Error for NotEnoughConnectionsError {}
+
+/// Failure injected by a `chaos::Chaos` layer
+#[derive(Debug)]
+pub struct InjectedFaultError {
+    /// Number of the failing call
+    pub call: usize,
+}
+
+impl Display for InjectedFaultError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "injected fault on call {}", self.call)
+    }
+}
+
+impl Error for InjectedFaultError {}
+
+/// A node of a `sim::Simulation` failed, running the simulation again with `seed` replays it
+#[derive(Debug)]
+pub struct SimulationError {
+    pub seed: u64,
+    pub node: IpAddr,
+    pub error: String,
+}
+
+impl Display for SimulationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "node {} failed: {} (simulation seed {})",
+            self.node, self.error, self.seed
+        )
+    }
+}
+
+impl Error for SimulationError {}
+
+/// A state panicked while the machine was running with the `unwind` executors
+#[derive(Debug)]
+pub struct StatePanickedError {
+    pub state: &'static str,
+    /// Message of the panic, if it was a string
+    pub payload: String,
+}
+
+impl Display for StatePanickedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "state {} panicked: {}", self.state, self.payload)
+    }
+}
+
+impl Error for StatePanickedError {}
+
+/// Failure of a machine run with the `trace` executors, with the states visited before it
+#[derive(Debug)]
+pub struct TracedError {
+    /// States visited up to the failing one, oldest first
+    pub trace: Vec<&'static str>,
+    pub error: MachineError,
+}
+
+impl Display for TracedError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} (visited {})", self.error, self.trace.join(" -> "))
+    }
+}
+
+impl Error for TracedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+/// The circuit breaker protecting the state is open
+#[derive(Debug)]
+pub struct CircuitOpenError;
+
+impl Display for CircuitOpenError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "circuit breaker is open")
+    }
+}
+
+impl Error for CircuitOpenError {}
@@ -0,0 +1,195 @@
+//! Externally driven machines whose next state depends on the event that was executed
+//!
+//! [ExternallyDrivenTransition::transition] only sees the state, so a state can't move to
+//! different states on different events without keeping them around itself. An [EventDependent]
+//! keeps the last event for the machine and hands it to [EventDependentTransition::transition],
+//! so any of the external executors can run it
+
+use std::{error::Error, time::Duration};
+
+use crate::{
+    external_enum::{EventContext, ExternallyDrivenTransition},
+    StateName,
+};
+
+/// Same as [ExternallyDrivenTransition], but the event is borrowed by `execute` and handed to
+/// `transition` afterwards
+pub trait EventDependentTransition {
+    type EventType;
+
+    fn execute(
+        &mut self,
+        input: &Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>>;
+    fn is_terminal_state(&self) -> bool;
+    fn transition(self, event: &Self::EventType) -> Self;
+
+    /// Fallible form of [EventDependentTransition::transition], see
+    /// [ExternallyDrivenTransition::try_transition]
+    fn try_transition(self, event: &Self::EventType) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        Ok(self.transition(event))
+    }
+
+    fn accepts_batch(&self) -> bool {
+        false
+    }
+
+    /// Execute a batch of events, the last one is handed to the transition
+    ///
+    /// By default the events are executed one by one
+    fn execute_batch(
+        &mut self,
+        inputs: &[Self::EventType],
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        for input in inputs {
+            self.execute(input, context)?;
+        }
+        Ok(())
+    }
+
+    fn can_handle(&self, _input: &Self::EventType) -> bool {
+        true
+    }
+
+    fn should_defer(&self, _input: &Self::EventType) -> bool {
+        false
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        None
+    }
+
+    fn on_idle(
+        &mut self,
+        _context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Wraps an [EventDependentTransition] so it can be run as an [ExternallyDrivenTransition]
+pub struct EventDependent<T: EventDependentTransition> {
+    machine: T,
+    last_event: Option<T::EventType>,
+}
+
+impl<T: EventDependentTransition> EventDependent<T> {
+    pub fn new(machine: T) -> Self {
+        Self {
+            machine,
+            last_event: None,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.machine
+    }
+}
+
+impl<T> StateName for EventDependent<T>
+where
+    T: EventDependentTransition + StateName,
+{
+    fn state_name(&self) -> &'static str {
+        self.machine.state_name()
+    }
+}
+
+impl<T: EventDependentTransition> ExternallyDrivenTransition for EventDependent<T> {
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.execute(&input, context)?;
+        self.last_event = Some(input);
+        Ok(())
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    /// Without an executed event the machine stays in the same state
+    fn transition(self) -> Self {
+        let machine = match &self.last_event {
+            Some(event) => self.machine.transition(event),
+            None => self.machine,
+        };
+
+        Self {
+            machine,
+            last_event: None,
+        }
+    }
+
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        let machine = match &self.last_event {
+            Some(event) => self.machine.try_transition(event)?,
+            None => self.machine,
+        };
+
+        Ok(Self {
+            machine,
+            last_event: None,
+        })
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        mut inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.execute_batch(&inputs, context)?;
+        if let Some(input) = inputs.pop() {
+            self.last_event = Some(input);
+        }
+        Ok(())
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.on_idle(context)
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
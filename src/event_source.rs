@@ -0,0 +1,497 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, VecDeque},
+    sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError},
+    time::Duration,
+};
+
+use crate::clock::{self, Clock, SharedClock};
+
+/// Result of waiting for an event without blocking forever
+pub enum TryNext<E> {
+    Event(E),
+    Empty,
+    Closed,
+}
+
+/// Source of events for the externally driven executors
+pub trait EventSource<E> {
+    /// Block until an event is available, `None` means the source is closed
+    fn next(&mut self) -> Option<E>;
+
+    /// Return immediately, even if there is no event available
+    fn try_next(&mut self) -> TryNext<E>;
+
+    /// Block for at most `timeout`
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E>;
+
+    /// Events waiting to be taken, `None` when the source can't tell
+    fn pending(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Async version of [EventSource]
+#[allow(async_fn_in_trait)]
+pub trait AsyncEventSource<E> {
+    /// Wait until an event is available, `None` means the source is closed
+    async fn next(&mut self) -> Option<E>;
+}
+
+impl<E, S> EventSource<E> for &mut S
+where
+    S: EventSource<E> + ?Sized,
+{
+    fn next(&mut self) -> Option<E> {
+        (**self).next()
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        (**self).try_next()
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        (**self).next_timeout(timeout)
+    }
+
+    fn pending(&self) -> Option<usize> {
+        (**self).pending()
+    }
+}
+
+impl<E> EventSource<E> for Receiver<E> {
+    fn next(&mut self) -> Option<E> {
+        self.recv().ok()
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        match self.try_recv() {
+            Ok(event) => TryNext::Event(event),
+            Err(TryRecvError::Empty) => TryNext::Empty,
+            Err(TryRecvError::Disconnected) => TryNext::Closed,
+        }
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        match self.recv_timeout(timeout) {
+            Ok(event) => TryNext::Event(event),
+            Err(RecvTimeoutError::Timeout) => TryNext::Empty,
+            Err(RecvTimeoutError::Disconnected) => TryNext::Closed,
+        }
+    }
+}
+
+/// Scripted events, the source is closed once all the events are consumed
+impl<E> EventSource<E> for VecDeque<E> {
+    fn next(&mut self) -> Option<E> {
+        self.pop_front()
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        match self.pop_front() {
+            Some(event) => TryNext::Event(event),
+            None => TryNext::Closed,
+        }
+    }
+
+    fn next_timeout(&mut self, _timeout: Duration) -> TryNext<E> {
+        self.try_next()
+    }
+
+    fn pending(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+/// Delivers the events available in `source` by priority, so control events jump ahead of bulk
+/// data. Events of the same priority keep their order
+///
+/// Only the events already received can be reordered, internal events posted by the states are
+/// still processed first by the executors
+pub struct PriorityEventSource<S, E, P> {
+    source: S,
+    priority: fn(&E) -> P,
+    queue: BinaryHeap<Prioritized<E, P>>,
+    received: u64,
+    closed: bool,
+}
+
+impl<S, E, P> PriorityEventSource<S, E, P>
+where
+    S: EventSource<E>,
+    P: Ord,
+{
+    /// Higher values of `priority` are delivered first
+    pub fn new(source: S, priority: fn(&E) -> P) -> Self {
+        Self {
+            source,
+            priority,
+            queue: BinaryHeap::new(),
+            received: 0,
+            closed: false,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+
+    fn push(&mut self, event: E) {
+        self.queue.push(Prioritized {
+            priority: (self.priority)(&event),
+            order: Reverse(self.received),
+            event,
+        });
+        self.received += 1;
+    }
+
+    /// Queue every event that is available without waiting
+    fn receive_available(&mut self) {
+        while !self.closed {
+            match self.source.try_next() {
+                TryNext::Event(event) => self.push(event),
+                TryNext::Empty => break,
+                TryNext::Closed => self.closed = true,
+            }
+        }
+    }
+
+    fn pop(&mut self) -> TryNext<E> {
+        match self.queue.pop() {
+            Some(prioritized) => TryNext::Event(prioritized.event),
+            None if self.closed => TryNext::Closed,
+            None => TryNext::Empty,
+        }
+    }
+}
+
+impl<S, E, P> EventSource<E> for PriorityEventSource<S, E, P>
+where
+    S: EventSource<E>,
+    P: Ord,
+{
+    fn next(&mut self) -> Option<E> {
+        self.receive_available();
+        if self.queue.is_empty() && !self.closed {
+            match self.source.next() {
+                Some(event) => self.push(event),
+                None => self.closed = true,
+            }
+            self.receive_available();
+        }
+
+        self.queue.pop().map(|prioritized| prioritized.event)
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        self.receive_available();
+        self.pop()
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        self.receive_available();
+        if self.queue.is_empty() && !self.closed {
+            match self.source.next_timeout(timeout) {
+                TryNext::Event(event) => self.push(event),
+                TryNext::Empty => return TryNext::Empty,
+                TryNext::Closed => self.closed = true,
+            }
+            self.receive_available();
+        }
+
+        self.pop()
+    }
+
+    /// Events already received plus the ones waiting in the source
+    fn pending(&self) -> Option<usize> {
+        Some(self.queue.len() + self.source.pending()?)
+    }
+}
+
+/// Event queued by [PriorityEventSource], ordered by priority and then by arrival
+struct Prioritized<E, P> {
+    priority: P,
+    order: Reverse<u64>,
+    event: E,
+}
+
+impl<E, P: Ord> Ord for Prioritized<E, P> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| self.order.cmp(&other.order))
+    }
+}
+
+impl<E, P: Ord> PartialOrd for Prioritized<E, P> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<E, P: Ord> PartialEq for Prioritized<E, P> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<E, P: Ord> Eq for Prioritized<E, P> {}
+
+/// How long [MergedEventSource] waits on a source before checking the next one, when none of them
+/// has an event
+pub const MERGE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Takes events from several sources in turn, so a busy source can't starve the others
+///
+/// Every source is added with a tag, mapping its events to the events of the machine, like
+/// `Event::Network` or `Event::Timer`. The merged source is closed once all of them are
+pub struct MergedEventSource<E> {
+    sources: Vec<Box<dyn EventSource<E> + Send>>,
+    next_source: usize,
+    poll_interval: Duration,
+    clock: SharedClock,
+}
+
+impl<E: 'static> MergedEventSource<E> {
+    pub fn new() -> Self {
+        Self {
+            sources: Vec::new(),
+            next_source: 0,
+            poll_interval: MERGE_POLL_INTERVAL,
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Add `source`, its events are tagged by `tag`
+    pub fn with_source<S, T>(mut self, source: S, tag: fn(T) -> E) -> Self
+    where
+        S: EventSource<T> + Send + 'static,
+        T: 'static,
+    {
+        self.sources.push(Box::new(Tagged { source, tag }));
+        self
+    }
+
+    /// How long to wait on a source before checking the next one, [MERGE_POLL_INTERVAL] by
+    /// default
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Clock measuring the timeout of [EventSource::next_timeout], the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Take an event from the first source that has one, starting after the last source an
+    /// event was taken from. Closed sources are dropped
+    fn poll(&mut self) -> TryNext<E> {
+        let mut checked = 0;
+        while checked < self.sources.len() {
+            let index = (self.next_source + checked) % self.sources.len();
+            match self.sources[index].try_next() {
+                TryNext::Event(event) => {
+                    self.next_source = index + 1;
+                    return TryNext::Event(event);
+                }
+                TryNext::Empty => checked += 1,
+                TryNext::Closed => {
+                    self.sources.remove(index);
+                }
+            }
+        }
+
+        if self.sources.is_empty() {
+            TryNext::Closed
+        } else {
+            TryNext::Empty
+        }
+    }
+
+    /// Wait for at most `timeout` on the source that is next in turn
+    fn wait(&mut self, timeout: Duration) -> TryNext<E> {
+        let index = self.next_source % self.sources.len();
+        self.next_source = index + 1;
+        match self.sources[index].next_timeout(timeout) {
+            TryNext::Closed => {
+                self.sources.remove(index);
+                if self.sources.is_empty() {
+                    TryNext::Closed
+                } else {
+                    TryNext::Empty
+                }
+            }
+            next => next,
+        }
+    }
+}
+
+impl<E: 'static> Default for MergedEventSource<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<E: 'static> EventSource<E> for MergedEventSource<E> {
+    fn next(&mut self) -> Option<E> {
+        loop {
+            match self.poll() {
+                TryNext::Event(event) => return Some(event),
+                TryNext::Closed => return None,
+                TryNext::Empty => {}
+            }
+
+            match self.wait(self.poll_interval) {
+                TryNext::Event(event) => return Some(event),
+                TryNext::Closed => return None,
+                TryNext::Empty => {}
+            }
+        }
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        self.poll()
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        let deadline = self.clock.now() + timeout;
+        loop {
+            match self.poll() {
+                TryNext::Empty => {}
+                next => return next,
+            }
+
+            let now = self.clock.now();
+            if now >= deadline {
+                return TryNext::Empty;
+            }
+
+            let wait = self.poll_interval.min(deadline - now);
+            match self.wait(wait) {
+                TryNext::Empty => clock::count_wait(&self.clock, now, wait),
+                next => return next,
+            }
+        }
+    }
+
+    /// Events waiting in all the sources, `None` when any of them can't tell
+    fn pending(&self) -> Option<usize> {
+        self.sources.iter().map(|source| source.pending()).sum()
+    }
+}
+
+/// Source of a [MergedEventSource], with its tag
+struct Tagged<S, T, E> {
+    source: S,
+    tag: fn(T) -> E,
+}
+
+impl<S, T, E> EventSource<E> for Tagged<S, T, E>
+where
+    S: EventSource<T>,
+{
+    fn next(&mut self) -> Option<E> {
+        self.source.next().map(self.tag)
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        let next = self.source.try_next();
+        self.tagged(next)
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        let next = self.source.next_timeout(timeout);
+        self.tagged(next)
+    }
+
+    fn pending(&self) -> Option<usize> {
+        self.source.pending()
+    }
+}
+
+impl<S, T, E> Tagged<S, T, E> {
+    fn tagged(&self, next: TryNext<T>) -> TryNext<E> {
+        match next {
+            TryNext::Event(event) => TryNext::Event((self.tag)(event)),
+            TryNext::Empty => TryNext::Empty,
+            TryNext::Closed => TryNext::Closed,
+        }
+    }
+}
+
+#[cfg(feature = "crossbeam-channel")]
+impl<E> EventSource<E> for crossbeam_channel::Receiver<E> {
+    fn next(&mut self) -> Option<E> {
+        self.recv().ok()
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        match self.try_recv() {
+            Ok(event) => TryNext::Event(event),
+            Err(crossbeam_channel::TryRecvError::Empty) => TryNext::Empty,
+            Err(crossbeam_channel::TryRecvError::Disconnected) => TryNext::Closed,
+        }
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        match self.recv_timeout(timeout) {
+            Ok(event) => TryNext::Event(event),
+            Err(crossbeam_channel::RecvTimeoutError::Timeout) => TryNext::Empty,
+            Err(crossbeam_channel::RecvTimeoutError::Disconnected) => TryNext::Closed,
+        }
+    }
+
+    fn pending(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+#[cfg(feature = "flume")]
+impl<E> EventSource<E> for flume::Receiver<E> {
+    fn next(&mut self) -> Option<E> {
+        self.recv().ok()
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        match self.try_recv() {
+            Ok(event) => TryNext::Event(event),
+            Err(flume::TryRecvError::Empty) => TryNext::Empty,
+            Err(flume::TryRecvError::Disconnected) => TryNext::Closed,
+        }
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        match self.recv_timeout(timeout) {
+            Ok(event) => TryNext::Event(event),
+            Err(flume::RecvTimeoutError::Timeout) => TryNext::Empty,
+            Err(flume::RecvTimeoutError::Disconnected) => TryNext::Closed,
+        }
+    }
+
+    fn pending(&self) -> Option<usize> {
+        Some(self.len())
+    }
+}
+
+#[cfg(feature = "flume")]
+impl<E> AsyncEventSource<E> for flume::Receiver<E> {
+    async fn next(&mut self) -> Option<E> {
+        self.recv_async().await.ok()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<E> AsyncEventSource<E> for tokio::sync::mpsc::Receiver<E> {
+    async fn next(&mut self) -> Option<E> {
+        self.recv().await
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<E> AsyncEventSource<E> for tokio::sync::mpsc::UnboundedReceiver<E> {
+    async fn next(&mut self) -> Option<E> {
+        self.recv().await
+    }
+}
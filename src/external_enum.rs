@@ -1,81 +1,882 @@
-use std::{error::Error, net::IpAddr, sync::mpsc::Receiver};
+use std::{collections::VecDeque, error::Error, io, net::IpAddr, sync::mpsc, time::Duration};
 
-use crate::NodeConnection;
+use crate::{
+    clock::{Clock, SharedClock, SystemClock},
+    discovery::{Discovery, NodeDiscovery},
+    error::{BudgetExhaustedError, MachineError, TimeoutError, UnhandledEventError},
+    event_source::{AsyncEventSource, EventSource, TryNext},
+    lease,
+    pool::ConnectionPool,
+    shutdown::ShutdownSignal,
+    sync::{self, Replication},
+    ConnectPolicy, NodeConnection, Quorum, Role, StateName,
+};
+
+/// Benchmark function, `events` must hold one event for each non terminal state
+pub fn run_full_state_machine(events: mpsc::Receiver<ExternalEvent>) {
+    externally_driven_executor(
+        FullStateMachine::DiscoverNodes(DiscoverNodes::default()),
+        events,
+    )
+    .unwrap();
+}
+
+/// Channel pre-filled with the events needed by [run_full_state_machine]
+pub fn full_state_machine_events() -> mpsc::Receiver<ExternalEvent> {
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..4 {
+        sender.send(ExternalEvent::Step).unwrap();
+    }
+
+    receiver
+}
+
+/// Async version of [run_full_state_machine]
+#[cfg(feature = "tokio")]
+pub async fn run_full_state_machine_async(
+    events: tokio::sync::mpsc::UnboundedReceiver<ExternalEvent>,
+) {
+    externally_driven_executor_async(
+        FullStateMachine::DiscoverNodes(DiscoverNodes::default()),
+        events,
+    )
+    .await
+    .unwrap();
+}
+
+/// Tokio channel pre-filled with the events needed by [run_full_state_machine_async]
+#[cfg(feature = "tokio")]
+pub fn full_state_machine_events_async() -> tokio::sync::mpsc::UnboundedReceiver<ExternalEvent> {
+    let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+    for _ in 0..4 {
+        sender.send(ExternalEvent::Step).unwrap();
+    }
+
+    receiver
+}
 
 pub trait ExternallyDrivenTransition {
     type EventType;
 
-    fn execute(&mut self, input: Self::EventType) -> Result<(), Box<dyn Error>>;
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>>;
     fn is_terminal_state(&self) -> bool;
+    /// Next state after an event was executed, see
+    /// [crate::event_dependent::EventDependentTransition] when it depends on the event
     fn transition(self) -> Self;
+
+    /// Fallible form of [ExternallyDrivenTransition::transition], the one called by the executors
+    ///
+    /// Override it when choosing the next state can fail, the executor fails with the error
+    fn try_transition(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        Ok(self.transition())
+    }
+
+    /// Returns true when the current state executes several events at once with
+    /// [ExternallyDrivenTransition::execute_batch], see [externally_driven_executor_batched]
+    fn accepts_batch(&self) -> bool {
+        false
+    }
+
+    /// Execute a batch of events, followed by a single transition
+    ///
+    /// By default the events are executed one by one
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        for input in inputs {
+            self.execute(input, context)?;
+        }
+        Ok(())
+    }
+
+    /// Returns false when the current state doesn't handle `input` at all, the executor applies
+    /// its [UnhandledEvent] policy instead of executing it
+    fn can_handle(&self, _input: &Self::EventType) -> bool {
+        true
+    }
+
+    /// Returns true when the current state can't handle `input` yet.
+    ///
+    /// Deferred events are queued by the executor and delivered again after the next transition
+    fn should_defer(&self, _input: &Self::EventType) -> bool {
+        false
+    }
+
+    /// How long the current state waits for an event, overriding the executor default
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Event delivered when the current state times out.
+    ///
+    /// When `None`, the executor fails with a [TimeoutError] instead
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        None
+    }
+
+    /// Called by the polling executor when there are no pending events, so the state can do
+    /// background work (heartbeats, retries) between events
+    fn on_idle(
+        &mut self,
+        _context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    /// Called on the current state when [externally_driven_executor_with_shutdown] stops the
+    /// machine before it reaches a terminal state
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Event queues owned by the executor.
+///
+/// It is handed to `execute`, so states can post internal events that are processed before the
+/// next external event is received (run to completion)
+pub struct EventContext<E> {
+    internal: VecDeque<E>,
+    pending: VecDeque<E>,
+    deferred: VecDeque<E>,
+}
+
+impl<E> EventContext<E> {
+    pub fn new() -> Self {
+        Self {
+            internal: VecDeque::new(),
+            pending: VecDeque::new(),
+            deferred: VecDeque::new(),
+        }
+    }
+
+    /// Enqueue an internal event
+    pub fn post(&mut self, event: E) {
+        self.internal.push_back(event);
+    }
+
+    /// Internal events first, then the deferred events that are being delivered again
+    pub(crate) fn next_queued(&mut self) -> Option<E> {
+        self.next_internal().or_else(|| self.next_pending())
+    }
+
+    fn next_internal(&mut self) -> Option<E> {
+        self.internal.pop_front()
+    }
+
+    fn next_pending(&mut self) -> Option<E> {
+        self.pending.pop_front()
+    }
+
+    fn has_queued(&self) -> bool {
+        !self.internal.is_empty() || !self.pending.is_empty()
+    }
+
+    pub(crate) fn defer(&mut self, event: E) {
+        self.deferred.push_back(event);
+    }
+
+    /// Deferred events are delivered again before any new event is received
+    fn redeliver_deferred(&mut self) {
+        self.deferred.append(&mut self.pending);
+        std::mem::swap(&mut self.pending, &mut self.deferred);
+    }
 }
 
-pub fn externally_driven_executor<T: ExternallyDrivenTransition>(
+impl<E> Default for EventContext<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State machine executor function
+///
+/// Returns the last state, which is not terminal if the event source was closed first
+pub fn externally_driven_executor<T, S>(initial_state: T, mut events: S) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// What the executor does with an event the current state can't handle, see
+/// [ExternallyDrivenTransition::can_handle]
+#[derive(Default)]
+pub enum UnhandledEvent<E> {
+    /// Drop the event, the state stays the same
+    #[default]
+    Ignore,
+    /// Fail with [UnhandledEventError]
+    Error,
+    /// Deliver the event again after the next transition, like a deferred event
+    Defer,
+    /// Hand the event to the function, usually a logger, then drop it
+    Log(fn(&E)),
+}
+
+/// Same as [externally_driven_executor], but the events the current state can't handle are
+/// dealt with by `policy` instead of being ignored
+pub fn externally_driven_executor_with_policy<T, S>(
     initial_state: T,
-    events: Receiver<T::EventType>,
-) -> Result<(), Box<dyn Error>> {
+    mut events: S,
+    policy: UnhandledEvent<T::EventType>,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
     let mut current_state = initial_state;
+    let mut context = EventContext::new();
 
-    while let Ok(input) = events.recv() {
-        current_state.execute(input)?;
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        current_state = process_event_with_policy(current_state, input, &mut context, &policy)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [externally_driven_executor], but a state that [accepts a
+/// batch](ExternallyDrivenTransition::accepts_batch) executes up to `max_batch` events at once,
+/// the ones that are available without waiting
+///
+/// Deferred and unhandled events are left out of the batch
+pub fn externally_driven_executor_batched<T, S>(
+    initial_state: T,
+    mut events: S,
+    max_batch: usize,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        if !current_state.accepts_batch() {
+            current_state = process_event(current_state, input, &mut context)?;
+        } else {
+            let mut batch = Vec::with_capacity(max_batch);
+            let mut next = Some(input);
+            while let Some(input) = next {
+                if current_state.should_defer(&input) {
+                    context.defer(input);
+                } else if current_state.can_handle(&input) {
+                    batch.push(input);
+                }
+
+                if batch.len() >= max_batch {
+                    break;
+                }
+
+                next = context.next_queued().or_else(|| match events.try_next() {
+                    TryNext::Event(input) => Some(input),
+                    TryNext::Empty | TryNext::Closed => None,
+                });
+            }
+
+            current_state = execute_batch(current_state, batch, &mut context)?;
+        }
 
-        current_state = current_state.transition();
         if current_state.is_terminal_state() {
             break;
         }
     }
 
-    Ok(())
+    Ok(current_state)
+}
+
+/// How often [externally_driven_executor_with_shutdown] checks the signal while waiting for an
+/// event
+pub const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Same as [externally_driven_executor], but `shutdown` is checked before every event and every
+/// [SHUTDOWN_POLL_INTERVAL] while waiting for one
+///
+/// Once it is requested, the `on_shutdown` hook of the current state is called and the state is
+/// returned, even though it is not terminal
+pub fn externally_driven_executor_with_shutdown<T, S, D>(
+    initial_state: T,
+    mut events: S,
+    mut shutdown: D,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+    D: ShutdownSignal,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    loop {
+        if shutdown.is_requested() {
+            current_state.on_shutdown()?;
+            break;
+        }
+
+        let input = match context.next_queued() {
+            Some(input) => input,
+            None => match events.next_timeout(SHUTDOWN_POLL_INTERVAL) {
+                TryNext::Event(input) => input,
+                TryNext::Empty => continue,
+                TryNext::Closed => break,
+            },
+        };
+
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [externally_driven_executor], but stops waiting for an event after the current state
+/// timeout, or `default_timeout` if the state doesn't have one
+pub fn externally_driven_executor_with_timeout<T, S>(
+    initial_state: T,
+    mut events: S,
+    default_timeout: Duration,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    loop {
+        let input = match context.next_queued() {
+            Some(input) => input,
+            None => {
+                let timeout = current_state.timeout().unwrap_or(default_timeout);
+                match events.next_timeout(timeout) {
+                    TryNext::Event(input) => input,
+                    TryNext::Empty => current_state
+                        .timeout_event()
+                        .ok_or(TimeoutError { timeout })?,
+                    TryNext::Closed => break,
+                }
+            }
+        };
+
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [externally_driven_executor], but never blocks waiting for an event.
+///
+/// When there are no pending events, the current state `on_idle` hook is called and the executor
+/// sleeps for `idle_interval` before polling again
+pub fn externally_driven_polling_executor<T, S>(
+    initial_state: T,
+    events: S,
+    idle_interval: Duration,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    externally_driven_polling_executor_with_clock(
+        initial_state,
+        events,
+        idle_interval,
+        &SystemClock,
+    )
+}
+
+/// Same as [externally_driven_polling_executor], the executor sleeps on `clock`
+pub fn externally_driven_polling_executor_with_clock<T, S>(
+    initial_state: T,
+    mut events: S,
+    idle_interval: Duration,
+    clock: &dyn Clock,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    loop {
+        let input = match context.next_queued() {
+            Some(input) => input,
+            None => match events.try_next() {
+                TryNext::Event(input) => input,
+                TryNext::Empty => {
+                    current_state.on_idle(&mut context)?;
+                    if !context.has_queued() {
+                        clock.sleep(idle_interval);
+                    }
+                    continue;
+                }
+                TryNext::Closed => break,
+            },
+        };
+
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [externally_driven_executor], but fails with [BudgetExhaustedError] when more than
+/// `max_internal_events` internal events are processed in a row, without receiving a new event
+pub fn externally_driven_executor_with_budget<T, S>(
+    initial_state: T,
+    mut events: S,
+    max_internal_events: usize,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+    let mut internal_events = 0;
+
+    loop {
+        let input = match context.next_internal() {
+            Some(input) => {
+                if internal_events == max_internal_events {
+                    return Err(BudgetExhaustedError {
+                        budget: max_internal_events,
+                    }
+                    .into());
+                }
+
+                internal_events += 1;
+                input
+            }
+            None => {
+                internal_events = 0;
+                match context
+                    .next_pending()
+                    .or_else(|| EventSource::next(&mut events))
+                {
+                    Some(input) => input,
+                    None => break,
+                }
+            }
+        };
+
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Async version of [externally_driven_executor]
+pub async fn externally_driven_executor_async<T, S>(
+    initial_state: T,
+    mut events: S,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition,
+    S: AsyncEventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    loop {
+        let input = match context.next_queued() {
+            Some(input) => input,
+            None => match AsyncEventSource::next(&mut events).await {
+                Some(input) => input,
+                None => break,
+            },
+        };
+
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Execute a single event, returning the next state
+///
+/// Events the state can't handle are ignored
+pub(crate) fn process_event<T: ExternallyDrivenTransition>(
+    state: T,
+    input: T::EventType,
+    context: &mut EventContext<T::EventType>,
+) -> Result<T, Box<dyn Error>> {
+    process_event_with_policy(state, input, context, &UnhandledEvent::Ignore)
+}
+
+/// Same as [process_event], with the events the state can't handle dealt with by `policy`
+pub(crate) fn process_event_with_policy<T: ExternallyDrivenTransition>(
+    state: T,
+    input: T::EventType,
+    context: &mut EventContext<T::EventType>,
+    policy: &UnhandledEvent<T::EventType>,
+) -> Result<T, Box<dyn Error>> {
+    if state.should_defer(&input) {
+        context.defer(input);
+        return Ok(state);
+    }
+
+    if !state.can_handle(&input) {
+        match policy {
+            UnhandledEvent::Ignore => {}
+            UnhandledEvent::Error => {
+                return Err(MachineError::from(UnhandledEventError).into());
+            }
+            UnhandledEvent::Defer => context.defer(input),
+            UnhandledEvent::Log(log) => log(&input),
+        }
+        return Ok(state);
+    }
+
+    execute_event(state, input, context)
+}
+
+/// Execute an event that is not deferred, transitioning to the next state
+pub(crate) fn execute_event<T: ExternallyDrivenTransition>(
+    mut state: T,
+    input: T::EventType,
+    context: &mut EventContext<T::EventType>,
+) -> Result<T, Box<dyn Error>> {
+    state.execute(input, context)?;
+
+    let state = state.try_transition()?;
+    context.redeliver_deferred();
+
+    Ok(state)
+}
+
+/// Execute a batch of events that are not deferred, transitioning to the next state
+fn execute_batch<T: ExternallyDrivenTransition>(
+    mut state: T,
+    inputs: Vec<T::EventType>,
+    context: &mut EventContext<T::EventType>,
+) -> Result<T, Box<dyn Error>> {
+    if inputs.is_empty() {
+        return Ok(state);
+    }
+
+    state.execute_batch(inputs, context)?;
+
+    let state = state.try_transition()?;
+    context.redeliver_deferred();
+
+    Ok(state)
 }
 
 /// Represent all possible states
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FullStateMachine {
     DiscoverNodes(DiscoverNodes),
     ConnectNodes(ConnectNodes),
     Consensus(Consensus),
+    InsufficientQuorum(InsufficientQuorum),
     Leader(Leader),
     Follower(Follower),
-    Terminate,
+    Terminate(Role),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExternalEvent {
+    /// Drives the current state forward
+    Step,
+    /// A peer missed its heartbeat deadline, see [crate::heartbeat::Heartbeat]
+    ///
+    /// The leader or a follower goes back to consensus without the peer
+    PeerDown(IpAddr),
+    /// A node joined the cluster, like the changes reported by
+    /// [crate::discovery::StaticFileDiscovery::watch]
+    ///
+    /// It is connected when the machine is already past discovery
+    NodeJoined(IpAddr),
+    /// A node left the cluster on purpose, unlike [ExternalEvent::PeerDown] a majority quorum
+    /// shrinks with it
+    NodeLeft(IpAddr),
+    /// Nodes found by a discovery running outside of the machine, completes the discovery like a
+    /// step would
+    NodesDiscovered(Vec<IpAddr>),
+    /// A node voted for this one in the election of `term`
+    ///
+    /// Votes of an older term are ignored, a newer term makes the leader step down
+    VoteReceived { from: IpAddr, term: u64 },
+    /// Data to replicate, numbered and broadcast by the leader
+    ///
+    /// A follower receives the messages of the leader instead, and applies them with its
+    /// [Replication]
+    SyncData(Vec<u8>),
+}
+
+impl ExternalEvent {
+    /// Priority for [crate::event_source::PriorityEventSource], membership and election events go
+    /// ahead of steps, which go ahead of the data to replicate
+    pub fn priority(&self) -> u8 {
+        match self {
+            ExternalEvent::PeerDown(_)
+            | ExternalEvent::NodeLeft(_)
+            | ExternalEvent::VoteReceived { .. } => 2,
+            ExternalEvent::Step
+            | ExternalEvent::NodeJoined(_)
+            | ExternalEvent::NodesDiscovered(_) => 1,
+            ExternalEvent::SyncData(_) => 0,
+        }
+    }
 }
 
-pub enum ExternalEvent {}
+impl FullStateMachine {
+    /// Set the discovery again after restoring the machine from a checkpoint
+    ///
+    /// The discovery isn't checkpointed, a restored machine fails to discover nodes until it is set
+    pub fn with_discovery(mut self, discovery: Discovery) -> Self {
+        match &mut self {
+            FullStateMachine::DiscoverNodes(state) => state.discovery = discovery,
+            FullStateMachine::ConnectNodes(state) => state.discovery = discovery,
+            FullStateMachine::Consensus(state) => state.discovery = discovery,
+            FullStateMachine::InsufficientQuorum(state) => state.discovery = discovery,
+            FullStateMachine::Leader(state) => state.discovery = discovery,
+            FullStateMachine::Follower(state) => state.discovery = discovery,
+            FullStateMachine::Terminate(_) => {}
+        }
+        self
+    }
+
+    /// Clock of the election, the lease and the backoff, the system clock by default
+    ///
+    /// Not checkpointed, a restored machine uses the system clock until it is set again
+    pub fn with_clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.with_shared_clock(SharedClock::new(clock))
+    }
+
+    fn with_shared_clock(mut self, clock: SharedClock) -> Self {
+        match &mut self {
+            FullStateMachine::DiscoverNodes(state) => state.clock = clock,
+            FullStateMachine::ConnectNodes(state) => state.clock = clock,
+            FullStateMachine::Consensus(state) => state.clock = clock,
+            FullStateMachine::InsufficientQuorum(state) => state.clock = clock,
+            FullStateMachine::Leader(state) => state.clock = clock,
+            FullStateMachine::Follower(state) => state.clock = clock,
+            FullStateMachine::Terminate(_) => {}
+        }
+        self
+    }
+
+    fn clock(&self) -> SharedClock {
+        match self {
+            FullStateMachine::DiscoverNodes(state) => state.clock.clone(),
+            FullStateMachine::ConnectNodes(state) => state.clock.clone(),
+            FullStateMachine::Consensus(state) => state.clock.clone(),
+            FullStateMachine::InsufficientQuorum(state) => state.clock.clone(),
+            FullStateMachine::Leader(state) => state.clock.clone(),
+            FullStateMachine::Follower(state) => state.clock.clone(),
+            FullStateMachine::Terminate(_) => SharedClock::default(),
+        }
+    }
+
+    fn next_state(self) -> Self {
+        match self {
+            FullStateMachine::DiscoverNodes(state) if !state.discovered => {
+                FullStateMachine::DiscoverNodes(state)
+            }
+            FullStateMachine::DiscoverNodes(state) => FullStateMachine::ConnectNodes(
+                ConnectNodes::new(state.nodes, state.discovery, state.replication),
+            ),
+            FullStateMachine::ConnectNodes(state) if !state.connected => {
+                FullStateMachine::ConnectNodes(state)
+            }
+            FullStateMachine::ConnectNodes(state)
+                if !state.quorum.is_met(state.connections.len()) =>
+            {
+                FullStateMachine::InsufficientQuorum(InsufficientQuorum::new(
+                    state.discovery,
+                    state.replication,
+                ))
+            }
+            FullStateMachine::ConnectNodes(state) => FullStateMachine::Consensus(Consensus::new(
+                state.connections,
+                state.quorum,
+                state.discovery,
+                state.replication,
+            )),
+            FullStateMachine::Consensus(state) if !state.has_quorum() => {
+                FullStateMachine::InsufficientQuorum(InsufficientQuorum::new(
+                    state.discovery,
+                    state.replication,
+                ))
+            }
+            FullStateMachine::Consensus(state) if !state.elected => {
+                FullStateMachine::Consensus(state)
+            }
+            FullStateMachine::Consensus(state) => {
+                if state.is_leader {
+                    FullStateMachine::Leader(
+                        Leader::new(
+                            state.connections,
+                            state.quorum,
+                            state.discovery,
+                            state.replication,
+                        )
+                        .with_term(state.term),
+                    )
+                } else {
+                    FullStateMachine::Follower(Follower::new(
+                        state.connections,
+                        state.quorum,
+                        state.discovery,
+                        state.replication,
+                    ))
+                }
+            }
+            FullStateMachine::InsufficientQuorum(state) => FullStateMachine::DiscoverNodes(
+                DiscoverNodes::new(state.discovery).with_replication(state.replication),
+            ),
+            FullStateMachine::Leader(state) if state.steps_down() => FullStateMachine::Consensus(
+                Consensus::new(
+                    state.pool.into_connections(),
+                    state.quorum,
+                    state.discovery,
+                    state.replication,
+                )
+                .with_term(state.term),
+            ),
+            FullStateMachine::Leader(state) if !state.stepped => FullStateMachine::Leader(state),
+            FullStateMachine::Leader(_) => FullStateMachine::Terminate(Role::Leader),
+            FullStateMachine::Follower(state) if state.peer_down || state.leader_silent => {
+                FullStateMachine::Consensus(Consensus::new(
+                    state.connections,
+                    state.quorum,
+                    state.discovery,
+                    state.replication,
+                ))
+            }
+            FullStateMachine::Follower(state) if !state.finished => {
+                FullStateMachine::Follower(state)
+            }
+            FullStateMachine::Follower(_) => FullStateMachine::Terminate(Role::Follower),
+            FullStateMachine::Terminate(_) => unreachable!(),
+        }
+    }
+}
 
 impl ExternallyDrivenTransition for FullStateMachine {
     type EventType = ExternalEvent;
 
-    fn execute(&mut self, input: Self::EventType) -> Result<(), Box<dyn Error>> {
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        _context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
         match self {
             FullStateMachine::DiscoverNodes(state) => state.execute(input),
             FullStateMachine::ConnectNodes(state) => state.execute(input),
             FullStateMachine::Consensus(state) => state.execute(input),
+            FullStateMachine::InsufficientQuorum(state) => state.execute(input),
             FullStateMachine::Leader(state) => state.execute(input),
             FullStateMachine::Follower(state) => state.execute(input),
-            FullStateMachine::Terminate => unreachable!(),
+            FullStateMachine::Terminate(_) => unreachable!(),
         }
+        .map_err(Into::into)
     }
 
     fn is_terminal_state(&self) -> bool {
-        matches!(self, Self::Terminate)
+        matches!(self, Self::Terminate(_))
     }
 
-    fn transition(self) -> Self {
+    /// Events a state has nothing to do with, any other event moves the machine forward
+    fn can_handle(&self, input: &Self::EventType) -> bool {
         match self {
-            FullStateMachine::DiscoverNodes(state) => {
-                FullStateMachine::ConnectNodes(ConnectNodes::new(state.nodes))
-            }
-            FullStateMachine::ConnectNodes(state) => {
-                FullStateMachine::Consensus(Consensus::new(state.connections))
+            FullStateMachine::DiscoverNodes(_) | FullStateMachine::ConnectNodes(_) => !matches!(
+                input,
+                ExternalEvent::PeerDown(_)
+                    | ExternalEvent::VoteReceived { .. }
+                    | ExternalEvent::SyncData(_)
+            ),
+            FullStateMachine::Leader(_) => !matches!(input, ExternalEvent::NodesDiscovered(_)),
+            FullStateMachine::Follower(_) => !matches!(
+                input,
+                ExternalEvent::NodeJoined(_)
+                    | ExternalEvent::NodesDiscovered(_)
+                    | ExternalEvent::VoteReceived { .. }
+            ),
+            // a late frame of the previous leader
+            FullStateMachine::Consensus(_) | FullStateMachine::InsufficientQuorum(_) => {
+                !matches!(input, ExternalEvent::SyncData(_))
             }
-            FullStateMachine::Consensus(state) => {
-                if state.is_leader {
-                    FullStateMachine::Leader(Leader::new(state.connections))
-                } else {
-                    FullStateMachine::Follower(Follower::new(state.connections))
-                }
+            _ => true,
+        }
+    }
+
+    /// A follower defers the messages of the leader until the lease tells who leads
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        match self {
+            FullStateMachine::Follower(state) => {
+                state.leader.is_none() && matches!(input, ExternalEvent::SyncData(_))
             }
-            FullStateMachine::Leader(_) => FullStateMachine::Terminate,
-            FullStateMachine::Follower(_) => FullStateMachine::Terminate,
-            FullStateMachine::Terminate => unreachable!(),
+            _ => false,
+        }
+    }
+
+    /// The clock of the state is handed over to the next one
+    fn transition(self) -> Self {
+        let clock = self.clock();
+        self.next_state().with_shared_clock(clock)
+    }
+}
+
+impl StateName for FullStateMachine {
+    fn state_name(&self) -> &'static str {
+        match self {
+            FullStateMachine::DiscoverNodes(_) => "DiscoverNodes",
+            FullStateMachine::ConnectNodes(_) => "ConnectNodes",
+            FullStateMachine::Consensus(_) => "Consensus",
+            FullStateMachine::InsufficientQuorum(_) => "InsufficientQuorum",
+            FullStateMachine::Leader(_) => "Leader",
+            FullStateMachine::Follower(_) => "Follower",
+            FullStateMachine::Terminate(_) => "Terminate",
         }
     }
 }
@@ -88,82 +889,509 @@ impl ExternallyDrivenTransition for FullStateMachine {
 //     1. Followers will wait for events
 //     2. The Leader will only send events
 
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct DiscoverNodes {
+    /// Not checkpointed, see [FullStateMachine::with_discovery]
+    #[cfg_attr(feature = "serde", serde(skip, default = "Discovery::unset"))]
+    discovery: Discovery,
     nodes: Vec<IpAddr>,
+    /// Nodes that left, ignored if they are discovered again
+    left: Vec<IpAddr>,
+    discovered: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 impl DiscoverNodes {
-    pub fn execute(&mut self, _input: ExternalEvent) -> Result<(), Box<dyn Error>> {
-        self.nodes = crate::get_service_nodes();
+    pub fn new(discovery: Discovery) -> Self {
+        Self {
+            discovery,
+            nodes: Vec::new(),
+            left: Vec::new(),
+            discovered: false,
+            replication: Replication::default(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Events broadcast once this node leads, and applied while it follows
+    pub fn with_replication(mut self, replication: Replication) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    /// A step discovers the nodes, adding them to the ones that joined meanwhile
+    pub fn execute(&mut self, input: ExternalEvent) -> Result<(), MachineError> {
+        match input {
+            ExternalEvent::Step => {
+                let discovered = self
+                    .discovery
+                    .discover()
+                    .map_err(MachineError::DiscoveryFailed)?;
+                self.add_discovered(discovered);
+            }
+            ExternalEvent::NodesDiscovered(nodes) => self.add_discovered(nodes),
+            ExternalEvent::NodeJoined(node) => {
+                self.left.retain(|left| *left != node);
+                if !self.nodes.contains(&node) {
+                    self.nodes.push(node);
+                }
+            }
+            ExternalEvent::NodeLeft(node) => {
+                self.nodes.retain(|known| *known != node);
+                self.left.push(node);
+            }
+            ExternalEvent::PeerDown(_)
+            | ExternalEvent::VoteReceived { .. }
+            | ExternalEvent::SyncData(_) => {}
+        }
         Ok(())
     }
+
+    fn add_discovered(&mut self, discovered: Vec<IpAddr>) {
+        for node in discovered {
+            if !self.nodes.contains(&node) && !self.left.contains(&node) {
+                self.nodes.push(node);
+            }
+        }
+        self.discovered = true;
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConnectNodes {
     nodes: Vec<IpAddr>,
     connections: Vec<NodeConnection>,
+    connected: bool,
+    quorum: Quorum,
+    /// Kept to discover the nodes again without a quorum
+    #[cfg_attr(feature = "serde", serde(skip, default = "Discovery::unset"))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl ConnectNodes {
-    pub fn new(nodes: Vec<IpAddr>) -> Self {
+    /// Requires a majority of the discovered `nodes`
+    pub fn new(nodes: Vec<IpAddr>, discovery: Discovery, replication: Replication) -> Self {
         Self {
+            quorum: Quorum::majority(nodes.len()),
             nodes,
             connections: Vec::new(),
+            connected: false,
+            discovery,
+            replication,
+            clock: SharedClock::default(),
         }
     }
 
-    pub fn execute(&mut self, _input: ExternalEvent) -> Result<(), Box<dyn Error>> {
-        self.connections = crate::connect_to_nodes(&self.nodes);
+    pub fn with_quorum(mut self, quorum: Quorum) -> Self {
+        self.quorum = quorum;
+        self
+    }
+
+    /// A step connects to the nodes, the ones that can't be reached are left out and the quorum
+    /// decides if the machine can proceed
+    ///
+    /// Membership changes before the step update the nodes and the quorum
+    pub fn execute(&mut self, input: ExternalEvent) -> Result<(), MachineError> {
+        match input {
+            ExternalEvent::Step => {
+                let policy = ConnectPolicy {
+                    min_successful: 0.0,
+                    ..Default::default()
+                };
+                self.connections = crate::connect_to_nodes(&self.nodes, &policy)?;
+                self.connected = true;
+            }
+            ExternalEvent::NodeJoined(node) => {
+                if !self.nodes.contains(&node) {
+                    self.nodes.push(node);
+                }
+                self.quorum.resize(self.nodes.len());
+            }
+            ExternalEvent::NodesDiscovered(nodes) => {
+                for node in nodes {
+                    if !self.nodes.contains(&node) {
+                        self.nodes.push(node);
+                    }
+                }
+                self.quorum.resize(self.nodes.len());
+            }
+            ExternalEvent::NodeLeft(node) => {
+                self.nodes.retain(|known| *known != node);
+                self.quorum.resize(self.nodes.len());
+            }
+            ExternalEvent::PeerDown(_)
+            | ExternalEvent::VoteReceived { .. }
+            | ExternalEvent::SyncData(_) => {}
+        }
         Ok(())
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Consensus {
     connections: Vec<NodeConnection>,
     is_leader: bool,
+    /// The election is over, [Consensus::is_leader] holds its result
+    elected: bool,
+    term: u64,
+    /// Nodes that voted for this one in `term`
+    votes: Vec<IpAddr>,
+    quorum: Quorum,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Discovery::unset"))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl Consensus {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
+    pub fn new(
+        connections: Vec<NodeConnection>,
+        quorum: Quorum,
+        discovery: Discovery,
+        replication: Replication,
+    ) -> Self {
         Self {
             connections,
             is_leader: false,
+            elected: false,
+            term: 0,
+            votes: Vec::new(),
+            quorum,
+            discovery,
+            replication,
+            clock: SharedClock::default(),
         }
     }
 
-    pub fn execute(&mut self, _input: ExternalEvent) -> Result<(), Box<dyn Error>> {
-        self.is_leader = true;
+    /// Newest term seen, votes of older terms are ignored
+    pub fn with_term(mut self, term: u64) -> Self {
+        self.term = term;
+        self
+    }
+
+    pub fn has_quorum(&self) -> bool {
+        self.quorum.is_met(self.connections.len())
+    }
+
+    /// Without a quorum there is no election, the machine discovers the nodes again
+    ///
+    /// Membership changes update the connections and the quorum. Votes of connected nodes are
+    /// counted, this node leads once they meet the quorum. Any other event runs the election with
+    /// the connected nodes
+    pub fn execute(&mut self, input: ExternalEvent) -> Result<(), MachineError> {
+        match input {
+            ExternalEvent::NodeJoined(node) => {
+                let connected = self
+                    .connections
+                    .iter()
+                    .any(|connection| connection.addr() == node);
+                // a node that can't be reached is left out, like in ConnectNodes
+                if !connected {
+                    if let Ok(connection) =
+                        ConnectPolicy::default().connect_with_clock(node, &self.clock)
+                    {
+                        self.connections.push(connection);
+                        self.quorum.resize(self.connections.len());
+                    }
+                }
+            }
+            ExternalEvent::NodeLeft(node) => {
+                self.connections
+                    .retain(|connection| connection.addr() != node);
+                self.votes.retain(|voter| *voter != node);
+                self.quorum.resize(self.connections.len());
+            }
+            ExternalEvent::PeerDown(peer) => {
+                self.connections
+                    .retain(|connection| connection.addr() != peer);
+                self.votes.retain(|voter| *voter != peer);
+            }
+            _ if !self.has_quorum() => {}
+            ExternalEvent::VoteReceived { from, term } => {
+                if term < self.term {
+                    return Ok(());
+                }
+
+                if term > self.term {
+                    self.term = term;
+                    self.votes.clear();
+                }
+
+                let connected = self
+                    .connections
+                    .iter()
+                    .any(|connection| connection.addr() == from);
+                if connected && !self.votes.contains(&from) {
+                    self.votes.push(from);
+                }
+
+                if self.quorum.is_met(self.votes.len()) {
+                    self.is_leader = true;
+                    self.elected = true;
+                }
+            }
+            _ => {
+                self.is_leader =
+                    crate::elect_leader_with_clock(&mut self.connections, &self.clock)?;
+                self.elected = true;
+            }
+        }
         Ok(())
     }
 }
 
+/// Too few nodes to make progress, the next event discovers the nodes again
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InsufficientQuorum {
+    #[cfg_attr(feature = "serde", serde(skip, default = "Discovery::unset"))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
+}
+
+impl InsufficientQuorum {
+    pub fn new(discovery: Discovery, replication: Replication) -> Self {
+        Self {
+            discovery,
+            replication,
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn execute(&mut self, _input: ExternalEvent) -> Result<(), MachineError> {
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Leader {
-    _connections: Vec<NodeConnection>,
+    pool: ConnectionPool,
+    peer_down: bool,
+    /// Term this node was elected in, or the newer one that superseded it
+    term: u64,
+    /// A vote of a newer term was seen, another election started
+    superseded: bool,
+    /// A step renewed the lease and ended the sync, the machine terminates
+    stepped: bool,
+    lease: Duration,
+    lease_expired: bool,
+    quorum: Quorum,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Discovery::unset"))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl Leader {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
+    pub fn new(
+        connections: Vec<NodeConnection>,
+        quorum: Quorum,
+        discovery: Discovery,
+        replication: Replication,
+    ) -> Self {
         Self {
-            _connections: connections,
+            // the connections of the machine are plain TCP, see crate::connect_to_nodes
+            pool: ConnectionPool::from_connections(connections, NodeConnection::connect),
+            peer_down: false,
+            term: 0,
+            superseded: false,
+            stepped: false,
+            lease: lease::LEASE,
+            lease_expired: false,
+            quorum,
+            discovery,
+            replication,
+            clock: SharedClock::default(),
         }
     }
 
-    pub fn execute(&mut self, _input: ExternalEvent) -> Result<(), Box<dyn Error>> {
+    /// How long the followers have to acknowledge the lease, [lease::LEASE] by default
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Term this node was elected in
+    pub fn with_term(mut self, term: u64) -> Self {
+        self.term = term;
+        self
+    }
+
+    /// Clock measuring the lease and the backoff of the nodes that join
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Every step renews the lease, the leader steps down and goes back to consensus when the
+    /// quorum doesn't acknowledge it
+    ///
+    /// Data and votes of the current term keep the leader running
+    pub fn execute(&mut self, input: ExternalEvent) -> Result<(), MachineError> {
+        match input {
+            ExternalEvent::Step => {
+                let mut connections: Vec<_> = self
+                    .pool
+                    .addrs()
+                    .into_iter()
+                    .filter_map(|addr| self.pool.checkout(addr))
+                    .collect();
+                self.lease_expired = !lease::renew(
+                    connections.iter_mut().map(|connection| &mut **connection),
+                    self.quorum,
+                    self.lease,
+                    &self.clock,
+                );
+                // back to the pool, so the end reaches them
+                drop(connections);
+                if !self.lease_expired {
+                    // a follower that can't be told goes back to consensus once the lease expires
+                    let _ = self.broadcast(sync::END);
+                }
+                self.stepped = true;
+            }
+            ExternalEvent::PeerDown(peer) => {
+                self.pool.remove(peer);
+                self.peer_down = true;
+            }
+            ExternalEvent::NodeJoined(node) => {
+                // a node that can't be reached is left out, like in ConnectNodes
+                if let Ok(connection) =
+                    ConnectPolicy::default().connect_with_clock(node, &self.clock)
+                {
+                    // the pool has no limit
+                    let _ = self.pool.insert(connection);
+                    self.quorum.resize(self.pool.len());
+                }
+            }
+            ExternalEvent::NodeLeft(node) => {
+                self.pool.remove(node);
+                self.quorum.resize(self.pool.len());
+            }
+            ExternalEvent::VoteReceived { term, .. } if term > self.term => {
+                self.term = term;
+                self.superseded = true;
+            }
+            ExternalEvent::VoteReceived { .. } => {}
+            ExternalEvent::SyncData(data) => {
+                // nothing waits for the followers, the sequence number isn't reused
+                let sequence = self.replication.sequence() + 1;
+                // the pool reconnects the followers that failed
+                let _ = self.broadcast(&sync::event_message(sequence, &data));
+                self.replication.acknowledged();
+            }
+            // the nodes that join are connected by NodeJoined
+            ExternalEvent::NodesDiscovered(_) => {}
+        }
         Ok(())
     }
+
+    /// The leader goes back to consensus when a peer is down, the lease expired, a newer term
+    /// started or the remaining nodes don't meet the quorum
+    fn steps_down(&self) -> bool {
+        self.peer_down
+            || self.lease_expired
+            || self.superseded
+            || !self.quorum.is_met(self.pool.len())
+    }
+
+    /// Send `message` to every follower, the failed ones are reconnected by the pool
+    pub fn broadcast(&self, message: &[u8]) -> Vec<(IpAddr, io::Error)> {
+        self.pool.broadcast(message)
+    }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Follower {
-    _connections: Vec<NodeConnection>,
+    connections: Vec<NodeConnection>,
+    peer_down: bool,
+    /// Learned from the lease, the messages received before are deferred
+    leader: Option<IpAddr>,
+    /// No lease renewal arrived, the follower goes back to consensus
+    leader_silent: bool,
+    /// The leader has no more events, the machine terminates
+    finished: bool,
+    quorum: Quorum,
+    #[cfg_attr(feature = "serde", serde(skip, default = "Discovery::unset"))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl Follower {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
+    pub fn new(
+        connections: Vec<NodeConnection>,
+        quorum: Quorum,
+        discovery: Discovery,
+        replication: Replication,
+    ) -> Self {
         Self {
-            _connections: connections,
+            connections,
+            peer_down: false,
+            leader: None,
+            leader_silent: false,
+            finished: false,
+            quorum,
+            discovery,
+            replication,
+            clock: SharedClock::default(),
         }
     }
 
-    pub fn execute(&mut self, _input: ExternalEvent) -> Result<(), Box<dyn Error>> {
+    /// Clock measuring the wait for a lease renewal
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Every step acknowledges the lease of the leader, the follower goes back to consensus when
+    /// no renewal arrives
+    ///
+    /// The events of the leader are applied by the [Replication], the follower runs until the
+    /// leader has no more
+    pub fn execute(&mut self, input: ExternalEvent) -> Result<(), MachineError> {
+        match input {
+            ExternalEvent::Step => {
+                match lease::acknowledge(&mut self.connections, lease::LEASE, &self.clock) {
+                    Some(leader) => self.leader = Some(leader),
+                    None => self.leader_silent = true,
+                }
+            }
+            ExternalEvent::PeerDown(peer) | ExternalEvent::NodeLeft(peer) => {
+                self.connections
+                    .retain(|connection| connection.addr() != peer);
+                self.peer_down = true;
+            }
+            ExternalEvent::SyncData(message) => match (self.leader, sync::parse_received(&message))
+            {
+                (Some(leader), Some(sync::Received::Event { sequence, event })) => {
+                    self.replication.apply(leader, sequence, event)
+                }
+                (Some(_), Some(sync::Received::End)) => self.finished = true,
+                // not a message of the leader
+                _ => {}
+            },
+            // the leader connects to the new node and runs the elections
+            ExternalEvent::NodeJoined(_)
+            | ExternalEvent::NodesDiscovered(_)
+            | ExternalEvent::VoteReceived { .. } => {}
+        }
         Ok(())
     }
 }
@@ -0,0 +1,127 @@
+use alloc::vec::Vec;
+use core::net::IpAddr;
+
+use crate::{
+    discovery::{Discovery, NodeDiscovery},
+    error::MachineError,
+    NodeConnection, Role, StateName,
+};
+
+/// Benchmark function
+pub fn run_full_state_machine() {
+    let mut context = Context::default();
+    executor(&STATES, StateId::DiscoverNodes, &mut context).unwrap();
+}
+
+/// State function, executes the state and returns the id of the next one
+pub type StateFn<C> = fn(&mut C) -> Result<StateId, MachineError>;
+
+/// State machine executor function
+///
+/// Every state is a function in `table`, indexed by its [StateId]. Returns the terminal state, or
+/// the error of the first state that fails
+pub fn executor<C>(
+    table: &[StateFn<C>],
+    initial_state: StateId,
+    context: &mut C,
+) -> Result<StateId, MachineError> {
+    let mut current_state = initial_state;
+
+    while current_state != StateId::Terminate {
+        current_state = table[current_state as usize](context)?;
+    }
+
+    Ok(current_state)
+}
+
+/// Index of each state in [STATES], the terminal state has no function
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateId {
+    DiscoverNodes,
+    ConnectNodes,
+    Consensus,
+    Leader,
+    Follower,
+    Terminate,
+}
+
+impl StateName for StateId {
+    fn state_name(&self) -> &'static str {
+        match self {
+            StateId::DiscoverNodes => "DiscoverNodes",
+            StateId::ConnectNodes => "ConnectNodes",
+            StateId::Consensus => "Consensus",
+            StateId::Leader => "Leader",
+            StateId::Follower => "Follower",
+            StateId::Terminate => "Terminate",
+        }
+    }
+}
+
+/// Lookup table with the function of each state, in the same order as [StateId]
+pub const STATES: [StateFn<Context>; 5] =
+    [discover_nodes, connect_nodes, consensus, leader, follower];
+
+// Mock States
+// 1. Discover all nodes in the network
+// 2. Connect to all nodes
+// 3. Elect a leader
+// 4. Start a sync process
+//     1. Followers will wait for events
+//     2. The Leader will only send events
+//
+// States are plain functions, the data lives in a context shared by all of them
+
+#[derive(Default)]
+pub struct Context {
+    discovery: Discovery,
+    nodes: Vec<IpAddr>,
+    connections: Vec<NodeConnection>,
+    role: Option<Role>,
+}
+
+impl Context {
+    pub fn new(discovery: Discovery) -> Self {
+        Self {
+            discovery,
+            ..Default::default()
+        }
+    }
+
+    /// Role of this node, once the machine terminates
+    pub fn role(&self) -> Option<Role> {
+        self.role
+    }
+}
+
+fn discover_nodes(context: &mut Context) -> Result<StateId, MachineError> {
+    context.nodes = context
+        .discovery
+        .discover()
+        .map_err(MachineError::DiscoveryFailed)?;
+    Ok(StateId::ConnectNodes)
+}
+
+fn connect_nodes(context: &mut Context) -> Result<StateId, MachineError> {
+    context.connections =
+        crate::connect_to_nodes(&context.nodes, &crate::ConnectPolicy::default())?;
+    Ok(StateId::Consensus)
+}
+
+fn consensus(context: &mut Context) -> Result<StateId, MachineError> {
+    if crate::elect_leader(&mut context.connections)? {
+        Ok(StateId::Leader)
+    } else {
+        Ok(StateId::Follower)
+    }
+}
+
+fn leader(context: &mut Context) -> Result<StateId, MachineError> {
+    context.role = Some(Role::Leader);
+    Ok(StateId::Terminate)
+}
+
+fn follower(context: &mut Context) -> Result<StateId, MachineError> {
+    context.role = Some(Role::Follower);
+    Ok(StateId::Terminate)
+}
@@ -0,0 +1,379 @@
+//! Membership maintained by gossip over UDP, a lite version of SWIM
+//!
+//! Every interval the node pings one of its members in turn, the ping and its acknowledgement
+//! carry the members known by the sender. A member that is silent for the suspect timeout after a
+//! ping it didn't answer is gone. There are no indirect probes, a member is only suspected by the
+//! nodes that ping it
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{self, Clock, SharedClock},
+    discovery::NodeDiscovery,
+    external_enum::ExternalEvent,
+};
+
+/// Sent to probe a member, followed by the members of the sender
+const PING: &[u8] = b"gossip/ping";
+/// Answer to a ping, followed by the members of the sender
+const ACK: &[u8] = b"gossip/ack";
+
+/// Change of the members, see [Gossip::start]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Membership {
+    Joined(IpAddr),
+    Left(IpAddr),
+}
+
+impl From<Membership> for ExternalEvent {
+    fn from(membership: Membership) -> Self {
+        match membership {
+            Membership::Joined(node) => ExternalEvent::NodeJoined(node),
+            Membership::Left(node) => ExternalEvent::NodeLeft(node),
+        }
+    }
+}
+
+/// Gossip of this node, listening on `port` of `addr`. Every node of the cluster uses the same port
+pub struct Gossip {
+    addr: IpAddr,
+    port: u16,
+    seeds: Vec<IpAddr>,
+    interval: Duration,
+    suspect_timeout: Duration,
+    clock: SharedClock,
+}
+
+impl Gossip {
+    pub fn new(addr: IpAddr, port: u16) -> Self {
+        Self {
+            addr,
+            port,
+            seeds: Vec::new(),
+            interval: Duration::from_millis(200),
+            suspect_timeout: Duration::from_secs(2),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Nodes pinged until they answer, the node joins the cluster through them
+    pub fn with_seeds(mut self, seeds: Vec<IpAddr>) -> Self {
+        self.seeds = seeds;
+        self
+    }
+
+    /// Time between two pings, 200ms by default
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long a member can stay silent after a ping before it is gone, 2s by default
+    pub fn with_suspect_timeout(mut self, suspect_timeout: Duration) -> Self {
+        self.suspect_timeout = suspect_timeout;
+        self
+    }
+
+    /// Clock measuring the interval and the suspect timeout, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Gossip on a background thread, every membership change is mapped with `to_event` and sent
+    /// to `events`, usually the source of an externally driven machine
+    ///
+    /// `ExternalEvent::from` maps the changes to [ExternalEvent::NodeJoined] and
+    /// [ExternalEvent::NodeLeft]. Gossip stops when the returned [GossipHandle] is dropped or the
+    /// receiver is gone
+    pub fn start<E, F>(&self, events: mpsc::Sender<E>, to_event: F) -> io::Result<GossipHandle>
+    where
+        E: Send + 'static,
+        F: Fn(Membership) -> E + Send + 'static,
+    {
+        let socket = UdpSocket::bind((self.addr, self.port))?;
+        let members = Members::default();
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut node = Node {
+            socket,
+            addr: self.addr,
+            port: self.port,
+            // the same seeds can be given to every node, this one included
+            seeds: self
+                .seeds
+                .iter()
+                .copied()
+                .filter(|seed| *seed != self.addr)
+                .collect(),
+            interval: self.interval,
+            suspect_timeout: self.suspect_timeout,
+            known: HashMap::new(),
+            gone: HashMap::new(),
+            members: members.clone(),
+            next_probe: 0,
+            clock: self.clock.clone(),
+        };
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let notify = |membership| events.send(to_event(membership)).is_ok();
+                while !stop.load(Ordering::Relaxed) {
+                    if node.round(&notify).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(GossipHandle {
+            members,
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+/// Members known by a running [Gossip], can be used as the discovery of the machine
+#[derive(Clone, Default)]
+pub struct Members(Arc<Mutex<Vec<IpAddr>>>);
+
+impl NodeDiscovery for Members {
+    fn discover(&self) -> Result<Vec<IpAddr>, Box<dyn std::error::Error>> {
+        Ok(self.0.lock().unwrap().clone())
+    }
+}
+
+/// Handle of a running gossip, it stops when dropped
+pub struct GossipHandle {
+    members: Members,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl GossipHandle {
+    pub fn members(&self) -> Members {
+        self.members.clone()
+    }
+}
+
+impl Drop for GossipHandle {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Receiver of the notifications went away
+struct Disconnected;
+
+/// State of the gossip thread
+struct Node {
+    socket: UdpSocket,
+    addr: IpAddr,
+    port: u16,
+    seeds: Vec<IpAddr>,
+    interval: Duration,
+    suspect_timeout: Duration,
+    /// Members, with the time of the first ping they didn't answer
+    known: HashMap<IpAddr, Option<Instant>>,
+    /// Members that are gone, not learned again from the gossip of others for a suspect timeout
+    gone: HashMap<IpAddr, Instant>,
+    members: Members,
+    next_probe: usize,
+    clock: SharedClock,
+}
+
+impl Node {
+    /// Ping the next member and process the messages received until the next interval
+    fn round(&mut self, notify: &impl Fn(Membership) -> bool) -> Result<(), Disconnected> {
+        self.probe();
+
+        let deadline = self.clock.now() + self.interval;
+        let mut buf = [0; 65_535];
+        while let Some(remaining) = deadline.checked_duration_since(self.clock.now()) {
+            if remaining.is_zero() || self.socket.set_read_timeout(Some(remaining)).is_err() {
+                break;
+            }
+
+            let started = self.clock.now();
+            let Ok((len, peer)) = self.socket.recv_from(&mut buf) else {
+                break;
+            };
+            clock::count_wait(&self.clock, started, remaining);
+            let (answer, gossip) = if let Some(gossip) = buf[..len].strip_prefix(PING) {
+                (true, gossip)
+            } else if let Some(gossip) = buf[..len].strip_prefix(ACK) {
+                (false, gossip)
+            } else {
+                continue;
+            };
+
+            if answer {
+                let message = self.message(ACK);
+                // a peer that can't be answered is suspected by its own pings
+                let _ = self.socket.send_to(&message, peer);
+            }
+
+            self.gone.remove(&peer.ip());
+            self.seen(peer.ip(), notify)?;
+            for member in decode(gossip) {
+                self.learn(member, notify)?;
+            }
+        }
+
+        self.expire(notify)
+    }
+
+    /// Ping the members in turn, the seeds too until they answer
+    fn probe(&mut self) {
+        let mut targets: Vec<_> = self.known.keys().copied().collect();
+        targets.extend(
+            self.seeds
+                .iter()
+                .filter(|seed| !self.known.contains_key(seed)),
+        );
+        targets.sort();
+        if targets.is_empty() {
+            return;
+        }
+
+        self.next_probe = (self.next_probe + 1) % targets.len();
+        let target = targets[self.next_probe];
+        if let Some(suspected) = self.known.get_mut(&target) {
+            suspected.get_or_insert_with(|| self.clock.now());
+        }
+
+        let message = self.message(PING);
+        // a member that can't be reached stops answering
+        let _ = self.socket.send_to(&message, (target, self.port));
+    }
+
+    /// Direct contact with `member`
+    fn seen(
+        &mut self,
+        member: IpAddr,
+        notify: &impl Fn(Membership) -> bool,
+    ) -> Result<(), Disconnected> {
+        let joined = self.known.insert(member, None).is_none();
+        if joined {
+            self.update_members();
+            if !notify(Membership::Joined(member)) {
+                return Err(Disconnected);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `member` is known by a peer, it is suspected like the others once it is pinged
+    fn learn(
+        &mut self,
+        member: IpAddr,
+        notify: &impl Fn(Membership) -> bool,
+    ) -> Result<(), Disconnected> {
+        if member == self.addr
+            || self.known.contains_key(&member)
+            || self.gone.contains_key(&member)
+        {
+            return Ok(());
+        }
+
+        self.seen(member, notify)
+    }
+
+    /// Remove the members silent for longer than the suspect timeout since a ping
+    fn expire(&mut self, notify: &impl Fn(Membership) -> bool) -> Result<(), Disconnected> {
+        let now = self.clock.now();
+        self.gone
+            .retain(|_, since| now.duration_since(*since) < self.suspect_timeout);
+
+        let expired: Vec<_> = self
+            .known
+            .iter()
+            .filter(|(_, suspected)| {
+                suspected.is_some_and(|since| now.duration_since(since) >= self.suspect_timeout)
+            })
+            .map(|(member, _)| *member)
+            .collect();
+        if expired.is_empty() {
+            return Ok(());
+        }
+
+        for member in expired.iter() {
+            self.known.remove(member);
+            self.gone.insert(*member, now);
+        }
+        self.update_members();
+
+        for member in expired {
+            if !notify(Membership::Left(member)) {
+                return Err(Disconnected);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn update_members(&self) {
+        let mut members: Vec<_> = self.known.keys().copied().collect();
+        members.sort();
+        *self.members.0.lock().unwrap() = members;
+    }
+
+    fn message(&self, kind: &[u8]) -> Vec<u8> {
+        let mut message = kind.to_vec();
+        for member in self.known.keys() {
+            match member {
+                IpAddr::V4(addr) => {
+                    message.push(4);
+                    message.extend_from_slice(&addr.octets());
+                }
+                IpAddr::V6(addr) => {
+                    message.push(6);
+                    message.extend_from_slice(&addr.octets());
+                }
+            }
+        }
+
+        message
+    }
+}
+
+/// Members encoded by [Node::message], a truncated member ends the list
+fn decode(mut gossip: &[u8]) -> Vec<IpAddr> {
+    let mut members = Vec::new();
+    while let Some((kind, rest)) = gossip.split_first() {
+        match kind {
+            4 => {
+                let Some((octets, rest)) = rest.split_first_chunk::<4>() else {
+                    break;
+                };
+                members.push(IpAddr::V4(Ipv4Addr::from(*octets)));
+                gossip = rest;
+            }
+            6 => {
+                let Some((octets, rest)) = rest.split_first_chunk::<16>() else {
+                    break;
+                };
+                members.push(IpAddr::V6(Ipv6Addr::from(*octets)));
+                gossip = rest;
+            }
+            _ => break,
+        }
+    }
+
+    members
+}
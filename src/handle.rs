@@ -0,0 +1,208 @@
+use std::{error::Error, sync::atomic::Ordering, time::Duration};
+
+use crate::{
+    event_source::EventSource,
+    external_enum::{externally_driven_executor, EventContext, ExternallyDrivenTransition},
+    internal_enum::{internally_driven_executor, InternallyDrivenTransition},
+    primitives::{
+        thread::{self, JoinHandle},
+        Arc, AtomicBool, AtomicUsize, Mutex,
+    },
+    StateName,
+};
+
+/// Metadata shared between the executor thread and its handle
+struct Metadata {
+    state_name: Mutex<&'static str>,
+    steps_taken: AtomicUsize,
+    finished: AtomicBool,
+}
+
+/// Handle to a machine running on its own thread
+pub struct ExecutorHandle {
+    metadata: Arc<Metadata>,
+    join_handle: JoinHandle<Result<(), String>>,
+}
+
+impl ExecutorHandle {
+    fn spawn<T, F>(machine: T, run: F) -> Self
+    where
+        T: StateName + Send + 'static,
+        F: FnOnce(Tracked<T>) -> Result<(), Box<dyn Error>> + Send + 'static,
+    {
+        let metadata = Arc::new(Metadata {
+            state_name: Mutex::new(machine.state_name()),
+            steps_taken: AtomicUsize::new(0),
+            finished: AtomicBool::new(false),
+        });
+
+        let machine = Tracked {
+            machine,
+            metadata: metadata.clone(),
+        };
+        let thread_metadata = metadata.clone();
+        let join_handle = thread::spawn(move || {
+            let result = run(machine).map_err(|err| err.to_string());
+            thread_metadata.finished.store(true, Ordering::Release);
+            result
+        });
+
+        Self {
+            metadata,
+            join_handle,
+        }
+    }
+
+    pub fn current_state_name(&self) -> &'static str {
+        *self.metadata.state_name.lock().unwrap()
+    }
+
+    pub fn steps_taken(&self) -> usize {
+        self.metadata.steps_taken.load(Ordering::Acquire)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.metadata.finished.load(Ordering::Acquire)
+    }
+
+    /// Wait for the machine to finish.
+    ///
+    /// Errors can't cross the thread boundary, so only their message is returned
+    pub fn join(self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        match self.join_handle.join() {
+            Ok(result) => result.map_err(Into::into),
+            Err(_) => Err("executor thread panicked".into()),
+        }
+    }
+}
+
+/// Run `machine` with [internally_driven_executor] on a new thread
+pub fn spawn_internally_driven<T>(machine: T) -> ExecutorHandle
+where
+    T: InternallyDrivenTransition + StateName + Send + 'static,
+{
+    ExecutorHandle::spawn(machine, |machine| {
+        internally_driven_executor(machine)
+            .map(|_| ())
+            .map_err(Into::into)
+    })
+}
+
+/// Run `machine` with [externally_driven_executor] on a new thread
+pub fn spawn_externally_driven<T, S>(machine: T, events: S) -> ExecutorHandle
+where
+    T: ExternallyDrivenTransition + StateName + Send + 'static,
+    S: EventSource<T::EventType> + Send + 'static,
+{
+    ExecutorHandle::spawn(machine, move |machine| {
+        externally_driven_executor(machine, events)
+            .map(|_| ())
+            .map_err(Into::into)
+    })
+}
+
+/// Wraps a state machine, updating the metadata shared with the [ExecutorHandle]
+struct Tracked<T> {
+    machine: T,
+    metadata: Arc<Metadata>,
+}
+
+impl<T> Tracked<T>
+where
+    T: StateName,
+{
+    fn track(machine: T, metadata: Arc<Metadata>) -> Self {
+        *metadata.state_name.lock().unwrap() = machine.state_name();
+        metadata.steps_taken.fetch_add(1, Ordering::AcqRel);
+        Self { machine, metadata }
+    }
+}
+
+impl<T> InternallyDrivenTransition for Tracked<T>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let machine = self.machine.execute()?;
+        Ok(Self::track(machine, self.metadata))
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T> ExternallyDrivenTransition for Tracked<T>
+where
+    T: ExternallyDrivenTransition + StateName,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.execute(input, context)
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.execute_batch(inputs, context)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(self) -> Self {
+        let machine = self.machine.transition();
+        Self::track(machine, self.metadata)
+    }
+
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        let machine = self.machine.try_transition()?;
+        Ok(Self::track(machine, self.metadata))
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.on_idle(context)
+    }
+}
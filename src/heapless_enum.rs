@@ -0,0 +1,187 @@
+use core::net::IpAddr;
+
+use heapless::Vec;
+
+use crate::{
+    error::{CapacityExceededError, MachineError},
+    NodeConnection, Role, StateName,
+};
+
+/// Maximum number of nodes the machine can track
+pub const MAX_NODES: usize = 16;
+
+/// Benchmark function
+pub fn run_full_state_machine() {
+    executor(FullStateMachine::DiscoverNodes(DiscoverNodes::default())).unwrap();
+}
+
+/// Same as [crate::internal_enum::InternallyDrivenTransition], but errors are plain values, so
+/// neither transitioning nor failing allocates
+pub trait HeaplessTransition: Sized {
+    type Error;
+
+    fn execute(self) -> Result<Self, Self::Error>;
+    fn is_terminal_state(&self) -> bool;
+}
+
+/// State machine executor function, states are moved around by value and never boxed
+///
+/// Returns the terminal state, so the caller can inspect how the machine ended
+pub fn executor<T: HeaplessTransition>(initial_state: T) -> Result<T, T::Error> {
+    let mut current_state = initial_state;
+
+    while !current_state.is_terminal_state() {
+        current_state = current_state.execute()?;
+    }
+
+    Ok(current_state)
+}
+
+/// Represent all possible states
+pub enum FullStateMachine {
+    DiscoverNodes(DiscoverNodes),
+    ConnectNodes(ConnectNodes),
+    Consensus(Consensus),
+    Leader(Leader),
+    Follower(Follower),
+    Terminate(Role),
+}
+
+impl HeaplessTransition for FullStateMachine {
+    type Error = MachineError;
+
+    fn execute(self) -> Result<Self, Self::Error> {
+        match self {
+            FullStateMachine::DiscoverNodes(discover_nodes) => {
+                let nodes = discover_nodes.execute();
+                Ok(FullStateMachine::ConnectNodes(ConnectNodes::new(nodes)))
+            }
+            FullStateMachine::ConnectNodes(connect_nodes) => {
+                let connections = connect_nodes.execute()?;
+                Ok(FullStateMachine::Consensus(Consensus::new(connections)))
+            }
+            FullStateMachine::Consensus(consensus) => {
+                let (is_leader, connections) = consensus.execute()?;
+                if is_leader {
+                    Ok(FullStateMachine::Leader(Leader::new(connections)))
+                } else {
+                    Ok(FullStateMachine::Follower(Follower::new(connections)))
+                }
+            }
+            FullStateMachine::Leader(leader) => {
+                leader.execute();
+                Ok(FullStateMachine::Terminate(Role::Leader))
+            }
+            FullStateMachine::Follower(follower) => {
+                follower.execute();
+                Ok(FullStateMachine::Terminate(Role::Follower))
+            }
+            FullStateMachine::Terminate(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        matches!(self, Self::Terminate(_))
+    }
+}
+
+impl StateName for FullStateMachine {
+    fn state_name(&self) -> &'static str {
+        match self {
+            FullStateMachine::DiscoverNodes(_) => "DiscoverNodes",
+            FullStateMachine::ConnectNodes(_) => "ConnectNodes",
+            FullStateMachine::Consensus(_) => "Consensus",
+            FullStateMachine::Leader(_) => "Leader",
+            FullStateMachine::Follower(_) => "Follower",
+            FullStateMachine::Terminate(_) => "Terminate",
+        }
+    }
+}
+
+// Mock States
+// 1. Discover all nodes in the network
+// 2. Connect to all nodes
+// 3. Elect a leader
+// 4. Start a sync process
+//     1. Followers will wait for events
+//     2. The Leader will only send events
+
+#[derive(Default)]
+pub struct DiscoverNodes {}
+impl DiscoverNodes {
+    pub fn execute(self) -> Vec<IpAddr, MAX_NODES> {
+        Vec::new()
+    }
+}
+
+pub struct ConnectNodes {
+    nodes: Vec<IpAddr, MAX_NODES>,
+}
+
+impl ConnectNodes {
+    pub fn new(nodes: Vec<IpAddr, MAX_NODES>) -> Self {
+        Self { nodes }
+    }
+
+    pub fn execute(self) -> Result<Vec<NodeConnection, MAX_NODES>, CapacityExceededError> {
+        let mut connections = Vec::new();
+        for node in self.nodes.iter() {
+            // unreachable nodes are left out, there is no room for the error in a heapless machine
+            let Ok(connection) = NodeConnection::connect(*node) else {
+                continue;
+            };
+            connections
+                .push(connection)
+                .map_err(|_| CapacityExceededError {
+                    capacity: MAX_NODES,
+                })?;
+        }
+
+        Ok(connections)
+    }
+}
+
+pub struct Consensus {
+    connections: Vec<NodeConnection, MAX_NODES>,
+}
+
+impl Consensus {
+    pub fn new(connections: Vec<NodeConnection, MAX_NODES>) -> Self {
+        Self { connections }
+    }
+
+    pub fn execute(mut self) -> Result<(bool, Vec<NodeConnection, MAX_NODES>), MachineError> {
+        let is_leader = crate::elect_leader(&mut self.connections)?;
+        Ok((is_leader, self.connections))
+    }
+}
+
+pub struct Leader {
+    _connections: Vec<NodeConnection, MAX_NODES>,
+}
+
+impl Leader {
+    pub fn new(connections: Vec<NodeConnection, MAX_NODES>) -> Self {
+        Self {
+            _connections: connections,
+        }
+    }
+
+    pub fn execute(self) {}
+}
+
+pub struct Follower {
+    _connections: Vec<NodeConnection, MAX_NODES>,
+}
+
+impl Follower {
+    pub fn new(connections: Vec<NodeConnection, MAX_NODES>) -> Self {
+        Self {
+            _connections: connections,
+        }
+    }
+
+    pub fn execute(self) {}
+}
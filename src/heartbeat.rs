@@ -0,0 +1,73 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    clock::{Clock, SharedClock},
+    NodeConnection,
+};
+
+/// Liveness checks of the peers of a running node
+///
+/// Pongs are processed by [NodeConnection::recv], so the connections must keep being received
+/// from between the checks, like in the sync loop of the leader
+pub struct Heartbeat {
+    interval: Duration,
+    deadline: Duration,
+    last_ping: Option<Instant>,
+    down: Vec<IpAddr>,
+    clock: SharedClock,
+}
+
+impl Heartbeat {
+    /// Ping every `interval`, a peer that sent nothing for `deadline` is down
+    pub fn new(interval: Duration, deadline: Duration) -> Self {
+        Self {
+            interval,
+            deadline,
+            last_ping: None,
+            down: Vec::new(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Clock measuring the interval and the deadline, the connections must stamp the last message
+    /// of their peer with the same one, see [NodeConnection::with_clock]
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Ping the connections if the interval elapsed and return the peers that went down since the
+    /// last check, usually fed into the machine as `ExternalEvent::PeerDown`
+    ///
+    /// A peer that can't be pinged is down as well, a peer that comes back can be reported again
+    pub fn check(&mut self, connections: &mut [NodeConnection]) -> Vec<IpAddr> {
+        let now = self.clock.now();
+        let ping = match self.last_ping {
+            Some(last_ping) => now.saturating_duration_since(last_ping) >= self.interval,
+            None => true,
+        };
+        if ping {
+            self.last_ping = Some(now);
+        }
+
+        let mut went_down = Vec::new();
+        for connection in connections.iter_mut() {
+            let alive = (!ping || connection.ping().is_ok())
+                && now.saturating_duration_since(connection.last_seen()) < self.deadline;
+
+            let addr = connection.addr();
+            let was_down = self.down.contains(&addr);
+            if alive && was_down {
+                self.down.retain(|down| *down != addr);
+            } else if !alive && !was_down {
+                self.down.push(addr);
+                went_down.push(addr);
+            }
+        }
+
+        went_down
+    }
+}
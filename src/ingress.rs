@@ -0,0 +1,269 @@
+//! Bounded queue of events, so a bursty producer can't grow the queue of a slow machine without
+//! limit
+//!
+//! [bounded] returns the side used by the producers and an [Ingress] to be used as the
+//! [EventSource] of the machine. The [Overflow] policy decides what happens when the queue is full
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::Duration,
+};
+
+use crate::{
+    clock::{self, Clock, SharedClock},
+    error::IngressError,
+    event_source::{EventSource, TryNext},
+};
+
+/// What [IngressSender::send] does when the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wait until the machine takes an event
+    #[default]
+    Block,
+    /// Drop the oldest queued event to make room for the new one
+    DropOldest,
+    /// Drop the new event
+    DropNewest,
+    /// Fail with [IngressError::Full]
+    Error,
+}
+
+/// Queue of `capacity` events, at least one
+pub fn bounded<E>(capacity: usize, overflow: Overflow) -> (IngressSender<E>, Ingress<E>) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            events: VecDeque::with_capacity(capacity.max(1)),
+            senders: 1,
+            receiving: true,
+        }),
+        not_empty: Condvar::new(),
+        not_full: Condvar::new(),
+        capacity: capacity.max(1),
+        overflow,
+        metrics: IngressMetrics::default(),
+    });
+
+    (
+        IngressSender {
+            shared: shared.clone(),
+        },
+        Ingress {
+            shared,
+            clock: SharedClock::default(),
+        },
+    )
+}
+
+struct Shared<E> {
+    queue: Mutex<Queue<E>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+    metrics: IngressMetrics,
+}
+
+struct Queue<E> {
+    events: VecDeque<E>,
+    senders: usize,
+    receiving: bool,
+}
+
+impl<E> Shared<E> {
+    fn lock(&self) -> MutexGuard<'_, Queue<E>> {
+        self.queue.lock().unwrap()
+    }
+
+    /// Take the next event, waking up a blocked sender
+    fn pop(&self, queue: &mut Queue<E>) -> Option<E> {
+        let event = queue.events.pop_front()?;
+        self.metrics
+            .depth
+            .store(queue.events.len(), Ordering::Relaxed);
+        self.not_full.notify_one();
+        Some(event)
+    }
+}
+
+/// Queue depth and drops of an [Ingress], they can be read while the machine runs
+#[derive(Debug, Clone, Default)]
+pub struct IngressMetrics {
+    depth: Arc<AtomicUsize>,
+    max_depth: Arc<AtomicUsize>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl IngressMetrics {
+    /// Events waiting in the queue
+    pub fn depth(&self) -> usize {
+        self.depth.load(Ordering::Relaxed)
+    }
+
+    /// Deepest the queue has been
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    /// Events dropped by the [Overflow::DropOldest] and [Overflow::DropNewest] policies
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// Producer side of [bounded], it can be cloned for each producer
+pub struct IngressSender<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> IngressSender<E> {
+    /// Queue `event`, applying the [Overflow] policy when the queue is full
+    ///
+    /// Fails with [IngressError::Closed] once the [Ingress] is dropped
+    pub fn send(&self, event: E) -> Result<(), IngressError<E>> {
+        let shared = &*self.shared;
+        let mut queue = shared.lock();
+        loop {
+            if !queue.receiving {
+                return Err(IngressError::Closed(event));
+            }
+
+            if queue.events.len() < shared.capacity {
+                break;
+            }
+
+            match shared.overflow {
+                Overflow::Block => queue = shared.not_full.wait(queue).unwrap(),
+                Overflow::DropOldest => {
+                    queue.events.pop_front();
+                    shared.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Overflow::DropNewest => {
+                    shared.metrics.dropped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                Overflow::Error => return Err(IngressError::Full(event)),
+            }
+        }
+
+        queue.events.push_back(event);
+        let depth = queue.events.len();
+        shared.metrics.depth.store(depth, Ordering::Relaxed);
+        shared.metrics.max_depth.fetch_max(depth, Ordering::Relaxed);
+        shared.not_empty.notify_one();
+
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> IngressMetrics {
+        self.shared.metrics.clone()
+    }
+}
+
+impl<E> Clone for IngressSender<E> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// The [Ingress] is closed once every sender is dropped and the queue is empty
+impl<E> Drop for IngressSender<E> {
+    fn drop(&mut self) {
+        self.shared.lock().senders -= 1;
+        self.shared.not_empty.notify_all();
+    }
+}
+
+/// Machine side of [bounded]
+pub struct Ingress<E> {
+    shared: Arc<Shared<E>>,
+    clock: SharedClock,
+}
+
+impl<E> Ingress<E> {
+    /// Clock measuring the timeout of [EventSource::next_timeout], the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    /// Metrics of the queue, keep a clone to read them while the machine runs
+    pub fn metrics(&self) -> IngressMetrics {
+        self.shared.metrics.clone()
+    }
+}
+
+/// Senders fail with [IngressError::Closed] once the ingress is dropped
+impl<E> Drop for Ingress<E> {
+    fn drop(&mut self) {
+        self.shared.lock().receiving = false;
+        self.shared.not_full.notify_all();
+    }
+}
+
+impl<E> EventSource<E> for Ingress<E> {
+    fn next(&mut self) -> Option<E> {
+        let shared = &*self.shared;
+        let mut queue = shared.lock();
+        loop {
+            if let Some(event) = shared.pop(&mut queue) {
+                return Some(event);
+            }
+
+            if queue.senders == 0 {
+                return None;
+            }
+
+            queue = shared.not_empty.wait(queue).unwrap();
+        }
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        let shared = &*self.shared;
+        let mut queue = shared.lock();
+        match shared.pop(&mut queue) {
+            Some(event) => TryNext::Event(event),
+            None if queue.senders == 0 => TryNext::Closed,
+            None => TryNext::Empty,
+        }
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        let shared = &*self.shared;
+        let deadline = self.clock.now() + timeout;
+        let mut queue = shared.lock();
+        loop {
+            if let Some(event) = shared.pop(&mut queue) {
+                return TryNext::Event(event);
+            }
+
+            if queue.senders == 0 {
+                return TryNext::Closed;
+            }
+
+            let now = self.clock.now();
+            if now >= deadline {
+                return TryNext::Empty;
+            }
+
+            queue = shared
+                .not_empty
+                .wait_timeout(queue, deadline - now)
+                .unwrap()
+                .0;
+            clock::count_wait(&self.clock, now, deadline - now);
+        }
+    }
+
+    fn pending(&self) -> Option<usize> {
+        Some(self.shared.lock().events.len())
+    }
+}
@@ -1,6 +1,15 @@
-use std::{error::Error, net::IpAddr};
+use alloc::{boxed::Box, vec::Vec};
+use core::{error::Error, net::IpAddr, time::Duration};
 
-use crate::NodeConnection;
+#[cfg(feature = "std")]
+use crate::clock::{Clock, SharedClock};
+use crate::{
+    discovery::{Discovery, NodeDiscovery},
+    error::{BudgetExhaustedError, MachineError},
+    shutdown::ShutdownSignal,
+    sync::{self, Replication},
+    ConnectPolicy, NodeConnection, Quorum, Role, StateName,
+};
 
 /// Benchmark function
 pub fn run_full_state_machine() {
@@ -11,74 +20,335 @@ pub fn run_full_state_machine() {
 ///
 /// The states in this implementation don't need to implement any trait
 pub trait InternallyDrivenTransition {
+    /// Execute the current state and choose the next one, both can fail
     fn execute(self) -> Result<Self, Box<dyn Error>>
     where
         Self: Sized;
 
     fn is_terminal_state(&self) -> bool;
+
+    /// Called on the current state when [internally_driven_executor_with_shutdown] stops the
+    /// machine before it reaches a terminal state
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
 }
 
 /// State machine executor function
+///
+/// Returns the terminal state, so the caller can inspect how the machine ended
 pub fn internally_driven_executor<T: InternallyDrivenTransition>(
     initial_state: T,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<T, MachineError> {
+    let mut current_state = initial_state;
+
+    while !current_state.is_terminal_state() {
+        current_state = current_state.execute()?;
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [internally_driven_executor], but fails with [BudgetExhaustedError] after `max_steps`
+/// transitions without reaching a terminal state
+pub fn internally_driven_executor_with_budget<T: InternallyDrivenTransition>(
+    initial_state: T,
+    max_steps: usize,
+) -> Result<T, MachineError> {
+    let mut current_state = initial_state;
+    let mut steps = 0;
+
+    while !current_state.is_terminal_state() {
+        if steps == max_steps {
+            return Err(BudgetExhaustedError { budget: max_steps }.into());
+        }
+
+        current_state = current_state.execute()?;
+        steps += 1;
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [internally_driven_executor], but `shutdown` is checked before every transition
+///
+/// Once it is requested, the `on_shutdown` hook of the current state is called and the state is
+/// returned, even though it is not terminal
+pub fn internally_driven_executor_with_shutdown<T, S>(
+    initial_state: T,
+    mut shutdown: S,
+) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition,
+    S: ShutdownSignal,
+{
     let mut current_state = initial_state;
 
     while !current_state.is_terminal_state() {
+        if shutdown.is_requested() {
+            current_state.on_shutdown()?;
+            break;
+        }
+
         current_state = current_state.execute()?;
     }
 
-    Ok(())
+    Ok(current_state)
+}
+
+/// Same as [InternallyDrivenTransition], but every state has access to a context shared by the
+/// whole machine, like configuration, metrics or a connection registry
+pub trait InternallyDrivenTransitionWithContext<C> {
+    fn execute(self, context: &mut C) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized;
+
+    fn is_terminal_state(&self) -> bool;
+}
+
+/// State machine executor function for [InternallyDrivenTransitionWithContext]
+///
+/// The executor owns `context` while the machine runs, it is returned with the terminal state
+pub fn internally_driven_executor_with_context<T, C>(
+    initial_state: T,
+    mut context: C,
+) -> Result<(T, C), MachineError>
+where
+    T: InternallyDrivenTransitionWithContext<C>,
+{
+    let mut current_state = initial_state;
+
+    while !current_state.is_terminal_state() {
+        current_state = current_state.execute(&mut context)?;
+    }
+
+    Ok((current_state, context))
+}
+
+/// Position of the machine after a transition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateSnapshot {
+    pub step: usize,
+    pub state_name: &'static str,
+}
+
+/// Extension to drive a machine one transition at a time
+pub trait IntoSteps: InternallyDrivenTransition + Sized {
+    fn into_steps(self) -> Steps<Self> {
+        Steps {
+            state: Some(self),
+            step: 0,
+        }
+    }
+}
+
+impl<T> IntoSteps for T where T: InternallyDrivenTransition {}
+
+/// Iterator that executes one transition per item, until the machine reaches a terminal state.
+///
+/// The iterator ends after the first error, since the failing state is consumed
+pub struct Steps<T> {
+    state: Option<T>,
+    step: usize,
+}
+
+impl<T> Steps<T> {
+    /// Current state, `None` after an error
+    pub fn state(&self) -> Option<&T> {
+        self.state.as_ref()
+    }
+
+    pub fn into_state(self) -> Option<T> {
+        self.state
+    }
+}
+
+impl<T> Iterator for Steps<T>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    type Item = Result<StateSnapshot, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.state.as_ref()?.is_terminal_state() {
+            return None;
+        }
+
+        match self.state.take()?.execute() {
+            Ok(next_state) => {
+                self.step += 1;
+                let snapshot = StateSnapshot {
+                    step: self.step,
+                    state_name: next_state.state_name(),
+                };
+
+                self.state = Some(next_state);
+                Some(Ok(snapshot))
+            }
+            Err(err) => Some(Err(err)),
+        }
+    }
 }
 
 /// Represent all possible states
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FullStateMachine {
     DiscoverNodes(DiscoverNodes),
     ConnectNodes(ConnectNodes),
     Consensus(Consensus),
+    InsufficientQuorum(InsufficientQuorum),
     Leader(Leader),
     Follower(Follower),
-    Terminate,
+    Terminate(Role),
 }
 
-impl InternallyDrivenTransition for FullStateMachine {
-    fn execute(self) -> Result<Self, Box<dyn Error>>
-    where
-        Self: Sized,
-    {
+impl FullStateMachine {
+    /// Clock of the timeouts, the lease and the retries, the system clock by default
+    ///
+    /// Not checkpointed, a restored machine uses the system clock until it is set again
+    #[cfg(feature = "std")]
+    pub fn with_clock<C: Clock + 'static>(self, clock: C) -> Self {
+        self.with_shared_clock(SharedClock::new(clock))
+    }
+
+    #[cfg(feature = "std")]
+    fn with_shared_clock(mut self, clock: SharedClock) -> Self {
+        match &mut self {
+            FullStateMachine::DiscoverNodes(state) => state.clock = clock,
+            FullStateMachine::ConnectNodes(state) => state.clock = clock,
+            FullStateMachine::Consensus(state) => state.clock = clock,
+            FullStateMachine::InsufficientQuorum(state) => state.clock = clock,
+            FullStateMachine::Leader(state) => state.clock = clock,
+            FullStateMachine::Follower(state) => state.clock = clock,
+            FullStateMachine::Terminate(_) => {}
+        }
+        self
+    }
+
+    #[cfg(feature = "std")]
+    fn clock(&self) -> SharedClock {
+        match self {
+            FullStateMachine::DiscoverNodes(state) => state.clock.clone(),
+            FullStateMachine::ConnectNodes(state) => state.clock.clone(),
+            FullStateMachine::Consensus(state) => state.clock.clone(),
+            FullStateMachine::InsufficientQuorum(state) => state.clock.clone(),
+            FullStateMachine::Leader(state) => state.clock.clone(),
+            FullStateMachine::Follower(state) => state.clock.clone(),
+            FullStateMachine::Terminate(_) => SharedClock::default(),
+        }
+    }
+
+    fn step(self) -> Result<Self, Box<dyn Error>> {
         match self {
             FullStateMachine::DiscoverNodes(discover_nodes) => {
-                let nodes = discover_nodes.execute();
-                Ok(FullStateMachine::ConnectNodes(ConnectNodes::new(nodes)))
+                let nodes = discover_nodes.execute()?;
+                Ok(FullStateMachine::ConnectNodes(ConnectNodes::new(
+                    nodes,
+                    discover_nodes.discovery,
+                    discover_nodes.replication,
+                )))
             }
             FullStateMachine::ConnectNodes(connect_nodes) => {
-                let connections = connect_nodes.execute();
-                Ok(FullStateMachine::Consensus(Consensus::new(connections)))
+                let connections = connect_nodes.execute()?;
+                if connect_nodes.quorum.is_met(connections.len()) {
+                    Ok(FullStateMachine::Consensus(Consensus::new(
+                        connections,
+                        connect_nodes.quorum,
+                        connect_nodes.discovery,
+                        connect_nodes.replication,
+                    )))
+                } else {
+                    Ok(FullStateMachine::InsufficientQuorum(
+                        InsufficientQuorum::new(connect_nodes.discovery, connect_nodes.replication),
+                    ))
+                }
             }
-            FullStateMachine::Consensus(consensus) => {
-                let (is_leader, connections) = consensus.execute();
-                if is_leader {
-                    Ok(FullStateMachine::Leader(Leader::new(connections)))
+            FullStateMachine::Consensus(consensus) if !consensus.has_quorum() => {
+                Ok(FullStateMachine::InsufficientQuorum(
+                    InsufficientQuorum::new(consensus.discovery, consensus.replication),
+                ))
+            }
+            FullStateMachine::Consensus(mut consensus) => {
+                if consensus.execute()? {
+                    Ok(FullStateMachine::Leader(Leader::new(
+                        consensus.connections,
+                        consensus.quorum,
+                        consensus.discovery,
+                        consensus.replication,
+                    )))
                 } else {
-                    Ok(FullStateMachine::Follower(Follower::new(connections)))
+                    Ok(FullStateMachine::Follower(Follower::new(
+                        consensus.connections,
+                        consensus.quorum,
+                        consensus.discovery,
+                        consensus.replication,
+                    )))
                 }
             }
-            FullStateMachine::Leader(leader) => {
-                leader.execute();
-                Ok(FullStateMachine::Terminate)
+            FullStateMachine::InsufficientQuorum(insufficient_quorum) => Ok(
+                FullStateMachine::DiscoverNodes(insufficient_quorum.execute()),
+            ),
+            FullStateMachine::Leader(mut leader) => {
+                if leader.execute() {
+                    Ok(FullStateMachine::Terminate(Role::Leader))
+                } else {
+                    Ok(FullStateMachine::Consensus(Consensus::new(
+                        leader.connections,
+                        leader.quorum,
+                        leader.discovery,
+                        leader.replication,
+                    )))
+                }
             }
-            FullStateMachine::Follower(follower) => {
-                follower.execute();
-                Ok(FullStateMachine::Terminate)
+            FullStateMachine::Follower(mut follower) => {
+                if follower.execute() {
+                    Ok(FullStateMachine::Terminate(Role::Follower))
+                } else {
+                    Ok(FullStateMachine::Consensus(Consensus::new(
+                        follower.connections,
+                        follower.quorum,
+                        follower.discovery,
+                        follower.replication,
+                    )))
+                }
             }
-            FullStateMachine::Terminate => {
+            FullStateMachine::Terminate(_) => {
                 unreachable!()
             }
         }
     }
+}
+
+impl InternallyDrivenTransition for FullStateMachine {
+    /// The clock of the state is handed over to the next one
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        #[cfg(feature = "std")]
+        let clock = self.clock();
+        let next = self.step()?;
+        #[cfg(feature = "std")]
+        let next = next.with_shared_clock(clock);
+        Ok(next)
+    }
 
     fn is_terminal_state(&self) -> bool {
-        matches!(self, Self::Terminate)
+        matches!(self, Self::Terminate(_))
+    }
+}
+
+impl StateName for FullStateMachine {
+    fn state_name(&self) -> &'static str {
+        match self {
+            FullStateMachine::DiscoverNodes(_) => "DiscoverNodes",
+            FullStateMachine::ConnectNodes(_) => "ConnectNodes",
+            FullStateMachine::Consensus(_) => "Consensus",
+            FullStateMachine::InsufficientQuorum(_) => "InsufficientQuorum",
+            FullStateMachine::Leader(_) => "Leader",
+            FullStateMachine::Follower(_) => "Follower",
+            FullStateMachine::Terminate(_) => "Terminate",
+        }
     }
 }
 
@@ -91,65 +361,313 @@ impl InternallyDrivenTransition for FullStateMachine {
 //     2. The Leader will only send events
 
 #[derive(Default)]
-pub struct DiscoverNodes {}
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DiscoverNodes {
+    /// Not checkpointed, a restored machine uses the default discovery
+    #[cfg_attr(feature = "serde", serde(skip))]
+    discovery: Discovery,
+    /// Not checkpointed, a restored machine has nothing to replicate
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    /// Not checkpointed, a restored machine uses the system clock
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
+}
 impl DiscoverNodes {
-    pub fn execute(self) -> Vec<IpAddr> {
-        crate::get_service_nodes()
+    pub fn new(discovery: Discovery) -> Self {
+        Self {
+            discovery,
+            replication: Replication::default(),
+            #[cfg(feature = "std")]
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Data broadcast by this node if it becomes the leader
+    pub fn with_replication(mut self, replication: Replication) -> Self {
+        self.replication = replication;
+        self
+    }
+
+    pub fn execute(&self) -> Result<Vec<IpAddr>, MachineError> {
+        self.discovery
+            .discover()
+            .map_err(MachineError::DiscoveryFailed)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConnectNodes {
     nodes: Vec<IpAddr>,
+    quorum: Quorum,
+    /// Kept to discover the nodes again without a quorum
+    #[cfg_attr(feature = "serde", serde(skip))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl ConnectNodes {
-    pub fn new(nodes: Vec<IpAddr>) -> Self {
-        Self { nodes }
+    /// Requires a majority of the discovered `nodes`
+    pub fn new(nodes: Vec<IpAddr>, discovery: Discovery, replication: Replication) -> Self {
+        Self {
+            quorum: Quorum::majority(nodes.len()),
+            nodes,
+            discovery,
+            replication,
+            #[cfg(feature = "std")]
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn with_quorum(mut self, quorum: Quorum) -> Self {
+        self.quorum = quorum;
+        self
     }
 
-    pub fn execute(self) -> Vec<crate::NodeConnection> {
-        crate::connect_to_nodes(&self.nodes)
+    /// Nodes that can't be reached are left out, the quorum decides if the machine can proceed
+    pub fn execute(&self) -> Result<Vec<NodeConnection>, MachineError> {
+        let policy = ConnectPolicy {
+            min_successful: 0.0,
+            ..Default::default()
+        };
+        crate::connect_to_nodes(&self.nodes, &policy)
     }
 }
 
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Consensus {
     connections: Vec<NodeConnection>,
+    quorum: Quorum,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl Consensus {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
-        Self { connections }
+    pub fn new(
+        connections: Vec<NodeConnection>,
+        quorum: Quorum,
+        discovery: Discovery,
+        replication: Replication,
+    ) -> Self {
+        Self {
+            connections,
+            quorum,
+            discovery,
+            replication,
+            #[cfg(feature = "std")]
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn has_quorum(&self) -> bool {
+        self.quorum.is_met(self.connections.len())
     }
 
-    pub fn execute(self) -> (bool, Vec<NodeConnection>) {
-        (true, self.connections)
+    /// Returns true if this node leads the cluster
+    #[cfg(feature = "std")]
+    pub fn execute(&mut self) -> Result<bool, MachineError> {
+        crate::elect_leader_with_clock(&mut self.connections, &self.clock)
     }
+
+    #[cfg(not(feature = "std"))]
+    pub fn execute(&mut self) -> Result<bool, MachineError> {
+        crate::elect_leader(&mut self.connections)
+    }
+}
+
+/// Too few nodes to make progress, waits [QUORUM_RETRY_DELAY] and discovers the nodes again
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InsufficientQuorum {
+    #[cfg_attr(feature = "serde", serde(skip))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
+/// Wait before discovering the nodes again, only with the `std` feature
+pub const QUORUM_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+impl InsufficientQuorum {
+    pub fn new(discovery: Discovery, replication: Replication) -> Self {
+        Self {
+            discovery,
+            replication,
+            #[cfg(feature = "std")]
+            clock: SharedClock::default(),
+        }
+    }
+
+    pub fn execute(self) -> DiscoverNodes {
+        #[cfg(feature = "std")]
+        self.clock.sleep(QUORUM_RETRY_DELAY);
+        DiscoverNodes::new(self.discovery).with_replication(self.replication)
+    }
+}
+
+/// Sync phase of the leader, broadcasts the events of its [Replication] until there are no more
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Leader {
-    _connections: Vec<NodeConnection>,
+    connections: Vec<NodeConnection>,
+    quorum: Quorum,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl Leader {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
+    pub fn new(
+        connections: Vec<NodeConnection>,
+        quorum: Quorum,
+        discovery: Discovery,
+        replication: Replication,
+    ) -> Self {
         Self {
-            _connections: connections,
+            connections,
+            quorum,
+            discovery,
+            replication,
+            #[cfg(feature = "std")]
+            clock: SharedClock::default(),
         }
     }
 
-    pub fn execute(self) {}
+    /// Renew the lease and broadcast every event, false when the quorum didn't acknowledge the
+    /// lease or an event and the leader steps down
+    ///
+    /// The event that wasn't acknowledged is kept by the [Replication], this node sends it again if
+    /// it leads after the next election. Another leader doesn't know about it
+    pub fn execute(&mut self) -> bool {
+        if !self.renew_lease() {
+            return false;
+        }
+
+        while let Some(event) = self.replication.next_event() {
+            let sequence = self.replication.sequence() + 1;
+            let acked = self.broadcast(sequence, &event);
+            if !self.quorum.is_met(acked.len()) {
+                self.replication.resend(event);
+                return false;
+            }
+
+            self.replication.acknowledged();
+        }
+
+        sync::finish(&mut self.connections);
+        true
+    }
+
+    #[cfg(feature = "std")]
+    fn renew_lease(&mut self) -> bool {
+        crate::lease::renew(
+            self.connections.iter_mut(),
+            self.quorum,
+            crate::lease::LEASE,
+            &self.clock,
+        )
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn renew_lease(&mut self) -> bool {
+        crate::renew_lease(&mut self.connections, self.quorum)
+    }
+
+    #[cfg(feature = "std")]
+    fn broadcast(&mut self, sequence: u64, event: &[u8]) -> Vec<IpAddr> {
+        sync::broadcast_with_clock(&mut self.connections, sequence, event, &self.clock)
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn broadcast(&mut self, sequence: u64, event: &[u8]) -> Vec<IpAddr> {
+        sync::broadcast(&mut self.connections, sequence, event)
+    }
 }
 
+/// Sync phase of a follower, applies the events of the leader until it has no more
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Follower {
-    _connections: Vec<NodeConnection>,
+    connections: Vec<NodeConnection>,
+    quorum: Quorum,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    discovery: Discovery,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    replication: Replication,
+    #[cfg(feature = "std")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    clock: SharedClock,
 }
 
 impl Follower {
-    pub fn new(connections: Vec<NodeConnection>) -> Self {
+    pub fn new(
+        connections: Vec<NodeConnection>,
+        quorum: Quorum,
+        discovery: Discovery,
+        replication: Replication,
+    ) -> Self {
         Self {
-            _connections: connections,
+            connections,
+            quorum,
+            discovery,
+            replication,
+            #[cfg(feature = "std")]
+            clock: SharedClock::default(),
         }
     }
 
-    pub fn execute(self) {}
+    /// Acknowledge the lease of the leader and apply its events, false when the leader is silent
+    /// for [sync::LEADER_TIMEOUT] and the follower goes back to consensus
+    ///
+    /// Without the `std` feature there is no leader to receive from
+    #[cfg(feature = "std")]
+    pub fn execute(&mut self) -> bool {
+        let Some(leader) =
+            crate::lease::acknowledge(&mut self.connections, crate::lease::LEASE, &self.clock)
+        else {
+            return false;
+        };
+        let Some(connection) = self
+            .connections
+            .iter_mut()
+            .find(|connection| connection.addr() == leader)
+        else {
+            return false;
+        };
+
+        loop {
+            let received = sync::receive(
+                core::slice::from_mut(connection),
+                sync::LEADER_TIMEOUT,
+                &self.clock,
+            );
+            match received {
+                Some(sync::Received::Event { sequence, event }) => {
+                    self.replication.apply(leader, sequence, event)
+                }
+                Some(sync::Received::End) => return true,
+                None => return false,
+            }
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn execute(&mut self) -> bool {
+        true
+    }
 }
@@ -0,0 +1,88 @@
+//! Leader lease, renewed while a quorum of the followers acknowledges the leader
+//!
+//! A leader that can't renew its lease may be partitioned from the cluster, so it steps down and
+//! goes back to consensus
+
+use std::{net::IpAddr, time::Duration};
+
+use crate::{
+    clock::{self, Clock},
+    NodeConnection, Quorum,
+};
+
+/// How long the leader waits for the acknowledgements, followers wait as long for a renewal
+pub const LEASE: Duration = Duration::from_secs(2);
+
+/// Time spent waiting on each connection while polling all of them
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Sent by the leader to every follower
+const RENEW: &[u8] = b"lease/renew";
+/// Sent back by a follower that accepts the leader
+const ACK: &[u8] = b"lease/ack";
+
+/// Ask the followers to renew the lease, true when `quorum` is met within `lease`, measured by
+/// `clock`
+///
+/// Followers that can't be reached don't acknowledge the lease
+pub fn renew<'a, I>(connections: I, quorum: Quorum, lease: Duration, clock: &dyn Clock) -> bool
+where
+    I: IntoIterator<Item = &'a mut NodeConnection>,
+{
+    let mut connections: Vec<_> = connections
+        .into_iter()
+        .filter_map(|connection| connection.send(RENEW).is_ok().then_some(connection))
+        .collect();
+
+    let mut acks = 0;
+    let deadline = clock.now() + lease;
+    while !quorum.is_met(acks) && !connections.is_empty() && clock.now() < deadline {
+        let started = clock.now();
+        connections.retain_mut(|connection| match connection.try_recv(POLL_INTERVAL) {
+            Ok(Some(message)) if message == ACK => {
+                acks += 1;
+                false
+            }
+            Ok(_) => true,
+            Err(_) => false,
+        });
+        clock::count_wait(clock, started, POLL_INTERVAL);
+    }
+
+    quorum.is_met(acks)
+}
+
+/// Wait up to `lease`, measured by `clock`, for a renewal and acknowledge it, returning the
+/// address of the leader
+///
+/// `None` if no renewal arrived, the leader is probably gone
+pub fn acknowledge(
+    connections: &mut [NodeConnection],
+    lease: Duration,
+    clock: &dyn Clock,
+) -> Option<IpAddr> {
+    let mut alive = vec![true; connections.len()];
+    let deadline = clock.now() + lease;
+    while alive.iter().any(|alive| *alive) && clock.now() < deadline {
+        let started = clock.now();
+        for (connection, alive) in connections.iter_mut().zip(alive.iter_mut()) {
+            if !*alive {
+                continue;
+            }
+
+            match connection.try_recv(POLL_INTERVAL) {
+                Ok(Some(message)) if message == RENEW => {
+                    if connection.send(ACK).is_ok() {
+                        return Some(connection.addr());
+                    }
+                    *alive = false;
+                }
+                Ok(_) => {}
+                Err(_) => *alive = false,
+            }
+        }
+        clock::count_wait(clock, started, POLL_INTERVAL);
+    }
+
+    None
+}
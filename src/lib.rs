@@ -1,31 +1,543 @@
-#![feature(async_fn_in_trait)]
+//! The internal enum, dyn trait and compose patterns only need `alloc`, everything that
+//! depends on threads, channels or clocks requires the `std` feature
+#![cfg_attr(not(feature = "std"), no_std)]
 
-use std::net::IpAddr;
+extern crate alloc;
 
+use alloc::{boxed::Box, vec::Vec};
+#[cfg(feature = "std")]
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::{error::Error, net::IpAddr, time::Duration};
+#[cfg(feature = "std")]
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Instant,
+};
+
+#[cfg(feature = "std")]
+use crate::clock::Clock;
+use crate::error::MachineError;
+
+#[cfg(feature = "anyhow")]
+pub mod anyhow_support;
+#[cfg(feature = "bench-support")]
+pub mod bench_support;
+#[cfg(feature = "std")]
+pub mod chaos;
+#[cfg(feature = "serde")]
+pub mod checkpoint;
+#[cfg(feature = "std")]
+pub mod circuit_breaker;
+#[cfg(feature = "std")]
+pub mod clock;
 pub mod compose_trait;
+#[cfg(feature = "std")]
+pub mod connect;
+#[cfg(feature = "std")]
+pub mod control;
+pub mod discovery;
 pub mod dyn_trait;
+#[cfg(feature = "std")]
+pub mod election;
+pub mod enum_dispatch;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod event_dependent;
+#[cfg(feature = "std")]
+pub mod event_source;
+#[cfg(feature = "std")]
 pub mod external_enum;
+pub mod fn_table;
+#[cfg(feature = "gossip")]
+pub mod gossip;
+#[cfg(feature = "std")]
+pub mod handle;
+#[cfg(feature = "heapless")]
+pub mod heapless_enum;
+#[cfg(feature = "std")]
+pub mod heartbeat;
+#[cfg(feature = "std")]
+pub mod ingress;
 pub mod internal_enum;
+#[cfg(feature = "std")]
+pub mod lease;
+pub mod machine;
+pub mod model_check;
+#[cfg(feature = "std")]
+pub mod output;
+#[cfg(feature = "std")]
+pub mod pool;
+#[cfg(feature = "std")]
+mod primitives;
+#[cfg(feature = "tokio")]
+pub mod publish;
+#[cfg(feature = "quic")]
+pub mod quic;
+#[cfg(feature = "serde")]
+pub mod record;
+#[cfg(feature = "std")]
+pub mod retry;
+pub mod shutdown;
+#[cfg(feature = "std")]
+pub mod sim;
+pub mod small_state;
+#[cfg(feature = "serde")]
+pub mod state_store;
+#[cfg(feature = "std")]
+pub mod stats;
+#[cfg(feature = "futures")]
+pub mod stream;
+pub mod sync;
+pub mod table_driven;
+#[cfg(feature = "std")]
+pub mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod transition_log;
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "std")]
+pub mod unwind;
+
+/// Name of the current state, used to observe a running machine
+pub trait StateName {
+    fn state_name(&self) -> &'static str;
+}
+
+/// Simulated latency of the mock I/O functions, in nanoseconds
+#[cfg(feature = "std")]
+static SIMULATED_LATENCY: AtomicU64 = AtomicU64::new(0);
+
+/// Make [discovery::StaticDiscovery] and [connect_to_nodes] spin for `latency` on every call
+///
+/// Without latency the benchmarks only measure dispatch overhead
+#[cfg(feature = "std")]
+pub fn set_simulated_latency(latency: Duration) {
+    SIMULATED_LATENCY.store(latency.as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// Spin for the configured latency, sleeping is not precise at microsecond scale
+#[cfg(feature = "std")]
+pub(crate) fn simulate_latency() {
+    let latency = SIMULATED_LATENCY.load(Ordering::Relaxed);
+    if latency == 0 {
+        return;
+    }
+
+    let latency = Duration::from_nanos(latency);
+    let start = Instant::now();
+    while start.elapsed() < latency {
+        std::hint::spin_loop();
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn simulate_latency() {}
+
+/// How [connect_to_nodes] deals with nodes that can't be reached
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectPolicy {
+    /// Attempts after the first failed connect to the same node
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled on every following retry. Retries don't wait without
+    /// the `std` feature
+    pub backoff: Duration,
+    /// Fraction of the nodes, between 0 and 1, that must be connected for the state to proceed
+    pub min_successful: f64,
+}
+
+impl Default for ConnectPolicy {
+    /// Three retries starting at 10ms, half of the nodes must be connected
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            backoff: Duration::from_millis(10),
+            min_successful: 0.5,
+        }
+    }
+}
+
+impl ConnectPolicy {
+    /// Connect to `node`, retrying with backoff up to `max_retries` times
+    #[cfg(feature = "std")]
+    pub fn connect(&self, node: IpAddr) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        self.connect_with_clock(node, &clock::SystemClock)
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn connect(&self, node: IpAddr) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        self.connect_with(node, |_| {})
+    }
+
+    /// Same as [ConnectPolicy::connect], waiting the backoff with `clock`
+    #[cfg(feature = "std")]
+    pub fn connect_with_clock(
+        &self,
+        node: IpAddr,
+        clock: &dyn clock::Clock,
+    ) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        self.connect_with(node, |backoff| clock.sleep(backoff))
+    }
 
-pub fn get_service_nodes() -> Vec<IpAddr> {
-    Vec::new()
+    fn connect_with(
+        &self,
+        node: IpAddr,
+        sleep: impl Fn(Duration),
+    ) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        let mut backoff = self.backoff;
+        let mut attempt = 0;
+        loop {
+            match NodeConnection::connect(node) {
+                Ok(connection) => return Ok(connection),
+                Err(err) if attempt == self.max_retries => return Err(err),
+                Err(_) => {
+                    sleep(backoff);
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+            }
+        }
+    }
 }
 
-pub fn connect_to_nodes(nodes: &[IpAddr]) -> Vec<NodeConnection> {
+/// Connect to every node following `policy`, nodes that can't be reached are left out
+///
+/// Fails with [MachineError::NotEnoughConnections] if less than `policy.min_successful` of the
+/// nodes are connected
+pub fn connect_to_nodes(
+    nodes: &[IpAddr],
+    policy: &ConnectPolicy,
+) -> Result<Vec<NodeConnection>, MachineError> {
+    simulate_latency();
     let mut connections = Vec::with_capacity(nodes.len());
 
     for node in nodes {
-        connections.push(NodeConnection::connect(*node));
+        if let Ok(connection) = policy.connect(*node) {
+            connections.push(connection);
+        }
+    }
+
+    if (connections.len() as f64) < policy.min_successful * nodes.len() as f64 {
+        return Err(error::NotEnoughConnectionsError {
+            connected: connections.len(),
+            nodes: nodes.len(),
+        }
+        .into());
     }
 
-    connections
+    Ok(connections)
+}
+
+/// Decide if this node leads the cluster, with a [election::bully] election over `connections`
+///
+/// A node without connections leads itself. Without the `std` feature there is nothing to
+/// exchange messages with, so the node always leads
+///
+/// Fails with [MachineError::ConsensusTimeout] when no coordinator is elected in time
+#[cfg(feature = "std")]
+pub fn elect_leader(connections: &mut [NodeConnection]) -> Result<bool, MachineError> {
+    elect_leader_with_clock(connections, &clock::SystemClock)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn elect_leader(_connections: &mut [NodeConnection]) -> Result<bool, MachineError> {
+    Ok(true)
+}
+
+/// Same as [elect_leader], the election timeouts are measured by `clock`
+#[cfg(feature = "std")]
+pub fn elect_leader_with_clock(
+    connections: &mut [NodeConnection],
+    clock: &dyn clock::Clock,
+) -> Result<bool, MachineError> {
+    let Some(connection) = connections.first_mut() else {
+        return Ok(true);
+    };
+
+    let own = connection
+        .local_addr()
+        .map_err(|err| MachineError::ConsensusFailed(Box::new(err)))?
+        .ip();
+    election::bully(own, connections, election::TIMEOUT, clock).map_err(|err| match err.kind() {
+        io::ErrorKind::TimedOut => MachineError::ConsensusTimeout,
+        _ => MachineError::ConsensusFailed(Box::new(err)),
+    })
+}
+
+/// Renew the lease of the leader with a [lease::renew] over `connections`
+///
+/// Without the `std` feature the lease never expires
+#[cfg(feature = "std")]
+pub fn renew_lease(connections: &mut [NodeConnection], quorum: Quorum) -> bool {
+    lease::renew(
+        connections.iter_mut(),
+        quorum,
+        lease::LEASE,
+        &clock::SystemClock,
+    )
 }
 
+#[cfg(not(feature = "std"))]
+pub fn renew_lease(_connections: &mut [NodeConnection], _quorum: Quorum) -> bool {
+    true
+}
+
+/// Nodes, this one included, needed for the cluster to make progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quorum {
+    required: usize,
+    majority: bool,
+}
+
+impl Quorum {
+    /// More than half of the discovered nodes plus this one
+    pub fn majority(discovered: usize) -> Self {
+        let cluster = discovered + 1;
+        Self {
+            required: cluster / 2 + 1,
+            majority: true,
+        }
+    }
+
+    pub fn at_least(required: usize) -> Self {
+        Self {
+            required,
+            majority: false,
+        }
+    }
+
+    /// Follow a membership change, a majority is computed again over `discovered` nodes while a
+    /// fixed quorum stays the same
+    pub fn resize(&mut self, discovered: usize) {
+        if self.majority {
+            *self = Self::majority(discovered);
+        }
+    }
+
+    /// `connected` doesn't include this node
+    pub fn is_met(&self, connected: usize) -> bool {
+        connected + 1 >= self.required
+    }
+}
+
+impl Default for Quorum {
+    /// This node alone
+    fn default() -> Self {
+        Self::at_least(1)
+    }
+}
+
+/// Role of this node once consensus is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Role {
+    Leader,
+    Follower,
+}
+
+/// Connection to another node of the cluster
+///
+/// With the `std` feature this is a TCP connection to [transport::PORT] of the node, messages are
+/// framed by [transport::write_frame]. Without it the connection is a placeholder that always
+/// succeeds
+///
+/// With the `tls` feature, `tls::TlsConnector` and `tls::TlsAcceptor` establish encrypted
+/// connections, with the `quic` feature `quic::QuicEndpoint` connects over QUIC. Any other
+/// [transport::Transport] can be used with [NodeConnection::new]
 pub struct NodeConnection {
-    _addr: IpAddr,
+    addr: IpAddr,
+    /// `None` for a connection restored from a checkpoint, until it is first used
+    #[cfg(feature = "std")]
+    transport: Option<Box<dyn transport::Transport>>,
+    /// Partial frame left by a read that timed out
+    #[cfg(feature = "std")]
+    frames: transport::FrameReader,
+    #[cfg(feature = "std")]
+    last_seen: Instant,
+    /// Stamps `last_seen`
+    #[cfg(feature = "std")]
+    clock: clock::SharedClock,
 }
+
 impl NodeConnection {
-    pub fn connect(addr: IpAddr) -> Self {
-        Self { _addr: addr }
+    #[cfg(feature = "std")]
+    pub fn connect(addr: IpAddr) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self::connect_timeout(
+            SocketAddr::new(addr, transport::PORT),
+            transport::CONNECT_TIMEOUT,
+        )?)
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn connect(addr: IpAddr) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Ok(Self { addr })
+    }
+
+    /// Connect to a node listening on any port
+    #[cfg(feature = "std")]
+    pub fn connect_timeout(addr: SocketAddr, timeout: Duration) -> io::Result<Self> {
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        Self::from_stream(stream)
+    }
+
+    /// Wait for the next node connecting to `listener`
+    #[cfg(feature = "std")]
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        Self::from_stream(stream)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_stream(stream: TcpStream) -> io::Result<Self> {
+        // messages are small and latency sensitive
+        stream.set_nodelay(true)?;
+        Self::new(stream)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn new<T: transport::Transport + 'static>(transport: T) -> io::Result<Self> {
+        Ok(Self {
+            addr: transport.peer_addr()?.ip(),
+            transport: Some(Box::new(transport)),
+            frames: transport::FrameReader::new(),
+            last_seen: Instant::now(),
+            clock: clock::SharedClock::default(),
+        })
+    }
+
+    /// Connection to `addr` that connects with [NodeConnection::connect] when it is first used
+    #[cfg(feature = "std")]
+    pub fn lazy(addr: IpAddr) -> Self {
+        Self {
+            addr,
+            transport: None,
+            frames: transport::FrameReader::new(),
+            last_seen: Instant::now(),
+            clock: clock::SharedClock::default(),
+        }
+    }
+
+    /// Clock stamping [NodeConnection::last_seen], use the one of the [heartbeat::Heartbeat]
+    /// checking the connection
+    #[cfg(feature = "std")]
+    pub fn with_clock<C: clock::Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = clock::SharedClock::new(clock);
+        self.last_seen = self.clock.now();
+        self
+    }
+
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The transport, connecting first if the connection is [lazy](NodeConnection::lazy)
+    #[cfg(feature = "std")]
+    fn transport(&mut self) -> io::Result<&mut Box<dyn transport::Transport>> {
+        if self.transport.is_none() {
+            let connection = Self::connect_timeout(
+                SocketAddr::new(self.addr, transport::PORT),
+                transport::CONNECT_TIMEOUT,
+            )?;
+            let clock = self.clock.clone();
+            *self = connection;
+            self.last_seen = clock.now();
+            self.clock = clock;
+        }
+
+        Ok(self.transport.as_mut().expect("connected above"))
+    }
+
+    /// Address of this node on the connection
+    #[cfg(feature = "std")]
+    pub fn local_addr(&mut self) -> io::Result<SocketAddr> {
+        self.transport()?.local_addr()
+    }
+
+    #[cfg(feature = "std")]
+    pub fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        transport::write_frame(self.transport()?, transport::FrameKind::Message, message)
+    }
+
+    /// Wait for the next message, up to the read timeout if one is set
+    ///
+    /// Pings of the peer are answered while waiting. A frame cut by the timeout is resumed by the
+    /// next call
+    #[cfg(feature = "std")]
+    pub fn recv(&mut self) -> io::Result<Vec<u8>> {
+        loop {
+            self.transport()?;
+            let Self {
+                transport, frames, ..
+            } = self;
+            let transport = transport.as_mut().expect("connected above");
+            let (kind, message) = frames.read_frame(transport)?;
+            self.last_seen = self.clock.now();
+
+            match kind {
+                transport::FrameKind::Message => return Ok(message),
+                transport::FrameKind::Ping => {
+                    transport::write_frame(self.transport()?, transport::FrameKind::Pong, &[])?
+                }
+                transport::FrameKind::Pong => {}
+            }
+        }
+    }
+
+    /// Same as [NodeConnection::recv], but `None` if nothing arrived within `timeout`
+    #[cfg(feature = "std")]
+    pub fn try_recv(&mut self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        self.set_read_timeout(Some(timeout))?;
+        match self.recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Ask the peer for a sign of life, the pong is processed by [NodeConnection::recv]
+    #[cfg(feature = "std")]
+    pub fn ping(&mut self) -> io::Result<()> {
+        transport::write_frame(self.transport()?, transport::FrameKind::Ping, &[])
+    }
+
+    /// Last time a frame was received from the peer, or when the connection was established, on
+    /// the clock of the connection
+    #[cfg(feature = "std")]
+    pub fn last_seen(&self) -> Instant {
+        self.last_seen
+    }
+
+    /// `None` blocks [NodeConnection::recv] until a message arrives
+    #[cfg(feature = "std")]
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.transport()?.set_read_timeout(timeout)
+    }
+}
+
+/// Connections are checkpointed as the node address, a restored connection is
+/// [lazy](NodeConnection::lazy), so restoring doesn't fail when a node is down
+#[cfg(feature = "serde")]
+impl serde::Serialize for NodeConnection {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.addr.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for NodeConnection {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IpAddr::deserialize(deserializer).map(NodeConnection::lazy)
     }
 }
@@ -0,0 +1,85 @@
+use alloc::boxed::Box;
+use core::error::Error;
+
+use crate::{
+    compose_trait, dyn_trait,
+    internal_enum::{internally_driven_executor, InternallyDrivenTransition},
+};
+#[cfg(feature = "std")]
+use crate::{
+    event_source::EventSource,
+    external_enum::{externally_driven_executor, ExternallyDrivenTransition},
+};
+
+/// A state machine ready to run, whatever pattern it is implemented with.
+///
+/// Call sites that only need to run a machine can depend on this trait and swap the pattern
+/// without changes
+pub trait Machine {
+    type Output;
+
+    fn run(self) -> Result<Self::Output, Box<dyn Error>>;
+}
+
+/// Internally driven machine, see [crate::internal_enum]
+pub struct InternallyDriven<T>(pub T);
+
+impl<T> Machine for InternallyDriven<T>
+where
+    T: InternallyDrivenTransition,
+{
+    type Output = T;
+
+    fn run(self) -> Result<Self::Output, Box<dyn Error>> {
+        internally_driven_executor(self.0).map_err(Into::into)
+    }
+}
+
+/// Externally driven machine and the source of its events, see [crate::external_enum]
+#[cfg(feature = "std")]
+pub struct ExternallyDriven<T, S> {
+    pub state: T,
+    pub events: S,
+}
+
+#[cfg(feature = "std")]
+impl<T, S> ExternallyDriven<T, S> {
+    pub fn new(state: T, events: S) -> Self {
+        Self { state, events }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, S> Machine for ExternallyDriven<T, S>
+where
+    T: ExternallyDrivenTransition,
+    S: EventSource<T::EventType>,
+{
+    type Output = T;
+
+    fn run(self) -> Result<Self::Output, Box<dyn Error>> {
+        externally_driven_executor(self.state, self.events).map_err(Into::into)
+    }
+}
+
+impl Machine for Box<dyn dyn_trait::State> {
+    type Output = ();
+
+    fn run(self) -> Result<Self::Output, Box<dyn Error>> {
+        dyn_trait::executor(self).map_err(Into::into)
+    }
+}
+
+/// Composed chain of states, see [crate::compose_trait]
+pub struct Composed<T>(pub T);
+
+impl<T> Machine for Composed<T>
+where
+    T: compose_trait::State,
+{
+    type Output = T::Output;
+
+    fn run(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.0.execute()
+    }
+}
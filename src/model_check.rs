@@ -0,0 +1,229 @@
+//! Exhaustive exploration of a machine defined as a transition table, see
+//! [crate::table_driven::Transition]
+//!
+//! Every event of the alphabet is tried in every reachable state, so the report covers all the
+//! paths the machine can take, not only the ones taken by a run
+
+use alloc::{collections::VecDeque, vec::Vec};
+use core::fmt::Debug;
+
+use crate::table_driven::Transition;
+
+/// Result of [ModelChecker::check], the machine is sound when [Report::is_ok]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Report<S> {
+    /// States reachable from the initial state, in the order they were found
+    pub reachable: Vec<S>,
+    /// States that can't be reached from the initial state
+    pub unreachable: Vec<S>,
+    /// Reachable states that are not terminal and have no transition for any event of the
+    /// alphabet, the machine gets stuck in them
+    pub dead: Vec<S>,
+    /// Reachable states from which no terminal state can be reached, dead states included
+    pub no_terminal_path: Vec<S>,
+}
+
+impl<S> Report<S> {
+    pub fn is_ok(&self) -> bool {
+        self.unreachable.is_empty() && self.dead.is_empty() && self.no_terminal_path.is_empty()
+    }
+}
+
+/// Explores the machine defined by `table` from `initial_state`, with the events of `events`
+pub struct ModelChecker<'a, S, E> {
+    table: &'a [Transition<S, E>],
+    initial_state: S,
+    events: &'a [E],
+    states: Option<&'a [S]>,
+    terminal_states: Option<&'a [S]>,
+}
+
+impl<'a, S, E> ModelChecker<'a, S, E>
+where
+    S: Copy + PartialEq,
+    E: Copy + PartialEq,
+{
+    pub fn new(table: &'a [Transition<S, E>], initial_state: S, events: &'a [E]) -> Self {
+        Self {
+            table,
+            initial_state,
+            events,
+            states: None,
+            terminal_states: None,
+        }
+    }
+
+    /// Every state of the machine, by default the states found in the table. Needed to report the
+    /// states that don't appear in any transition
+    pub fn with_states(mut self, states: &'a [S]) -> Self {
+        self.states = Some(states);
+        self
+    }
+
+    /// States where the machine may stop, by default the states without transitions
+    pub fn with_terminal_states(mut self, terminal_states: &'a [S]) -> Self {
+        self.terminal_states = Some(terminal_states);
+        self
+    }
+
+    pub fn check(&self) -> Report<S> {
+        let states = self.states();
+        let reachable = self.reachable();
+        let terminal: Vec<S> = match self.terminal_states {
+            Some(terminal_states) => terminal_states.to_vec(),
+            None => states
+                .iter()
+                .copied()
+                .filter(|state| !self.table.iter().any(|(from, _, _)| from == state))
+                .collect(),
+        };
+
+        let unreachable = states
+            .iter()
+            .copied()
+            .filter(|state| !reachable.contains(state))
+            .collect();
+
+        let dead = reachable
+            .iter()
+            .copied()
+            .filter(|state| !terminal.contains(state) && self.successors(*state).next().is_none())
+            .collect();
+
+        // walk back from the terminal states until no more states lead to them
+        let mut leads_to_terminal: Vec<S> = reachable
+            .iter()
+            .copied()
+            .filter(|state| terminal.contains(state))
+            .collect();
+        loop {
+            let before = leads_to_terminal.len();
+            for state in reachable.iter() {
+                if !leads_to_terminal.contains(state)
+                    && self
+                        .successors(*state)
+                        .any(|next| leads_to_terminal.contains(&next))
+                {
+                    leads_to_terminal.push(*state);
+                }
+            }
+
+            if leads_to_terminal.len() == before {
+                break;
+            }
+        }
+
+        let no_terminal_path = reachable
+            .iter()
+            .copied()
+            .filter(|state| !leads_to_terminal.contains(state))
+            .collect();
+
+        Report {
+            reachable,
+            unreachable,
+            dead,
+            no_terminal_path,
+        }
+    }
+
+    /// States given by [ModelChecker::with_states], or the ones found in the table
+    fn states(&self) -> Vec<S> {
+        if let Some(states) = self.states {
+            return states.to_vec();
+        }
+
+        let mut states = Vec::from([self.initial_state]);
+        for (from, _, to) in self.table {
+            for state in [from, to] {
+                if !states.contains(state) {
+                    states.push(*state);
+                }
+            }
+        }
+
+        states
+    }
+
+    fn reachable(&self) -> Vec<S> {
+        let mut reachable = Vec::from([self.initial_state]);
+        let mut pending = VecDeque::from([self.initial_state]);
+        while let Some(state) = pending.pop_front() {
+            for next in self.successors(state) {
+                if !reachable.contains(&next) {
+                    reachable.push(next);
+                    pending.push_back(next);
+                }
+            }
+        }
+
+        reachable
+    }
+
+    /// States reached from `state` with an event of the alphabet
+    fn successors(&self, state: S) -> impl Iterator<Item = S> + '_ {
+        self.table
+            .iter()
+            .filter(move |(from, on, _)| *from == state && self.events.contains(on))
+            .map(|(_, _, to)| *to)
+    }
+}
+
+/// Result of [analyze]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Analysis<S, E> {
+    /// States of the table that can't be reached from the initial state
+    pub unreachable: Vec<S>,
+    /// Transitions that never fire, an earlier row handles the same event in the same state
+    pub shadowed: Vec<Transition<S, E>>,
+    /// Reachable states from which no state without transitions can be reached
+    pub no_terminal_path: Vec<S>,
+}
+
+impl<S, E> Analysis<S, E> {
+    pub fn is_ok(&self) -> bool {
+        self.unreachable.is_empty() && self.shadowed.is_empty() && self.no_terminal_path.is_empty()
+    }
+}
+
+impl<S: Debug, E: Debug> Analysis<S, E> {
+    /// Panic with the problems found, meant to be called from a unit test of the table
+    #[track_caller]
+    pub fn assert_ok(&self) {
+        assert!(self.is_ok(), "transition table has problems: {self:#?}");
+    }
+}
+
+/// Analyze `table` statically, every event found in the table can happen in any state
+///
+/// [table_executor](crate::table_driven::table_executor) picks the first row matching the state
+/// and the event, so later rows with the same pair are reported as shadowed
+pub fn analyze<S, E>(table: &[Transition<S, E>], initial_state: S) -> Analysis<S, E>
+where
+    S: Copy + PartialEq,
+    E: Copy + PartialEq,
+{
+    let mut events = Vec::new();
+    let mut shadowed = Vec::new();
+    for (index, (from, on, to)) in table.iter().enumerate() {
+        if !events.contains(on) {
+            events.push(*on);
+        }
+
+        if table[..index]
+            .iter()
+            .any(|(earlier_from, earlier_on, _)| earlier_from == from && earlier_on == on)
+        {
+            shadowed.push((*from, *on, *to));
+        }
+    }
+
+    let report = ModelChecker::new(table, initial_state, &events).check();
+    Analysis {
+        unreachable: report.unreachable,
+        shadowed,
+        no_terminal_path: report.no_terminal_path,
+    }
+}
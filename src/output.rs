@@ -0,0 +1,361 @@
+//! Machines that return the effects of their states as outputs, instead of performing them
+//!
+//! A [Mealy] wraps the machine and forwards the outputs of every step to an [OutputSink], so any
+//! of the enum executors can run it. Tests can collect the outputs in a `Vec` and check them
+//! without any I/O
+//!
+//! States implementing [MooreOutput] instead have an output that only depends on the state, emitted
+//! by [internally_driven_executor_with_output] and [externally_driven_executor_with_output] every
+//! time the state is entered. A state is entered when the [StateName] of the machine changes
+
+use std::{
+    error::Error,
+    sync::mpsc::{Sender, SyncSender},
+    time::Duration,
+};
+
+use crate::{
+    error::MachineError,
+    event_source::EventSource,
+    external_enum::{process_event, EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    StateName,
+};
+
+/// Receives the outputs of a machine, in the order they were produced
+pub trait OutputSink<O> {
+    fn send(&mut self, output: O) -> Result<(), Box<dyn Error>>;
+}
+
+impl<O> OutputSink<O> for Vec<O> {
+    fn send(&mut self, output: O) -> Result<(), Box<dyn Error>> {
+        self.push(output);
+        Ok(())
+    }
+}
+
+/// Fails once the receiver is dropped
+impl<O: Send + 'static> OutputSink<O> for Sender<O> {
+    fn send(&mut self, output: O) -> Result<(), Box<dyn Error>> {
+        Sender::send(self, output)?;
+        Ok(())
+    }
+}
+
+/// Blocks while the channel is full, fails once the receiver is dropped
+impl<O: Send + 'static> OutputSink<O> for SyncSender<O> {
+    fn send(&mut self, output: O) -> Result<(), Box<dyn Error>> {
+        SyncSender::send(self, output)?;
+        Ok(())
+    }
+}
+
+/// Next state of an [InternallyDrivenMealy] with the outputs of the step
+pub type Step<T, O> = (T, Vec<O>);
+
+/// Same as [InternallyDrivenTransition], but every step also returns its outputs
+pub trait InternallyDrivenMealy: Sized {
+    type Output;
+
+    fn execute(self) -> Result<Step<Self, Self::Output>, Box<dyn Error>>;
+    fn is_terminal_state(&self) -> bool;
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Same as [ExternallyDrivenTransition], but executing an event also returns its outputs
+pub trait ExternallyDrivenMealy {
+    type EventType;
+    type Output;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<Vec<Self::Output>, Box<dyn Error>>;
+    fn is_terminal_state(&self) -> bool;
+    fn transition(self) -> Self;
+
+    /// Fallible form of [ExternallyDrivenMealy::transition], see
+    /// [ExternallyDrivenTransition::try_transition]
+    fn try_transition(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        Ok(self.transition())
+    }
+
+    fn accepts_batch(&self) -> bool {
+        false
+    }
+
+    /// Execute a batch of events, returning the outputs of all of them
+    ///
+    /// By default the events are executed one by one
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<Vec<Self::Output>, Box<dyn Error>> {
+        let mut outputs = Vec::new();
+        for input in inputs {
+            outputs.extend(self.execute(input, context)?);
+        }
+        Ok(outputs)
+    }
+
+    fn can_handle(&self, _input: &Self::EventType) -> bool {
+        true
+    }
+
+    fn should_defer(&self, _input: &Self::EventType) -> bool {
+        false
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        None
+    }
+
+    /// Background work between events, its outputs are forwarded like the ones of an event
+    fn on_idle(
+        &mut self,
+        _context: &mut EventContext<Self::EventType>,
+    ) -> Result<Vec<Self::Output>, Box<dyn Error>> {
+        Ok(Vec::new())
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Wraps a Mealy machine, forwarding its outputs to `sink`
+///
+/// A failing sink fails the machine
+pub struct Mealy<T, S> {
+    machine: T,
+    sink: S,
+}
+
+impl<T, S> Mealy<T, S> {
+    pub fn new(machine: T, sink: S) -> Self {
+        Self { machine, sink }
+    }
+
+    /// Unwrap the machine and the sink, a `Vec` sink holds the outputs produced
+    pub fn into_inner(self) -> (T, S) {
+        (self.machine, self.sink)
+    }
+
+    fn forward<O>(&mut self, outputs: Vec<O>) -> Result<(), Box<dyn Error>>
+    where
+        S: OutputSink<O>,
+    {
+        for output in outputs {
+            self.sink.send(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, S> StateName for Mealy<T, S>
+where
+    T: StateName,
+{
+    fn state_name(&self) -> &'static str {
+        self.machine.state_name()
+    }
+}
+
+impl<T, S> InternallyDrivenTransition for Mealy<T, S>
+where
+    T: InternallyDrivenMealy,
+    S: OutputSink<T::Output>,
+{
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let (machine, outputs) = self.machine.execute()?;
+        let mut mealy = Self {
+            machine,
+            sink: self.sink,
+        };
+
+        mealy.forward(outputs)?;
+        Ok(mealy)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T, S> ExternallyDrivenTransition for Mealy<T, S>
+where
+    T: ExternallyDrivenMealy,
+    S: OutputSink<T::Output>,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        let outputs = self.machine.execute(input, context)?;
+        self.forward(outputs)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(self) -> Self {
+        Self {
+            machine: self.machine.transition(),
+            sink: self.sink,
+        }
+    }
+
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            machine: self.machine.try_transition()?,
+            sink: self.sink,
+        })
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        let outputs = self.machine.execute_batch(inputs, context)?;
+        self.forward(outputs)
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        let outputs = self.machine.on_idle(context)?;
+        self.forward(outputs)
+    }
+}
+
+/// Output of the current state, like a status LED or a UI label
+pub trait MooreOutput {
+    type Output;
+
+    /// `None` when the state has nothing to emit
+    fn output(&self) -> Option<Self::Output> {
+        None
+    }
+}
+
+/// Same as [crate::internal_enum::internally_driven_executor], but the output of every state
+/// entered, the initial one included, is sent to `sink`
+///
+/// A step that stays in the same state emits nothing
+pub fn internally_driven_executor_with_output<T, S>(
+    initial_state: T,
+    mut sink: S,
+) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition + MooreOutput + StateName,
+    S: OutputSink<T::Output>,
+{
+    let mut current_state = initial_state;
+    emit(&current_state, &mut sink)?;
+
+    while !current_state.is_terminal_state() {
+        let previous = current_state.state_name();
+        current_state = current_state.execute()?;
+        if current_state.state_name() != previous {
+            emit(&current_state, &mut sink)?;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [crate::external_enum::externally_driven_executor], but the output of every state
+/// entered, the initial one included, is sent to `sink`
+///
+/// Events handled without leaving the state emit nothing
+pub fn externally_driven_executor_with_output<T, S, O>(
+    initial_state: T,
+    mut events: S,
+    mut sink: O,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition + MooreOutput + StateName,
+    S: EventSource<T::EventType>,
+    O: OutputSink<<T as MooreOutput>::Output>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+    emit(&current_state, &mut sink)?;
+
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        let previous = current_state.state_name();
+        current_state = process_event(current_state, input, &mut context)?;
+        if current_state.state_name() != previous {
+            emit(&current_state, &mut sink)?;
+        }
+
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+fn emit<T, S>(state: &T, sink: &mut S) -> Result<(), Box<dyn Error>>
+where
+    T: MooreOutput,
+    S: OutputSink<T::Output>,
+{
+    match state.output() {
+        Some(output) => sink.send(output),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io,
+    net::IpAddr,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{error::CapacityExceededError, NodeConnection};
+
+/// Connections to the peers of the node, keyed by address
+///
+/// Broken connections are connected again in the background with the [Connector] of the pool,
+/// every `reconnect_interval`, until they are removed from the pool. The reconnection thread is
+/// only started once a connection breaks
+pub struct ConnectionPool {
+    state: Arc<Mutex<PoolState>>,
+    connector: Connector,
+    reconnect_interval: Duration,
+    stop: Arc<AtomicBool>,
+    handle: Mutex<Option<JoinHandle<()>>>,
+}
+
+/// Connects the pool to a peer, so a broken TLS or QUIC connection is replaced by another one
+/// instead of plain TCP, like `NodeConnection::connect` does
+pub type Connector =
+    Arc<dyn Fn(IpAddr) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> + Send + Sync>;
+
+struct PoolState {
+    max_connections: usize,
+    idle: HashMap<IpAddr, NodeConnection>,
+    checked_out: Vec<IpAddr>,
+    disconnected: Vec<IpAddr>,
+}
+
+impl PoolState {
+    fn len(&self) -> usize {
+        self.idle.len() + self.checked_out.len()
+    }
+}
+
+impl ConnectionPool {
+    pub fn new<C>(max_connections: usize, reconnect_interval: Duration, connector: C) -> Self
+    where
+        C: Fn(IpAddr) -> Result<NodeConnection, Box<dyn Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let state = Arc::new(Mutex::new(PoolState {
+            max_connections,
+            idle: HashMap::new(),
+            checked_out: Vec::new(),
+            disconnected: Vec::new(),
+        }));
+
+        Self {
+            state,
+            connector: Arc::new(connector),
+            reconnect_interval,
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: Mutex::new(None),
+        }
+    }
+
+    /// Pool without a connection limit, reconnecting every second with `connector`
+    pub fn from_connections<C>(connections: Vec<NodeConnection>, connector: C) -> Self
+    where
+        C: Fn(IpAddr) -> Result<NodeConnection, Box<dyn Error + Send + Sync>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        let pool = Self::new(usize::MAX, Duration::from_secs(1), connector);
+        for connection in connections {
+            // the pool has no limit
+            let _ = pool.insert(connection);
+        }
+
+        pool
+    }
+
+    /// Add a connection, replacing the connection to the same address if there is one
+    pub fn insert(&self, connection: NodeConnection) -> Result<(), CapacityExceededError> {
+        let mut state = self.state.lock().unwrap();
+        let addr = connection.addr();
+        if !state.idle.contains_key(&addr) && state.len() >= state.max_connections {
+            return Err(CapacityExceededError {
+                capacity: state.max_connections,
+            });
+        }
+
+        state
+            .disconnected
+            .retain(|disconnected| *disconnected != addr);
+        state.idle.insert(addr, connection);
+        Ok(())
+    }
+
+    /// Forget about `addr`, it won't be reconnected anymore
+    ///
+    /// A checked out connection is dropped when it is returned
+    pub fn remove(&self, addr: IpAddr) -> Option<NodeConnection> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .disconnected
+            .retain(|disconnected| *disconnected != addr);
+        state.checked_out.retain(|checked_out| *checked_out != addr);
+        state.idle.remove(&addr)
+    }
+
+    /// Take the connection to `addr` out of the pool, it is returned when the guard is dropped
+    ///
+    /// `None` if there is no connection to `addr` or it is already checked out
+    pub fn checkout(&self, addr: IpAddr) -> Option<PooledConnection<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let connection = state.idle.remove(&addr)?;
+        state.checked_out.push(addr);
+
+        Some(PooledConnection {
+            pool: self,
+            connection: Some(connection),
+            broken: false,
+        })
+    }
+
+    /// Send `message` to every connection that isn't checked out
+    ///
+    /// Connections that fail are scheduled for reconnection, their errors are returned
+    pub fn broadcast(&self, message: &[u8]) -> Vec<(IpAddr, io::Error)> {
+        let mut state = self.state.lock().unwrap();
+        let mut errors = Vec::new();
+        for (addr, connection) in state.idle.iter_mut() {
+            if let Err(err) = connection.send(message) {
+                errors.push((*addr, err));
+            }
+        }
+
+        for (addr, _) in errors.iter() {
+            state.idle.remove(addr);
+            state.disconnected.push(*addr);
+        }
+        if !errors.is_empty() {
+            self.start_reconnecting();
+        }
+
+        errors
+    }
+
+    /// Addresses of the connected peers, including the checked out ones
+    pub fn addrs(&self) -> Vec<IpAddr> {
+        let state = self.state.lock().unwrap();
+        state
+            .idle
+            .keys()
+            .chain(state.checked_out.iter())
+            .copied()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Stop reconnecting and take the idle connections
+    pub fn into_connections(self) -> Vec<NodeConnection> {
+        let mut state = self.state.lock().unwrap();
+        state
+            .idle
+            .drain()
+            .map(|(_, connection)| connection)
+            .collect()
+    }
+
+    fn check_in(&self, connection: NodeConnection, broken: bool) {
+        let mut state = self.state.lock().unwrap();
+        let addr = connection.addr();
+        if !state.checked_out.contains(&addr) {
+            // removed while checked out
+            return;
+        }
+
+        state.checked_out.retain(|checked_out| *checked_out != addr);
+        if broken {
+            state.disconnected.push(addr);
+            self.start_reconnecting();
+        } else {
+            state.idle.insert(addr, connection);
+        }
+    }
+
+    fn start_reconnecting(&self) {
+        let mut handle = self.handle.lock().unwrap();
+        if handle.is_some() {
+            return;
+        }
+
+        let state = self.state.clone();
+        let connector = self.connector.clone();
+        let stop = self.stop.clone();
+        let reconnect_interval = self.reconnect_interval;
+        *handle = Some(thread::spawn(move || {
+            // unparked when the pool is dropped
+            thread::park_timeout(reconnect_interval);
+            while !stop.load(Ordering::Relaxed) {
+                reconnect(&state, &connector);
+                thread::park_timeout(reconnect_interval);
+            }
+        }));
+    }
+}
+
+/// Connect again to the disconnected peers, while there is room in the pool
+fn reconnect(state: &Mutex<PoolState>, connector: &Connector) {
+    let disconnected = state.lock().unwrap().disconnected.clone();
+    for addr in disconnected {
+        // connecting may take a while, the pool stays usable meanwhile
+        let Ok(connection) = connector(addr) else {
+            continue;
+        };
+
+        let mut state = state.lock().unwrap();
+        if state.disconnected.contains(&addr) && state.len() < state.max_connections {
+            state
+                .disconnected
+                .retain(|disconnected| *disconnected != addr);
+            state.idle.insert(addr, connection);
+        }
+    }
+}
+
+impl Drop for ConnectionPool {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.get_mut().unwrap().take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Connections are checkpointed as the addresses of the peers, a restored pool connects to them
+/// again over TCP
+#[cfg(feature = "serde")]
+impl serde::Serialize for ConnectionPool {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.addrs().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ConnectionPool {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let connections = Vec::<NodeConnection>::deserialize(deserializer)?;
+        Ok(ConnectionPool::from_connections(
+            connections,
+            NodeConnection::connect,
+        ))
+    }
+}
+
+/// Connection checked out of a [ConnectionPool], returned to the pool when dropped
+pub struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    connection: Option<NodeConnection>,
+    broken: bool,
+}
+
+impl PooledConnection<'_> {
+    /// The connection failed, it is connected again in the background instead of being returned
+    pub fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = NodeConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.connection.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.check_in(connection, self.broken);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Sync primitives shared by the executors and their handles
+//!
+//! The `loom` feature swaps them for the ones of `loom`, so the races of pausing, resuming and
+//! shutting down a machine can be model checked. Handles and controlled machines then only work
+//! inside `loom::model`
+
+#[cfg(feature = "loom")]
+pub(crate) use loom::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+#[cfg(not(feature = "loom"))]
+pub(crate) use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
@@ -0,0 +1,130 @@
+use std::error::Error;
+
+use tokio::sync::watch;
+
+use crate::{
+    external_enum::{EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    StateName,
+};
+
+/// Wraps a state machine, publishing the current state name after every transition.
+///
+/// Can be used with any executor, other tasks observe the machine through the
+/// [watch::Receiver] returned by [publish_state]
+pub struct Published<T> {
+    machine: T,
+    sender: watch::Sender<&'static str>,
+}
+
+/// Wrap `machine`, returning the receiver side of the published state names
+pub fn publish_state<T: StateName>(machine: T) -> (Published<T>, watch::Receiver<&'static str>) {
+    let (sender, receiver) = watch::channel(machine.state_name());
+    (Published { machine, sender }, receiver)
+}
+
+impl<T> Published<T>
+where
+    T: StateName,
+{
+    fn publish(machine: T, sender: watch::Sender<&'static str>) -> Self {
+        sender.send_replace(machine.state_name());
+        Self { machine, sender }
+    }
+}
+
+impl<T> Published<T> {
+    /// Unwrap the machine, usually the terminal state returned by the executor
+    pub fn into_inner(self) -> T {
+        self.machine
+    }
+}
+
+impl<T> InternallyDrivenTransition for Published<T>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let machine = self.machine.execute()?;
+        Ok(Self::publish(machine, self.sender))
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T> ExternallyDrivenTransition for Published<T>
+where
+    T: ExternallyDrivenTransition + StateName,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.execute(input, context)
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.execute_batch(inputs, context)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(self) -> Self {
+        let machine = self.machine.transition();
+        Self::publish(machine, self.sender)
+    }
+
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        let machine = self.machine.try_transition()?;
+        Ok(Self::publish(machine, self.sender))
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<std::time::Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.on_idle(context)
+    }
+}
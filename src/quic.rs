@@ -0,0 +1,200 @@
+//! [NodeConnection]s over QUIC with quinn
+//!
+//! Every connection uses a single bidirectional stream, the TLS configuration is shared with the
+//! `tls` module
+
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    ClientConfig, Endpoint, RecvStream, SendStream, ServerConfig,
+};
+use tokio::runtime::{Builder, Runtime};
+
+use crate::{
+    tls::{TlsAcceptor, TlsConnector},
+    transport::Transport,
+    NodeConnection,
+};
+
+/// Sent by the connecting side, a QUIC stream is only visible to the peer after its first byte
+const OPEN: &[u8] = &[0];
+
+/// QUIC socket of this node, used both to connect to and to accept other nodes
+///
+/// The endpoint runs on its own tokio runtime, so it can be used from the synchronous states
+pub struct QuicEndpoint {
+    endpoint: Endpoint,
+    runtime: Arc<Runtime>,
+}
+
+impl QuicEndpoint {
+    /// Endpoint that only connects to other nodes
+    pub fn client(
+        bind: SocketAddr,
+        tls: &TlsConnector,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let runtime = runtime()?;
+        let crypto = QuicClientConfig::try_from(tls.config())?;
+        let mut endpoint = {
+            let _guard = runtime.enter();
+            Endpoint::client(bind)?
+        };
+        endpoint.set_default_client_config(ClientConfig::new(Arc::new(crypto)));
+
+        Ok(Self { endpoint, runtime })
+    }
+
+    /// Endpoint that accepts connections of other nodes on `bind`
+    pub fn server(
+        bind: SocketAddr,
+        tls: &TlsAcceptor,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let runtime = runtime()?;
+        let crypto = QuicServerConfig::try_from(tls.config())?;
+        let endpoint = {
+            let _guard = runtime.enter();
+            Endpoint::server(ServerConfig::with_crypto(Arc::new(crypto)), bind)?
+        };
+
+        Ok(Self { endpoint, runtime })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+
+    /// Connect to the node at `addr`, its certificate must be valid for `server_name`
+    pub fn connect(
+        &self,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        let (local_ip, send, recv) = self.runtime.block_on(async {
+            let connection = self.endpoint.connect(addr, server_name)?.await?;
+            let (mut send, recv) = connection.open_bi().await?;
+            send.write_all(OPEN).await?;
+
+            Ok::<_, Box<dyn Error + Send + Sync>>((connection.local_ip(), send, recv))
+        })?;
+
+        Ok(NodeConnection::new(
+            self.stream(local_ip, addr, send, recv)?,
+        )?)
+    }
+
+    /// Wait for the next node connecting to this endpoint
+    pub fn accept(&self) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        let (local_ip, addr, send, recv) = self.runtime.block_on(async {
+            let incoming = self.endpoint.accept().await.ok_or("endpoint closed")?;
+            let connection = incoming.await?;
+            let (send, mut recv) = connection.accept_bi().await?;
+
+            let mut open = [0; 1];
+            recv.read_exact(&mut open).await?;
+
+            Ok::<_, Box<dyn Error + Send + Sync>>((
+                connection.local_ip(),
+                connection.remote_address(),
+                send,
+                recv,
+            ))
+        })?;
+
+        Ok(NodeConnection::new(
+            self.stream(local_ip, addr, send, recv)?,
+        )?)
+    }
+
+    fn stream(
+        &self,
+        local_ip: Option<IpAddr>,
+        peer_addr: SocketAddr,
+        send: SendStream,
+        recv: RecvStream,
+    ) -> io::Result<QuicStream> {
+        // the endpoint may be bound to the unspecified address
+        let mut local_addr = self.endpoint.local_addr()?;
+        if let Some(local_ip) = local_ip {
+            local_addr.set_ip(local_ip);
+        }
+
+        Ok(QuicStream {
+            local_addr,
+            peer_addr,
+            send,
+            recv,
+            read_timeout: None,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+fn runtime() -> io::Result<Arc<Runtime>> {
+    // one worker is enough to drive the endpoint between the blocking calls
+    let runtime = Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()?;
+
+    Ok(Arc::new(runtime))
+}
+
+/// Bidirectional QUIC stream, reads and writes block on the runtime of its endpoint
+pub struct QuicStream {
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    send: SendStream,
+    recv: RecvStream,
+    read_timeout: Option<Duration>,
+    runtime: Arc<Runtime>,
+}
+
+impl Read for QuicStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.runtime.block_on(async {
+            match self.read_timeout {
+                Some(timeout) => tokio::time::timeout(timeout, self.recv.read(buf))
+                    .await
+                    .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?,
+                None => self.recv.read(buf).await,
+            }
+            .map_err(io::Error::from)
+        })?;
+
+        // the peer finished the stream
+        Ok(read.unwrap_or(0))
+    }
+}
+
+impl Write for QuicStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.runtime.block_on(self.send.write_all(buf))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for QuicStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    io::{self, BufRead, Write},
+    time::{Duration, Instant},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    event_source::{EventSource, TryNext},
+    external_enum::{externally_driven_executor, ExternallyDrivenTransition},
+};
+
+/// Entry of an event log, one per line in JSON format
+#[derive(Serialize, Deserialize)]
+struct RecordedEvent<E> {
+    /// Time since the recording started
+    elapsed: Duration,
+    event: E,
+}
+
+/// Event source wrapper that writes every received event to `writer`.
+///
+/// Pass it by reference to the executor, so [RecordingSource::finish] can be called after the run
+pub struct RecordingSource<S, W> {
+    source: S,
+    writer: W,
+    started: Instant,
+    error: Option<io::Error>,
+}
+
+impl<S, W> RecordingSource<S, W>
+where
+    W: Write,
+{
+    pub fn new(source: S, writer: W) -> Self {
+        Self {
+            source,
+            writer,
+            started: Instant::now(),
+            error: None,
+        }
+    }
+
+    /// Flush the log, returning the first error that happened while recording
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn record<E: Serialize>(&mut self, event: &E) {
+        if self.error.is_some() {
+            return;
+        }
+
+        let entry = RecordedEvent {
+            elapsed: self.started.elapsed(),
+            event,
+        };
+        let result = serde_json::to_writer(&mut self.writer, &entry)
+            .map_err(io::Error::from)
+            .and_then(|_| self.writer.write_all(b"\n"));
+
+        if let Err(err) = result {
+            self.error = Some(err);
+        }
+    }
+}
+
+impl<E, S, W> EventSource<E> for RecordingSource<S, W>
+where
+    E: Serialize,
+    S: EventSource<E>,
+    W: Write,
+{
+    fn next(&mut self) -> Option<E> {
+        let event = self.source.next()?;
+        self.record(&event);
+        Some(event)
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        let next = self.source.try_next();
+        if let TryNext::Event(event) = &next {
+            self.record(event);
+        }
+        next
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        let next = self.source.next_timeout(timeout);
+        if let TryNext::Event(event) = &next {
+            self.record(event);
+        }
+        next
+    }
+
+    fn pending(&self) -> Option<usize> {
+        self.source.pending()
+    }
+}
+
+/// Read a log written by [RecordingSource], the events can be fed back into a machine as an
+/// [EventSource]
+pub fn read_log<E, R>(reader: R) -> io::Result<VecDeque<E>>
+where
+    E: DeserializeOwned,
+    R: BufRead,
+{
+    let mut events = VecDeque::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry: RecordedEvent<E> = serde_json::from_str(&line)?;
+        events.push_back(entry.event);
+    }
+
+    Ok(events)
+}
+
+/// Run a fresh machine with the events recorded in `reader`
+pub fn replay<T, R>(initial_state: T, reader: R) -> Result<T, Box<dyn Error>>
+where
+    T: ExternallyDrivenTransition,
+    T::EventType: DeserializeOwned,
+    R: BufRead,
+{
+    externally_driven_executor(initial_state, read_log(reader)?).map_err(Into::into)
+}
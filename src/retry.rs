@@ -0,0 +1,201 @@
+use std::{
+    error::Error,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    clock::{Clock, SharedClock},
+    compose_trait, dyn_trait,
+};
+
+/// Decides how long to wait before retrying a failed state
+pub trait BackoffPolicy {
+    /// Delay before the retry number `attempt` (starting at 1), `None` to give up
+    fn next_delay(&mut self, attempt: usize) -> Option<Duration>;
+}
+
+/// Same delay between every retry
+#[derive(Debug, Clone)]
+pub struct Fixed {
+    delay: Duration,
+    max_retries: usize,
+}
+
+impl Fixed {
+    pub fn new(delay: Duration, max_retries: usize) -> Self {
+        Self { delay, max_retries }
+    }
+}
+
+impl BackoffPolicy for Fixed {
+    fn next_delay(&mut self, attempt: usize) -> Option<Duration> {
+        (attempt <= self.max_retries).then_some(self.delay)
+    }
+}
+
+/// Doubles the delay after every retry, starting at 100ms and capped at 30s by default
+#[derive(Debug, Clone)]
+pub struct Exponential {
+    initial_delay: Duration,
+    max_delay: Duration,
+    max_retries: usize,
+}
+
+impl Exponential {
+    pub fn new(max_retries: usize) -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            max_retries,
+        }
+    }
+
+    pub fn with_initial_delay(mut self, initial_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self
+    }
+
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+}
+
+impl BackoffPolicy for Exponential {
+    fn next_delay(&mut self, attempt: usize) -> Option<Duration> {
+        if attempt > self.max_retries {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(attempt as u32 - 1);
+        Some(
+            self.initial_delay
+                .saturating_mul(factor)
+                .min(self.max_delay),
+        )
+    }
+}
+
+/// Randomizes the delay of another policy between zero and the original delay (full jitter), so
+/// many nodes retrying at the same time don't hit the same peer in lockstep
+#[derive(Debug, Clone)]
+pub struct Jitter<P> {
+    policy: P,
+    seed: u64,
+}
+
+impl<P> Jitter<P> {
+    pub fn new(policy: P) -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+
+        Self::with_seed(policy, seed)
+    }
+
+    /// Same delays for the same seed, for reproducible simulations
+    pub fn with_seed(policy: P, seed: u64) -> Self {
+        Self {
+            policy,
+            // xorshift can't start from zero
+            seed: seed | 1,
+        }
+    }
+
+    /// xorshift64, good enough to spread retries
+    fn next_random(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+}
+
+impl<P> BackoffPolicy for Jitter<P>
+where
+    P: BackoffPolicy,
+{
+    fn next_delay(&mut self, attempt: usize) -> Option<Duration> {
+        let delay = self.policy.next_delay(attempt)?;
+        let nanos = delay.as_nanos() as u64;
+        Some(Duration::from_nanos(self.next_random() % (nanos + 1)))
+    }
+}
+
+/// Wrapper state that executes the state again when it fails, according to a [BackoffPolicy].
+///
+/// Every attempt executes a fresh clone of the state, the last error is returned once the policy
+/// gives up
+pub struct Retry<S, P> {
+    state: S,
+    policy: P,
+    clock: SharedClock,
+}
+
+impl<S, P> Retry<S, P>
+where
+    P: BackoffPolicy,
+{
+    pub fn new(state: S, policy: P) -> Self {
+        Self {
+            state,
+            policy,
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Clock waiting between the attempts
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    fn run<T>(
+        mut self,
+        execute: impl Fn(S) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Box<dyn Error>>
+    where
+        S: Clone,
+    {
+        let mut attempt = 0;
+        loop {
+            let err = match execute(self.state.clone()) {
+                Ok(output) => return Ok(output),
+                Err(err) => err,
+            };
+
+            attempt += 1;
+            match self.policy.next_delay(attempt) {
+                Some(delay) => self.clock.sleep(delay),
+                None => return Err(err),
+            }
+        }
+    }
+}
+
+impl<S, P> compose_trait::State for Retry<S, P>
+where
+    S: compose_trait::State + Clone,
+    P: BackoffPolicy,
+{
+    type Output = S::Output;
+
+    fn describe(&self) -> String {
+        format!("retry({})", self.state.describe())
+    }
+
+    fn execute(self) -> Result<Self::Output, Box<dyn Error>> {
+        self.run(compose_trait::State::execute)
+    }
+}
+
+impl<S, P> dyn_trait::State for Retry<S, P>
+where
+    S: dyn_trait::State + Clone + 'static,
+    P: BackoffPolicy + 'static,
+{
+    fn execute(self: Box<Self>) -> Result<Option<Box<dyn dyn_trait::State>>, Box<dyn Error>> {
+        self.run(|state| Box::new(state).execute())
+    }
+}
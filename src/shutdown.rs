@@ -0,0 +1,60 @@
+//! Signals asking a running machine to stop, see `internally_driven_executor_with_shutdown` and
+//! `externally_driven_executor_with_shutdown`
+
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "signals")]
+use std::io;
+#[cfg(feature = "std")]
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+/// Checked by the executors between two steps
+pub trait ShutdownSignal {
+    fn is_requested(&mut self) -> bool;
+}
+
+/// Requested once the flag is set
+impl ShutdownSignal for Arc<AtomicBool> {
+    fn is_requested(&mut self) -> bool {
+        self.load(Ordering::Acquire)
+    }
+}
+
+impl ShutdownSignal for &AtomicBool {
+    fn is_requested(&mut self) -> bool {
+        self.load(Ordering::Acquire)
+    }
+}
+
+/// Flag of a `loom` model, requested once it is set
+#[cfg(feature = "loom")]
+impl ShutdownSignal for crate::primitives::Arc<crate::primitives::AtomicBool> {
+    fn is_requested(&mut self) -> bool {
+        self.load(Ordering::Acquire)
+    }
+}
+
+/// Requested by a message, or when every sender is dropped
+#[cfg(feature = "std")]
+impl ShutdownSignal for Receiver<()> {
+    fn is_requested(&mut self) -> bool {
+        !matches!(self.try_recv(), Err(TryRecvError::Empty))
+    }
+}
+
+/// Flag set on SIGINT or SIGTERM, for the executors to stop the machine at a state boundary
+///
+/// A second signal, while the machine is stopping, terminates the process right away
+#[cfg(feature = "signals")]
+pub fn on_signals() -> io::Result<Arc<AtomicBool>> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+
+    let flag = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        // registered first, so it only sees the flag set by a previous signal
+        signal_hook::flag::register_conditional_shutdown(signal, 1, flag.clone())?;
+        signal_hook::flag::register(signal, flag.clone())?;
+    }
+
+    Ok(flag)
+}
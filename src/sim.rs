@@ -0,0 +1,393 @@
+//! In-memory [Transport] to test multi-node machines in a single process
+//!
+//! Every flush of a [SimStream] is a packet, delivered to the other end after the configured
+//! latency or dropped. [NodeConnection] flushes once per frame, so dropped packets lose whole
+//! messages instead of corrupting the stream
+//!
+//! A [Simulation] derives every seed of a multi-node scenario (network drops, fault injection,
+//! election timeouts, jitter) from a single seed, so a failing run can be replayed from it
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    chaos::{Chaos, Faults},
+    clock::{self, Clock, SharedClock},
+    error::SimulationError,
+    internal_enum::{
+        internally_driven_executor_with_context, InternallyDrivenTransitionWithContext,
+    },
+    retry::Jitter,
+    transport::Transport,
+    NodeConnection,
+};
+
+/// Network conditions between two simulated endpoints
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimConfig {
+    /// Delay of every packet
+    pub latency: Duration,
+    /// Fraction of the packets lost, between 0 and 1
+    pub drop_rate: f64,
+    /// Seed of the drops, the same seed drops the same packets
+    pub seed: u64,
+}
+
+/// Two connected endpoints, the first one is bound to `a` and the second one to `b`
+pub fn pair(a: SocketAddr, b: SocketAddr, config: &SimConfig) -> (SimStream, SimStream) {
+    pair_with_clock(a, b, config, &SharedClock::default())
+}
+
+/// [pair] with the latency and read timeouts measured on `clock`
+pub fn pair_with_clock(
+    a: SocketAddr,
+    b: SocketAddr,
+    config: &SimConfig,
+    clock: &SharedClock,
+) -> (SimStream, SimStream) {
+    let (to_b, from_a) = mpsc::channel();
+    let (to_a, from_b) = mpsc::channel();
+
+    let stream_a = SimStream::new(a, b, to_b, from_b, config, config.seed, clock.clone());
+    // each direction drops independently
+    let stream_b = SimStream::new(
+        b,
+        a,
+        to_a,
+        from_a,
+        config,
+        config.seed.wrapping_add(1),
+        clock.clone(),
+    );
+    (stream_a, stream_b)
+}
+
+/// Connection between the nodes `a` and `b`, as seen from each of them
+pub fn connect(a: IpAddr, b: IpAddr, config: &SimConfig) -> (NodeConnection, NodeConnection) {
+    connect_with_clock(a, b, config, &SharedClock::default())
+}
+
+/// [connect] with the latency and read timeouts measured on `clock`
+pub fn connect_with_clock(
+    a: IpAddr,
+    b: IpAddr,
+    config: &SimConfig,
+    clock: &SharedClock,
+) -> (NodeConnection, NodeConnection) {
+    let (stream_a, stream_b) = pair_with_clock(
+        SocketAddr::new(a, crate::transport::PORT),
+        SocketAddr::new(b, crate::transport::PORT),
+        config,
+        clock,
+    );
+
+    (
+        NodeConnection::new(stream_a).expect("simulated streams have a peer address"),
+        NodeConnection::new(stream_b).expect("simulated streams have a peer address"),
+    )
+}
+
+/// Packet and the time it reaches the other end
+type Packet = (Instant, Vec<u8>);
+
+/// One end of a simulated connection
+pub struct SimStream {
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    sender: mpsc::Sender<Packet>,
+    receiver: mpsc::Receiver<Packet>,
+    latency: Duration,
+    drop_rate: f64,
+    rng: SimRng,
+    read_timeout: Option<Duration>,
+    pending: Vec<u8>,
+    received: VecDeque<u8>,
+    clock: SharedClock,
+}
+
+impl SimStream {
+    fn new(
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+        sender: mpsc::Sender<Packet>,
+        receiver: mpsc::Receiver<Packet>,
+        config: &SimConfig,
+        seed: u64,
+        clock: SharedClock,
+    ) -> Self {
+        Self {
+            local_addr,
+            peer_addr,
+            sender,
+            receiver,
+            latency: config.latency,
+            drop_rate: config.drop_rate,
+            rng: SimRng::new(seed),
+            read_timeout: None,
+            pending: Vec::new(),
+            received: VecDeque::new(),
+            clock,
+        }
+    }
+}
+
+impl Read for SimStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.received.is_empty() {
+            let packet = match self.read_timeout {
+                Some(timeout) => {
+                    let started = self.clock.now();
+                    let packet = self.receiver.recv_timeout(timeout);
+                    if let Err(RecvTimeoutError::Timeout) = packet {
+                        clock::count_wait(&self.clock, started, timeout);
+                    }
+                    packet
+                }
+                None => self
+                    .receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
+            };
+
+            let (deliver_at, packet) = match packet {
+                Ok(packet) => packet,
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                // the other end was dropped
+                Err(RecvTimeoutError::Disconnected) => return Ok(0),
+            };
+
+            if let Some(delay) = deliver_at.checked_duration_since(self.clock.now()) {
+                self.clock.sleep(delay);
+            }
+            self.received.extend(packet);
+        }
+
+        let len = buf.len().min(self.received.len());
+        for (byte, received) in buf.iter_mut().zip(self.received.drain(..len)) {
+            *byte = received;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for SimStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let packet = std::mem::take(&mut self.pending);
+        if packet.is_empty() || self.rng.next_f64() < self.drop_rate {
+            return Ok(());
+        }
+
+        self.sender
+            .send((self.clock.now() + self.latency, packet))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+}
+
+impl Transport for SimStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        Ok(())
+    }
+}
+
+/// Small deterministic random number generator (splitmix64), not suitable for cryptography
+#[derive(Debug, Clone)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Seeded multi-node scenario
+///
+/// Randomness is replayed exactly as long as the scenario is set up in the same order, the
+/// interleaving of the node threads is left to the OS
+pub struct Simulation {
+    seed: u64,
+    rng: SimRng,
+    network: SimConfig,
+    clock: SharedClock,
+}
+
+impl Simulation {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: SimRng::new(seed),
+            network: SimConfig::default(),
+            clock: SharedClock::default(),
+        }
+    }
+
+    /// Seed from the `SIM_SEED` environment variable, or a random one, to replay a failure
+    /// reported by [SimulationError]
+    pub fn from_env() -> Self {
+        let seed = std::env::var("SIM_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos() as u64
+            });
+
+        Self::new(seed)
+    }
+
+    /// Latency and drop rate of the connections, the seed is replaced by one of the simulation
+    pub fn with_network(mut self, network: SimConfig) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Clock of the connections and fault injection, the system clock by default
+    pub fn with_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock::new(clock);
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Context of the machine of `node`, the only source of randomness of its states
+    pub fn context(&mut self, node: IpAddr) -> SimContext {
+        SimContext {
+            node,
+            rng: SimRng::new(self.rng.next_u64()),
+        }
+    }
+
+    /// Simulated connection between two nodes, see [connect]
+    pub fn connect(&mut self, a: IpAddr, b: IpAddr) -> (NodeConnection, NodeConnection) {
+        let config = SimConfig {
+            seed: self.rng.next_u64(),
+            ..self.network
+        };
+
+        connect_with_clock(a, b, &config, &self.clock)
+    }
+
+    /// Fault injection layer, its seed is replaced by one of the simulation
+    pub fn chaos(&mut self, faults: Faults) -> Chaos {
+        Chaos::new(Faults {
+            seed: self.rng.next_u64(),
+            ..faults
+        })
+        .with_clock(self.clock.clone())
+    }
+
+    /// Run the machine of every node on its own thread, until all of them terminate
+    ///
+    /// The first failure is reported with the seed of the simulation
+    pub fn run<T>(
+        &self,
+        nodes: Vec<(T, SimContext)>,
+    ) -> Result<Vec<(T, SimContext)>, SimulationError>
+    where
+        T: InternallyDrivenTransitionWithContext<SimContext> + Send + 'static,
+    {
+        let handles: Vec<_> = nodes
+            .into_iter()
+            .map(|(machine, context)| {
+                let node = context.node;
+                let handle = thread::spawn(move || {
+                    internally_driven_executor_with_context(machine, context)
+                        .map_err(|err| err.to_string())
+                });
+
+                (node, handle)
+            })
+            .collect();
+
+        let mut finished = Vec::with_capacity(handles.len());
+        let mut failure = None;
+        for (node, handle) in handles {
+            let result = handle
+                .join()
+                .unwrap_or_else(|_| Err("node panicked".to_string()));
+
+            match result {
+                Ok(machine) => finished.push(machine),
+                Err(error) if failure.is_none() => {
+                    failure = Some(SimulationError {
+                        seed: self.seed,
+                        node,
+                        error,
+                    })
+                }
+                Err(_) => {}
+            }
+        }
+
+        match failure {
+            Some(failure) => Err(failure),
+            None => Ok(finished),
+        }
+    }
+}
+
+/// Executor context of a simulated node
+pub struct SimContext {
+    pub node: IpAddr,
+    rng: SimRng,
+}
+
+impl SimContext {
+    pub fn rng(&mut self) -> &mut SimRng {
+        &mut self.rng
+    }
+
+    /// Random election timeout in `[min, max)`, so the nodes don't start elections in lockstep
+    pub fn election_timeout(&mut self, min: Duration, max: Duration) -> Duration {
+        let range = max.saturating_sub(min).as_nanos() as u64;
+        if range == 0 {
+            return min;
+        }
+
+        min + Duration::from_nanos(self.rng.next_u64() % range)
+    }
+
+    /// Jittered version of a backoff policy, seeded by this context
+    pub fn jitter<P>(&mut self, policy: P) -> Jitter<P> {
+        Jitter::with_seed(policy, self.rng.next_u64())
+    }
+}
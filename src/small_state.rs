@@ -0,0 +1,108 @@
+use alloc::boxed::Box;
+use core::{
+    error::Error,
+    marker::PhantomData,
+    mem::{self, MaybeUninit},
+    ptr,
+};
+
+use crate::error::MachineError;
+
+/// Size, in words, of the inline buffer of [SmallState]
+pub const INLINE_WORDS: usize = 4;
+
+type Storage = MaybeUninit<[usize; INLINE_WORDS]>;
+type StateResult = Result<Option<SmallState>, Box<dyn Error>>;
+
+/// Same as [crate::dyn_trait::State], but the next state is returned as a [SmallState]
+pub trait InlineState: 'static {
+    fn execute(self) -> Result<Option<SmallState>, Box<dyn Error>>;
+}
+
+/// Owned state that is stored inline when it fits in [INLINE_WORDS] words, and boxed otherwise
+///
+/// States that fit never hit the allocator when transitioning
+pub struct SmallState {
+    storage: Storage,
+    inline: bool,
+    execute: unsafe fn(*mut Storage) -> StateResult,
+    drop: unsafe fn(*mut Storage),
+    // the stored state may not be Send or Sync
+    _marker: PhantomData<*mut ()>,
+}
+
+impl SmallState {
+    pub fn new<S: InlineState>(state: S) -> Self {
+        let mut storage = Storage::uninit();
+        let inline = mem::size_of::<S>() <= mem::size_of::<Storage>()
+            && mem::align_of::<S>() <= mem::align_of::<Storage>();
+
+        if inline {
+            // SAFETY: the buffer is big enough and aligned for S
+            unsafe { storage.as_mut_ptr().cast::<S>().write(state) };
+            Self {
+                storage,
+                inline,
+                execute: execute_inline::<S>,
+                drop: drop_inline::<S>,
+                _marker: PhantomData,
+            }
+        } else {
+            let boxed = Box::into_raw(Box::new(state));
+            // SAFETY: the buffer always fits a pointer
+            unsafe { storage.as_mut_ptr().cast::<*mut S>().write(boxed) };
+            Self {
+                storage,
+                inline,
+                execute: execute_boxed::<S>,
+                drop: drop_boxed::<S>,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    /// Whether the state is stored in the inline buffer
+    pub fn is_inline(&self) -> bool {
+        self.inline
+    }
+
+    pub fn execute(self) -> Result<Option<SmallState>, Box<dyn Error>> {
+        let mut this = mem::ManuallyDrop::new(self);
+        // SAFETY: the state is moved out exactly once and `this` is never dropped
+        unsafe { (this.execute)(&mut this.storage) }
+    }
+}
+
+impl Drop for SmallState {
+    fn drop(&mut self) {
+        // SAFETY: the state was not moved out, otherwise drop would not run
+        unsafe { (self.drop)(&mut self.storage) }
+    }
+}
+
+unsafe fn execute_inline<S: InlineState>(storage: *mut Storage) -> StateResult {
+    ptr::read(storage.cast::<S>()).execute()
+}
+
+unsafe fn drop_inline<S>(storage: *mut Storage) {
+    ptr::drop_in_place(storage.cast::<S>())
+}
+
+unsafe fn execute_boxed<S: InlineState>(storage: *mut Storage) -> StateResult {
+    Box::from_raw(ptr::read(storage.cast::<*mut S>())).execute()
+}
+
+unsafe fn drop_boxed<S>(storage: *mut Storage) {
+    drop(Box::from_raw(ptr::read(storage.cast::<*mut S>())))
+}
+
+/// State machine executor function for [InlineState]
+pub fn inline_executor(initial_state: SmallState) -> Result<(), MachineError> {
+    let mut current_state = Some(initial_state);
+
+    while let Some(state) = current_state {
+        current_state = state.execute()?;
+    }
+
+    Ok(())
+}
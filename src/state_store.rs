@@ -0,0 +1,202 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{self, PathBuf},
+};
+
+use crate::{
+    checkpoint::{
+        externally_driven_executor_with_checkpoints, internally_driven_executor_with_checkpoints,
+        load_checkpoint, save_checkpoint, Checkpoint, Checkpointer,
+    },
+    error::MachineError,
+    event_source::EventSource,
+    external_enum::ExternallyDrivenTransition,
+    internal_enum::InternallyDrivenTransition,
+};
+
+/// Storage for checkpoints, each machine keeps its latest checkpoint under its own id
+pub trait StateStore {
+    fn save(&mut self, id: &str, checkpoint: &[u8]) -> io::Result<()>;
+    fn load(&self, id: &str) -> io::Result<Option<Vec<u8>>>;
+    /// Ids of all the stored checkpoints
+    fn list_checkpoints(&self) -> io::Result<Vec<String>>;
+}
+
+/// Stores each checkpoint as `<id>.json` inside a directory
+///
+/// Ids can't be empty, `.` or `..`, nor contain path separators, so they can't escape the
+/// directory
+pub struct FileSystemStore {
+    dir: PathBuf,
+}
+
+impl FileSystemStore {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Path of the checkpoint of `id` and of the temporary file it is written to
+    fn paths(&self, id: &str) -> io::Result<(PathBuf, PathBuf)> {
+        if id.is_empty() || id == "." || id == ".." || id.contains(path::is_separator) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid checkpoint id {id:?}"),
+            ));
+        }
+
+        Ok((
+            self.dir.join(format!("{id}.json")),
+            self.dir.join(format!("{id}.json.tmp")),
+        ))
+    }
+}
+
+impl StateStore for FileSystemStore {
+    fn save(&mut self, id: &str, checkpoint: &[u8]) -> io::Result<()> {
+        let (path, temp_path) = self.paths(id)?;
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(checkpoint)?;
+        file.sync_all()?;
+
+        fs::rename(temp_path, path)
+    }
+
+    fn load(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+        let (path, _) = self.paths(id)?;
+        match fs::read(path) {
+            Ok(checkpoint) => Ok(Some(checkpoint)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn list_checkpoints(&self) -> io::Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            if let Some(id) = name.to_string_lossy().strip_suffix(".json") {
+                ids.push(id.to_string());
+            }
+        }
+
+        Ok(ids)
+    }
+}
+
+/// Stores the checkpoints in a sled tree, keyed by id
+#[cfg(feature = "sled")]
+pub struct SledStore {
+    tree: sled::Tree,
+}
+
+#[cfg(feature = "sled")]
+impl SledStore {
+    pub fn new(tree: sled::Tree) -> Self {
+        Self { tree }
+    }
+}
+
+#[cfg(feature = "sled")]
+impl StateStore for SledStore {
+    fn save(&mut self, id: &str, checkpoint: &[u8]) -> io::Result<()> {
+        self.tree.insert(id, checkpoint).map_err(io::Error::other)?;
+        self.tree.flush().map_err(io::Error::other)?;
+        Ok(())
+    }
+
+    fn load(&self, id: &str) -> io::Result<Option<Vec<u8>>> {
+        let checkpoint = self.tree.get(id).map_err(io::Error::other)?;
+        Ok(checkpoint.map(|checkpoint| checkpoint.to_vec()))
+    }
+
+    fn list_checkpoints(&self) -> io::Result<Vec<String>> {
+        self.tree
+            .iter()
+            .keys()
+            .map(|key| {
+                key.map(|key| String::from_utf8_lossy(&key).into_owned())
+                    .map_err(io::Error::other)
+            })
+            .collect()
+    }
+}
+
+/// [Checkpointer] that saves the checkpoints of a machine into a [StateStore]
+pub struct StoreCheckpointer<S> {
+    store: S,
+    id: String,
+}
+
+impl<S> StoreCheckpointer<S>
+where
+    S: StateStore,
+{
+    pub fn new<I: Into<String>>(store: S, id: I) -> Self {
+        Self {
+            store,
+            id: id.into(),
+        }
+    }
+
+    /// Latest checkpoint of the machine, if any
+    pub fn load<T: Checkpoint>(&self) -> io::Result<Option<T>> {
+        match self.store.load(&self.id)? {
+            Some(checkpoint) => load_checkpoint(&checkpoint[..]).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<S, T> Checkpointer<T> for StoreCheckpointer<S>
+where
+    S: StateStore,
+    T: Checkpoint,
+{
+    fn checkpoint(&mut self, machine: &T) -> io::Result<()> {
+        let mut checkpoint = Vec::new();
+        save_checkpoint(machine, &mut checkpoint)?;
+        self.store.save(&self.id, &checkpoint)
+    }
+}
+
+/// Resume the machine stored under `id`, starting from `initial_state` if there is no checkpoint
+pub fn internally_driven_executor_from_store<T, S>(
+    store: S,
+    id: &str,
+    initial_state: T,
+) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition + Checkpoint,
+    S: StateStore,
+{
+    let checkpointer = StoreCheckpointer::new(store, id);
+    let state = checkpointer
+        .load()
+        .map_err(MachineError::Checkpoint)?
+        .unwrap_or(initial_state);
+    internally_driven_executor_with_checkpoints(state, checkpointer)
+}
+
+/// Resume the machine stored under `id`, starting from `initial_state` if there is no checkpoint
+pub fn externally_driven_executor_from_store<T, S, E>(
+    store: S,
+    id: &str,
+    initial_state: T,
+    events: E,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition + Checkpoint,
+    S: StateStore,
+    E: EventSource<T::EventType>,
+{
+    let checkpointer = StoreCheckpointer::new(store, id);
+    let state = checkpointer
+        .load()
+        .map_err(MachineError::Checkpoint)?
+        .unwrap_or(initial_state);
+    externally_driven_executor_with_checkpoints(state, events, checkpointer)
+}
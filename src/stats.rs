@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    error::MachineError,
+    event_source::{EventSource, TryNext},
+    external_enum::{externally_driven_executor, EventContext, ExternallyDrivenTransition},
+    internal_enum::{internally_driven_executor, InternallyDrivenTransition},
+    StateName,
+};
+
+/// Statistics collected during a machine run
+#[derive(Debug, Default, Clone)]
+pub struct RunStats {
+    pub transitions: usize,
+    pub events_consumed: usize,
+    /// Most events seen waiting behind the one taken, zero when the source can't tell its depth,
+    /// see [EventSource::pending]
+    pub max_queue_depth: usize,
+    pub time_per_state: HashMap<&'static str, Duration>,
+    pub total_time: Duration,
+}
+
+/// Same as [internally_driven_executor], but also returns the [RunStats]
+pub fn internally_driven_executor_with_stats<T>(
+    initial_state: T,
+) -> Result<(T, RunStats), MachineError>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    let started = Instant::now();
+    let measured = internally_driven_executor(Measured::new(initial_state))?;
+    Ok(measured.finish(started))
+}
+
+/// Same as [externally_driven_executor], but also returns the [RunStats]
+pub fn externally_driven_executor_with_stats<T, S>(
+    initial_state: T,
+    events: S,
+) -> Result<(T, RunStats), MachineError>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: EventSource<T::EventType>,
+{
+    let started = Instant::now();
+    let mut events = Sampled {
+        source: events,
+        max_depth: 0,
+    };
+    let measured = externally_driven_executor(Measured::new(initial_state), &mut events)?;
+    let (machine, mut stats) = measured.finish(started);
+    stats.max_queue_depth = events.max_depth;
+    Ok((machine, stats))
+}
+
+/// Wraps an event source, keeping the deepest its queue was seen
+struct Sampled<S> {
+    source: S,
+    max_depth: usize,
+}
+
+impl<S> Sampled<S> {
+    fn sample<E>(&mut self)
+    where
+        S: EventSource<E>,
+    {
+        if let Some(depth) = self.source.pending() {
+            self.max_depth = self.max_depth.max(depth);
+        }
+    }
+}
+
+impl<E, S> EventSource<E> for Sampled<S>
+where
+    S: EventSource<E>,
+{
+    fn next(&mut self) -> Option<E> {
+        let event = self.source.next();
+        self.sample();
+        event
+    }
+
+    fn try_next(&mut self) -> TryNext<E> {
+        let next = self.source.try_next();
+        self.sample();
+        next
+    }
+
+    fn next_timeout(&mut self, timeout: Duration) -> TryNext<E> {
+        let next = self.source.next_timeout(timeout);
+        self.sample();
+        next
+    }
+
+    fn pending(&self) -> Option<usize> {
+        self.source.pending()
+    }
+}
+
+/// Wraps a state machine, collecting [RunStats] as it runs
+struct Measured<T> {
+    machine: T,
+    stats: RunStats,
+    entered_at: Instant,
+}
+
+impl<T> Measured<T>
+where
+    T: StateName,
+{
+    fn new(machine: T) -> Self {
+        Self {
+            machine,
+            stats: RunStats::default(),
+            entered_at: Instant::now(),
+        }
+    }
+
+    /// Account the time spent in the current state
+    fn leave_state(&mut self) {
+        let now = Instant::now();
+        *self
+            .stats
+            .time_per_state
+            .entry(self.machine.state_name())
+            .or_default() += now - self.entered_at;
+        self.entered_at = now;
+    }
+
+    fn finish(mut self, started: Instant) -> (T, RunStats) {
+        self.leave_state();
+        self.stats.total_time = started.elapsed();
+        (self.machine, self.stats)
+    }
+}
+
+impl<T> InternallyDrivenTransition for Measured<T>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    fn execute(mut self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let name = self.machine.state_name();
+        let machine = self.machine.execute()?;
+
+        let now = Instant::now();
+        *self.stats.time_per_state.entry(name).or_default() += now - self.entered_at;
+        self.stats.transitions += 1;
+
+        Ok(Self {
+            machine,
+            stats: self.stats,
+            entered_at: now,
+        })
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T> ExternallyDrivenTransition for Measured<T>
+where
+    T: ExternallyDrivenTransition + StateName,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.stats.events_consumed += 1;
+        self.machine.execute(input, context)
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.stats.events_consumed += inputs.len();
+        self.machine.execute_batch(inputs, context)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(mut self) -> Self {
+        self.leave_state();
+        self.stats.transitions += 1;
+
+        Self {
+            machine: self.machine.transition(),
+            stats: self.stats,
+            entered_at: self.entered_at,
+        }
+    }
+
+    fn try_transition(mut self) -> Result<Self, Box<dyn Error>> {
+        self.leave_state();
+        self.stats.transitions += 1;
+
+        Ok(Self {
+            machine: self.machine.try_transition()?,
+            stats: self.stats,
+            entered_at: self.entered_at,
+        })
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.on_idle(context)
+    }
+}
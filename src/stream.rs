@@ -0,0 +1,67 @@
+use std::error::Error;
+
+use futures::{stream, Stream};
+
+use crate::{
+    event_source::AsyncEventSource,
+    external_enum::{execute_event, EventContext, ExternallyDrivenTransition},
+    StateName,
+};
+
+/// Transition between two states of a running machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Transition {
+    pub from: &'static str,
+    pub to: &'static str,
+}
+
+/// Run the machine as a [Stream] of transitions, an alternative to
+/// [externally_driven_executor_async](crate::external_enum::externally_driven_executor_async).
+///
+/// The machine only makes progress while the stream is polled, it ends when a terminal state is
+/// reached, the event source is closed or after the first error
+pub fn transition_stream<T, S>(
+    initial_state: T,
+    events: S,
+) -> impl Stream<Item = Result<Transition, Box<dyn Error>>>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: AsyncEventSource<T::EventType>,
+{
+    let run = Some((initial_state, events, EventContext::new()));
+
+    stream::unfold(run, |run| async move {
+        let (current_state, mut events, mut context) = run?;
+        if current_state.is_terminal_state() {
+            return None;
+        }
+
+        loop {
+            let input = match context.next_queued() {
+                Some(input) => input,
+                None => events.next().await?,
+            };
+
+            if current_state.should_defer(&input) {
+                context.defer(input);
+                continue;
+            }
+
+            if !current_state.can_handle(&input) {
+                continue;
+            }
+
+            let from = current_state.state_name();
+            return match execute_event(current_state, input, &mut context) {
+                Ok(next_state) => {
+                    let transition = Transition {
+                        from,
+                        to: next_state.state_name(),
+                    };
+                    Some((Ok(transition), Some((next_state, events, context))))
+                }
+                Err(err) => Some((Err(err), None)),
+            };
+        }
+    })
+}
@@ -0,0 +1,341 @@
+//! Sync phase, the leader broadcasts the events of the application and the followers acknowledge
+//! them
+//!
+//! Every event carries a sequence number, acknowledgements refer to it so a late acknowledgement
+//! isn't mistaken for the one of the current event. Each leader numbers its own events, so the
+//! followers keep the last sequence number applied from every leader
+
+#[cfg(feature = "std")]
+use crate::clock::{self, Clock};
+use crate::NodeConnection;
+use alloc::{boxed::Box, vec::Vec};
+use core::net::IpAddr;
+#[cfg(feature = "std")]
+use core::time::Duration;
+
+/// Application data replicated once a leader is elected, the leader broadcasts `events` to the
+/// followers, which pass them to `apply`
+///
+/// Carried by every state of the machine, since the role of the node is only known after the
+/// election. There are no events by default and received events are dropped
+pub struct Replication {
+    events: Box<dyn Iterator<Item = Vec<u8>> + Send>,
+    apply: Box<dyn FnMut(Vec<u8>) + Send>,
+    /// Sequence number of the last event of this node acknowledged by the quorum, kept across
+    /// elections
+    sequence: u64,
+    /// Sequence number of the last event applied from each leader
+    applied: Vec<(IpAddr, u64)>,
+}
+
+impl Replication {
+    pub fn new<I>(events: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        Self {
+            events: Box::new(events.into_iter()),
+            apply: Box::new(|_| {}),
+            sequence: 0,
+            applied: Vec::new(),
+        }
+    }
+
+    /// Called with every event received from the leader, in order
+    pub fn with_apply<F>(mut self, apply: F) -> Self
+    where
+        F: FnMut(Vec<u8>) + Send + 'static,
+    {
+        self.apply = Box::new(apply);
+        self
+    }
+
+    /// Events of `leader` at or below the last sequence number applied from it are skipped, a
+    /// leader elected again sends the events it didn't see acknowledged
+    #[cfg(feature = "std")]
+    pub(crate) fn apply(&mut self, leader: IpAddr, sequence: u64, event: Vec<u8>) {
+        match self.applied.iter_mut().find(|(addr, _)| *addr == leader) {
+            Some((_, applied)) if sequence <= *applied => return,
+            Some((_, applied)) => *applied = sequence,
+            None => self.applied.push((leader, sequence)),
+        }
+
+        (self.apply)(event);
+    }
+
+    /// Sequence number of the last event of this node acknowledged by the quorum
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Sequence number of the last event applied from `leader`
+    pub fn applied(&self, leader: IpAddr) -> u64 {
+        self.applied
+            .iter()
+            .find(|(addr, _)| *addr == leader)
+            .map_or(0, |(_, applied)| *applied)
+    }
+
+    pub(crate) fn next_event(&mut self) -> Option<Vec<u8>> {
+        self.events.next()
+    }
+
+    /// The event with the next sequence number was acknowledged by the quorum
+    pub(crate) fn acknowledged(&mut self) {
+        self.sequence += 1;
+    }
+
+    /// Put back an event that wasn't acknowledged, this node sends it again with the same sequence
+    /// number if it leads after the next election
+    pub(crate) fn resend(&mut self, event: Vec<u8>) {
+        let events = core::mem::replace(&mut self.events, Box::new(core::iter::empty()));
+        self.events = Box::new(core::iter::once(event).chain(events));
+    }
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Self::new(core::iter::empty())
+    }
+}
+
+/// How long the leader waits for the followers to acknowledge an event
+#[cfg(feature = "std")]
+pub const ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a follower waits for the next message of the leader before going back to consensus
+#[cfg(feature = "std")]
+pub const LEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time spent waiting on each connection while polling all of them
+#[cfg(feature = "std")]
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Prefix of an event, followed by its sequence number and payload
+#[cfg(feature = "std")]
+const EVENT: &[u8] = b"sync/event";
+/// Prefix of an acknowledgement, followed by the sequence number of the event
+#[cfg(feature = "std")]
+const ACK: &[u8] = b"sync/ack";
+/// Sent by the leader once its events are over
+#[cfg(feature = "std")]
+pub(crate) const END: &[u8] = b"sync/end";
+
+/// Send `event` to every follower and wait up to [ACK_TIMEOUT] for their acknowledgements
+///
+/// Returns the followers that acknowledged the event, the ones that can't be reached don't.
+/// Without the `std` feature there is nothing to send to, so every follower acknowledges it
+#[cfg(feature = "std")]
+pub fn broadcast(connections: &mut [NodeConnection], sequence: u64, event: &[u8]) -> Vec<IpAddr> {
+    broadcast_with_clock(connections, sequence, event, &clock::SystemClock)
+}
+
+#[cfg(not(feature = "std"))]
+pub fn broadcast(connections: &mut [NodeConnection], _sequence: u64, _event: &[u8]) -> Vec<IpAddr> {
+    connections.iter().map(NodeConnection::addr).collect()
+}
+
+/// Same as [broadcast], [ACK_TIMEOUT] is measured by `clock`
+#[cfg(feature = "std")]
+pub fn broadcast_with_clock(
+    connections: &mut [NodeConnection],
+    sequence: u64,
+    event: &[u8],
+    clock: &dyn Clock,
+) -> Vec<IpAddr> {
+    let message = event_message(sequence, event);
+    let mut waiting: Vec<_> = connections
+        .iter_mut()
+        .filter_map(|connection| connection.send(&message).is_ok().then_some(connection))
+        .collect();
+
+    let mut acked = Vec::with_capacity(waiting.len());
+    let deadline = clock.now() + ACK_TIMEOUT;
+    while !waiting.is_empty() && clock.now() < deadline {
+        let started = clock.now();
+        waiting.retain_mut(|connection| match connection.try_recv(POLL_INTERVAL) {
+            Ok(Some(message)) if parse_ack(&message) == Some(sequence) => {
+                acked.push(connection.addr());
+                false
+            }
+            // late acknowledgements of older events are ignored
+            Ok(_) => true,
+            Err(_) => false,
+        });
+        clock::count_wait(clock, started, POLL_INTERVAL);
+    }
+
+    acked
+}
+
+/// Tell the followers that there are no more events
+#[cfg(feature = "std")]
+pub fn finish(connections: &mut [NodeConnection]) {
+    for connection in connections.iter_mut() {
+        // a follower that can't be told times out waiting for the next event
+        let _ = connection.send(END);
+    }
+}
+
+#[cfg(not(feature = "std"))]
+pub fn finish(_connections: &mut [NodeConnection]) {}
+
+/// Message received by a follower, see [receive]
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Eq)]
+pub enum Received {
+    Event {
+        sequence: u64,
+        event: Vec<u8>,
+    },
+    /// The leader has no more events
+    End,
+}
+
+/// Wait up to `timeout`, measured by `clock`, for the next message of the leader, events are
+/// acknowledged before they are returned
+///
+/// `None` if nothing arrived in time or every connection failed
+#[cfg(feature = "std")]
+pub fn receive(
+    connections: &mut [NodeConnection],
+    timeout: Duration,
+    clock: &dyn Clock,
+) -> Option<Received> {
+    let mut alive = vec![true; connections.len()];
+    let deadline = clock.now() + timeout;
+    while alive.iter().any(|alive| *alive) && clock.now() < deadline {
+        let started = clock.now();
+        for (connection, alive) in connections.iter_mut().zip(alive.iter_mut()) {
+            if !*alive {
+                continue;
+            }
+
+            let message = match connection.try_recv(POLL_INTERVAL) {
+                Ok(Some(message)) => message,
+                Ok(None) => continue,
+                Err(_) => {
+                    *alive = false;
+                    continue;
+                }
+            };
+
+            let Some(received) = parse_received(&message) else {
+                continue;
+            };
+            let Received::Event { sequence, .. } = received else {
+                return Some(received);
+            };
+
+            let mut ack = Vec::with_capacity(ACK.len() + 8);
+            ack.extend_from_slice(ACK);
+            ack.extend_from_slice(&sequence.to_be_bytes());
+            if connection.send(&ack).is_err() {
+                *alive = false;
+                continue;
+            }
+
+            return Some(received);
+        }
+        clock::count_wait(clock, started, POLL_INTERVAL);
+    }
+
+    None
+}
+
+/// Message carrying `event`, as sent by [broadcast]
+#[cfg(feature = "std")]
+pub(crate) fn event_message(sequence: u64, event: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(EVENT.len() + 8 + event.len());
+    message.extend_from_slice(EVENT);
+    message.extend_from_slice(&sequence.to_be_bytes());
+    message.extend_from_slice(event);
+    message
+}
+
+/// Event or end of the events of the leader, `None` for any other message
+#[cfg(feature = "std")]
+pub(crate) fn parse_received(message: &[u8]) -> Option<Received> {
+    if message == END {
+        return Some(Received::End);
+    }
+
+    let (sequence, event) = parse_event(message)?;
+    Some(Received::Event {
+        sequence,
+        event: event.to_vec(),
+    })
+}
+
+#[cfg(feature = "std")]
+fn parse_event(message: &[u8]) -> Option<(u64, &[u8])> {
+    let message = message.strip_prefix(EVENT)?;
+    let (sequence, event) = message.split_first_chunk::<8>()?;
+    Some((u64::from_be_bytes(*sequence), event))
+}
+
+#[cfg(feature = "std")]
+fn parse_ack(message: &[u8]) -> Option<u64> {
+    let sequence = message.strip_prefix(ACK)?.first_chunk::<8>()?;
+    Some(u64::from_be_bytes(*sequence))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn follower() -> (Replication, Arc<Mutex<Vec<Vec<u8>>>>) {
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let events = applied.clone();
+        let replication =
+            Replication::default().with_apply(move |event| events.lock().unwrap().push(event));
+        (replication, applied)
+    }
+
+    #[test]
+    fn new_leader_behind_a_follower() {
+        let first: IpAddr = [10, 0, 0, 1].into();
+        let second: IpAddr = [10, 0, 0, 2].into();
+        let (mut ahead, ahead_events) = follower();
+        let (mut behind, _) = follower();
+
+        for sequence in 1..=5 {
+            ahead.apply(first, sequence, vec![sequence as u8]);
+        }
+        for sequence in 1..=3 {
+            behind.apply(first, sequence, vec![sequence as u8]);
+        }
+
+        // the node that is behind leads, its own events are numbered from its own sequence
+        let sequence = behind.sequence() + 1;
+        ahead.apply(second, sequence, b"new".to_vec());
+        // sent again after a re-election
+        ahead.apply(second, sequence, b"new".to_vec());
+
+        assert_eq!(ahead.applied(first), 5);
+        assert_eq!(ahead.applied(second), sequence);
+        assert_eq!(
+            *ahead_events.lock().unwrap(),
+            vec![vec![1], vec![2], vec![3], vec![4], vec![5], b"new".to_vec()]
+        );
+    }
+
+    #[test]
+    fn leader_elected_again_resends() {
+        let leader: IpAddr = [10, 0, 0, 1].into();
+        let other: IpAddr = [10, 0, 0, 2].into();
+        let (mut replication, events) = follower();
+
+        replication.apply(leader, 1, b"first".to_vec());
+        replication.apply(other, 1, b"other".to_vec());
+        replication.apply(leader, 1, b"first".to_vec());
+        replication.apply(leader, 2, b"second".to_vec());
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![b"first".to_vec(), b"other".to_vec(), b"second".to_vec()]
+        );
+    }
+}
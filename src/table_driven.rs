@@ -0,0 +1,150 @@
+use alloc::{boxed::Box, format, vec::Vec};
+use core::{error::Error, fmt::Debug, net::IpAddr};
+
+use crate::{
+    discovery::{Discovery, NodeDiscovery},
+    error::{MachineError, NoTransitionError},
+    NodeConnection, StateName,
+};
+
+/// Benchmark function
+pub fn run_full_state_machine() {
+    let mut context = Context::default();
+    table_executor(&TRANSITIONS, StateId::DiscoverNodes, |state| {
+        context.execute(state)
+    })
+    .unwrap();
+}
+
+/// Row of a transition table, `(from, event, to)`
+pub type Transition<S, E> = (S, E, S);
+
+/// State machine interpreter.
+///
+/// `action` executes the current state and returns the event it produced, the next state is
+/// looked up in `table`. The machine stops when `action` returns `None`, or fails with
+/// [NoTransitionError] when the table has no row for the event
+///
+/// Returns the last state
+pub fn table_executor<S, E, F>(
+    table: &[Transition<S, E>],
+    initial_state: S,
+    mut action: F,
+) -> Result<S, MachineError>
+where
+    S: Copy + PartialEq + StateName,
+    E: Copy + PartialEq + Debug,
+    F: FnMut(S) -> Result<Option<E>, Box<dyn Error>>,
+{
+    let mut current_state = initial_state;
+
+    while let Some(event) = action(current_state)? {
+        current_state = table
+            .iter()
+            .find(|(from, on, _)| *from == current_state && *on == event)
+            .map(|(_, _, to)| *to)
+            .ok_or_else(|| NoTransitionError {
+                state: current_state.state_name(),
+                event: format!("{event:?}"),
+            })?;
+    }
+
+    Ok(current_state)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateId {
+    DiscoverNodes,
+    ConnectNodes,
+    Consensus,
+    Leader,
+    Follower,
+}
+
+impl StateName for StateId {
+    fn state_name(&self) -> &'static str {
+        match self {
+            StateId::DiscoverNodes => "DiscoverNodes",
+            StateId::ConnectNodes => "ConnectNodes",
+            StateId::Consensus => "Consensus",
+            StateId::Leader => "Leader",
+            StateId::Follower => "Follower",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventId {
+    NodesDiscovered,
+    NodesConnected,
+    Elected,
+    Defeated,
+}
+
+/// All the transitions of the machine, known at compile time
+pub const TRANSITIONS: [Transition<StateId, EventId>; 4] = [
+    (
+        StateId::DiscoverNodes,
+        EventId::NodesDiscovered,
+        StateId::ConnectNodes,
+    ),
+    (
+        StateId::ConnectNodes,
+        EventId::NodesConnected,
+        StateId::Consensus,
+    ),
+    (StateId::Consensus, EventId::Elected, StateId::Leader),
+    (StateId::Consensus, EventId::Defeated, StateId::Follower),
+];
+
+// Mock States
+// 1. Discover all nodes in the network
+// 2. Connect to all nodes
+// 3. Elect a leader
+// 4. Start a sync process
+//     1. Followers will wait for events
+//     2. The Leader will only send events
+//
+// States don't hold any data in this pattern, it lives in a context shared by all of them
+
+#[derive(Default)]
+pub struct Context {
+    discovery: Discovery,
+    nodes: Vec<IpAddr>,
+    connections: Vec<NodeConnection>,
+}
+
+impl Context {
+    pub fn new(discovery: Discovery) -> Self {
+        Self {
+            discovery,
+            ..Default::default()
+        }
+    }
+
+    pub fn execute(&mut self, state: StateId) -> Result<Option<EventId>, Box<dyn Error>> {
+        match state {
+            StateId::DiscoverNodes => {
+                self.nodes = self
+                    .discovery
+                    .discover()
+                    .map_err(MachineError::DiscoveryFailed)?;
+                Ok(Some(EventId::NodesDiscovered))
+            }
+            StateId::ConnectNodes => {
+                self.connections =
+                    crate::connect_to_nodes(&self.nodes, &crate::ConnectPolicy::default())?;
+                Ok(Some(EventId::NodesConnected))
+            }
+            StateId::Consensus => {
+                let is_leader = crate::elect_leader(&mut self.connections)?;
+                if is_leader {
+                    Ok(Some(EventId::Elected))
+                } else {
+                    Ok(Some(EventId::Defeated))
+                }
+            }
+            StateId::Leader | StateId::Follower => Ok(None),
+        }
+    }
+}
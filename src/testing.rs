@@ -0,0 +1,354 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::Debug,
+    io,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    external_enum::{
+        externally_driven_executor, process_event, EventContext, ExternallyDrivenTransition,
+    },
+    transition_log::{InMemoryLog, Logged, TransitionLog, TransitionRecord},
+    StateName,
+};
+
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+
+/// Executor for golden trace tests.
+///
+/// Drives a machine with a scripted sequence of events and checks the exact sequence of states
+/// visited, including the initial state
+pub struct ReplayExecutor<E> {
+    events: VecDeque<E>,
+}
+
+impl<E> ReplayExecutor<E>
+where
+    E: Debug,
+{
+    pub fn new<I: IntoIterator<Item = E>>(events: I) -> Self {
+        Self {
+            events: events.into_iter().collect(),
+        }
+    }
+
+    /// Run the machine until the script ends or a terminal state is reached, returning the
+    /// states visited
+    pub fn run<T>(self, initial_state: T) -> Result<Vec<&'static str>, Box<dyn Error>>
+    where
+        T: ExternallyDrivenTransition<EventType = E> + StateName,
+    {
+        let initial_name = initial_state.state_name();
+        let logged = Logged::new(initial_state, InMemoryLog::new());
+        let (_, log) = externally_driven_executor(logged, self.events)?.finish()?;
+
+        let mut visited = vec![initial_name];
+        visited.extend(log.records().iter().map(|record| record.to));
+        Ok(visited)
+    }
+
+    /// Run the machine, panicking with a readable diff if the states visited don't match
+    /// `expected`
+    pub fn assert_states<T>(self, initial_state: T, expected: &[&str])
+    where
+        T: ExternallyDrivenTransition<EventType = E> + StateName,
+    {
+        let visited = match self.run(initial_state) {
+            Ok(visited) => visited,
+            Err(err) => panic!("state machine failed: {err}"),
+        };
+
+        if visited != expected {
+            panic!("{}", diff_states(expected, &visited));
+        }
+    }
+}
+
+/// Fluent driver for unit tests of externally driven machines
+///
+/// Events are processed right away, along with the internal events they post. Every assertion
+/// panics with the state the machine is in, for example
+/// `TestDriver::new(machine).send(ExternalEvent::Step).expect_state("ConnectNodes")`
+pub struct TestDriver<T: ExternallyDrivenTransition> {
+    machine: Option<T>,
+    context: EventContext<T::EventType>,
+}
+
+impl<T> TestDriver<T>
+where
+    T: ExternallyDrivenTransition + StateName,
+    T::EventType: Debug,
+{
+    pub fn new(machine: T) -> Self {
+        Self {
+            machine: Some(machine),
+            context: EventContext::new(),
+        }
+    }
+
+    /// Process `event`, panicking if the machine fails or already terminated
+    #[track_caller]
+    pub fn send(mut self, event: T::EventType) -> Self {
+        if self.machine().is_terminal_state() {
+            panic!(
+                "can't send {event:?}, the machine terminated in {}",
+                self.machine().state_name()
+            );
+        }
+
+        self.process(event);
+        self
+    }
+
+    /// Process `event`, panicking if the machine moves to another state
+    #[track_caller]
+    pub fn send_without_transition(self, event: T::EventType) -> Self {
+        let before = self.machine().state_name();
+        let event_name = format!("{event:?}");
+        let driver = self.send(event);
+
+        let after = driver.machine().state_name();
+        if after != before {
+            panic!("{event_name} moved the machine from {before} to {after}");
+        }
+        driver
+    }
+
+    /// Time out the current state right away, delivering its timeout event
+    #[track_caller]
+    pub fn timeout(mut self) -> Self {
+        let Some(event) = self.machine().timeout_event() else {
+            panic!("{} has no timeout event", self.machine().state_name());
+        };
+
+        self.process(event);
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_state(self, expected: &str) -> Self {
+        let actual = self.machine().state_name();
+        if actual != expected {
+            panic!("expected the machine in {expected}, it is in {actual}");
+        }
+        self
+    }
+
+    /// Check the timeout of the current state, `None` when it waits forever
+    #[track_caller]
+    pub fn expect_timeout(self, expected: Option<Duration>) -> Self {
+        let actual = self.machine().timeout();
+        if actual != expected {
+            panic!(
+                "expected {} to time out after {expected:?}, it times out after {actual:?}",
+                self.machine().state_name()
+            );
+        }
+        self
+    }
+
+    #[track_caller]
+    pub fn expect_terminal(self) -> Self {
+        if !self.machine().is_terminal_state() {
+            panic!(
+                "expected the machine to terminate, it is in {}",
+                self.machine().state_name()
+            );
+        }
+        self
+    }
+
+    /// Unwrap the machine, to check what the assertions don't cover
+    pub fn into_inner(mut self) -> T {
+        self.machine
+            .take()
+            .expect("machine is only taken while processing")
+    }
+
+    fn machine(&self) -> &T {
+        self.machine
+            .as_ref()
+            .expect("machine is only taken while processing")
+    }
+
+    #[track_caller]
+    fn process(&mut self, event: T::EventType) {
+        let mut machine = self
+            .machine
+            .take()
+            .expect("machine is only taken while processing");
+        let mut next = Some(event);
+        while let Some(input) = next {
+            let state = machine.state_name();
+            machine = match process_event(machine, input, &mut self.context) {
+                Ok(machine) => machine,
+                Err(err) => panic!("{state} failed: {err}"),
+            };
+            if machine.is_terminal_state() {
+                break;
+            }
+
+            next = self.context.next_queued();
+        }
+
+        self.machine = Some(machine);
+    }
+}
+
+/// Side by side comparison of two state sequences, mismatched steps are marked with `>`
+fn diff_states(expected: &[&str], actual: &[&str]) -> String {
+    let width = expected
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or_default()
+        .max("expected".len());
+
+    let mut diff = format!(
+        "state sequence mismatch\n       {:width$}  actual\n",
+        "expected"
+    );
+    for step in 0..expected.len().max(actual.len()) {
+        let expected = expected.get(step).copied().unwrap_or("<none>");
+        let actual = actual.get(step).copied().unwrap_or("<none>");
+        let marker = if expected == actual { ' ' } else { '>' };
+        diff.push_str(&format!(
+            "{marker} {step:>3}  {expected:width$}  {actual}\n"
+        ));
+    }
+
+    diff
+}
+
+/// Transition between two states, the event is kept in its debug representation
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: &'static str,
+    /// Internally driven machines don't have events
+    pub event: Option<String>,
+    pub to: &'static str,
+}
+
+impl Edge {
+    /// Transition of an internally driven machine
+    pub fn internal(from: &'static str, to: &'static str) -> Self {
+        Self {
+            from,
+            event: None,
+            to,
+        }
+    }
+
+    /// Transition of an externally driven machine on `event`
+    pub fn external<E: Debug>(from: &'static str, event: &E, to: &'static str) -> Self {
+        Self {
+            from,
+            event: Some(format!("{event:?}")),
+            to,
+        }
+    }
+}
+
+/// Transitions exercised by the tests of a machine, compared with the ones it is expected to take
+///
+/// Clones share the same records, so a single coverage can be kept in a `static` and collect the
+/// transitions of the whole test suite
+#[derive(Clone, Default)]
+pub struct Coverage {
+    expected: Arc<Vec<Edge>>,
+    exercised: Arc<Mutex<Vec<Edge>>>,
+}
+
+impl Coverage {
+    pub fn new<I: IntoIterator<Item = Edge>>(expected: I) -> Self {
+        Self {
+            expected: Arc::new(expected.into_iter().collect()),
+            exercised: Arc::default(),
+        }
+    }
+
+    /// Wrap `machine` so its transitions are recorded, works with any of the enum executors
+    pub fn wrap<T: StateName>(&self, machine: T) -> Logged<T, Coverage> {
+        Logged::new(machine, self.clone())
+    }
+
+    pub fn exercised(&self) -> Vec<Edge> {
+        self.exercised.lock().unwrap().clone()
+    }
+
+    /// Expected transitions that no test exercised
+    pub fn uncovered(&self) -> Vec<Edge> {
+        let exercised = self.exercised.lock().unwrap();
+        self.expected
+            .iter()
+            .filter(|edge| !exercised.contains(edge))
+            .cloned()
+            .collect()
+    }
+
+    /// Exercised transitions that were not expected, the machine or the expectations are wrong
+    pub fn unexpected(&self) -> Vec<Edge> {
+        self.exercised()
+            .into_iter()
+            .filter(|edge| !self.expected.contains(edge))
+            .collect()
+    }
+
+    /// Share of the expected transitions exercised, from 0 to 1
+    pub fn ratio(&self) -> f64 {
+        if self.expected.is_empty() {
+            return 1.0;
+        }
+
+        let covered = self.expected.len() - self.uncovered().len();
+        covered as f64 / self.expected.len() as f64
+    }
+
+    /// Readable summary with the uncovered and unexpected transitions, one per line
+    pub fn report(&self) -> String {
+        let mut report = format!("transition coverage {:.1}%\n", self.ratio() * 100.0);
+        for (title, edges) in [
+            ("uncovered", self.uncovered()),
+            ("unexpected", self.unexpected()),
+        ] {
+            for edge in edges {
+                let arrow = match edge.event {
+                    Some(event) => format!("--{event}-->"),
+                    None => "-->".to_string(),
+                };
+                report.push_str(&format!("{title:>10}  {} {arrow} {}\n", edge.from, edge.to));
+            }
+        }
+
+        report
+    }
+
+    /// Panic with the [Coverage::report] if less than `threshold` of the expected transitions were
+    /// exercised
+    #[track_caller]
+    pub fn assert_threshold(&self, threshold: f64) {
+        if self.ratio() < threshold {
+            panic!("{}", self.report());
+        }
+    }
+}
+
+impl TransitionLog for Coverage {
+    fn append(&mut self, record: TransitionRecord) -> io::Result<()> {
+        let edge = Edge {
+            from: record.from,
+            event: record.event,
+            to: record.to,
+        };
+
+        let mut exercised = self.exercised.lock().unwrap();
+        if !exercised.contains(&edge) {
+            exercised.push(edge);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+//! proptest strategies driving machines with random events
+//!
+//! A run is generated as a list of choices rather than events, each choice picks an event from the
+//! alphabet of the state the machine is in. proptest shrinks failing runs to fewer and earlier
+//! choices, so the sequence reported is a short one that still breaks the machine
+
+use std::fmt::Debug;
+
+use proptest::{
+    arbitrary::any,
+    collection,
+    sample::{self, Index},
+    strategy::Strategy,
+    test_runner::{TestCaseError, TestRunner},
+};
+
+use crate::{
+    external_enum::{process_event, EventContext, ExternallyDrivenTransition},
+    StateName,
+};
+
+/// Up to `max_len` events picked from `alphabet`, for machines that accept any event in any state
+pub fn events<E>(alphabet: Vec<E>, max_len: usize) -> impl Strategy<Value = Vec<E>>
+where
+    E: Clone + Debug + 'static,
+{
+    collection::vec(sample::select(alphabet), 0..=max_len)
+}
+
+/// Up to `max_len` choices of events, see [check_invariant]
+pub fn choices(max_len: usize) -> impl Strategy<Value = Vec<Index>> {
+    collection::vec(any::<Index>(), 0..=max_len)
+}
+
+/// Drive a machine with `choices`, checking `invariant` on the initial state and after every
+/// transition
+///
+/// Every choice picks an event from `alphabet`, called with the current state. The run ends when
+/// the choices are over, the alphabet is empty or the machine reaches a terminal state. A state
+/// that fails breaks the invariant too. Meant to be called from a `proptest!` test with the
+/// [choices] strategy
+pub fn check_invariant<T, A, F>(
+    initial_state: T,
+    choices: &[Index],
+    alphabet: A,
+    invariant: F,
+) -> Result<(), TestCaseError>
+where
+    T: ExternallyDrivenTransition + StateName,
+    T::EventType: Clone + Debug,
+    A: Fn(&T) -> Vec<T::EventType>,
+    F: Fn(&T) -> bool,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+    let mut events = Vec::new();
+    let mut choices = choices.iter();
+
+    if !invariant(&current_state) {
+        return Err(TestCaseError::fail(format!(
+            "invariant broken in initial state {}",
+            current_state.state_name()
+        )));
+    }
+
+    while !current_state.is_terminal_state() {
+        let input = match context.next_queued() {
+            Some(input) => input,
+            None => {
+                let Some(choice) = choices.next() else {
+                    break;
+                };
+                let alphabet = alphabet(&current_state);
+                if alphabet.is_empty() {
+                    break;
+                }
+
+                let event = choice.get(&alphabet).clone();
+                events.push(event.clone());
+                event
+            }
+        };
+
+        let state = current_state.state_name();
+        current_state = process_event(current_state, input, &mut context).map_err(|err| {
+            TestCaseError::fail(format!("{state} failed after {events:?}: {err}"))
+        })?;
+
+        if !invariant(&current_state) {
+            return Err(TestCaseError::fail(format!(
+                "invariant broken in {} after {events:?}",
+                current_state.state_name()
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check `invariant` for runs of up to `max_len` events, with a fresh machine from
+/// `initial_state` for every run
+///
+/// Panics with the shortest run found that breaks the invariant, see [check_invariant]
+#[track_caller]
+pub fn assert_invariant<T, I, A, F>(initial_state: I, alphabet: A, max_len: usize, invariant: F)
+where
+    T: ExternallyDrivenTransition + StateName,
+    T::EventType: Clone + Debug,
+    I: Fn() -> T,
+    A: Fn(&T) -> Vec<T::EventType>,
+    F: Fn(&T) -> bool,
+{
+    let result = TestRunner::default().run(&choices(max_len), |choices| {
+        check_invariant(initial_state(), &choices, &alphabet, &invariant)
+    });
+
+    if let Err(err) = result {
+        panic!("{err}");
+    }
+}
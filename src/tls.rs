@@ -0,0 +1,152 @@
+//! Encrypted [NodeConnection]s with rustls
+//!
+//! Certificates and keys are DER encoded, loading them from PEM files is up to the caller
+
+use std::{
+    error::Error,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConfig, ClientConnection, RootCertStore, ServerConfig, ServerConnection, StreamOwned,
+};
+
+use crate::NodeConnection;
+
+/// Client side of TLS node connections, verifies the certificate of the node it connects to
+#[derive(Clone)]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConnector {
+    /// Trust the nodes with a certificate signed by one of `roots`
+    pub fn new(roots: Vec<CertificateDer<'static>>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(root_store(roots)?)
+            .with_no_client_auth();
+
+        Ok(Self::from_config(config))
+    }
+
+    /// Same as [TlsConnector::new], presenting `cert_chain` to nodes that verify their peers
+    pub fn with_client_auth(
+        roots: Vec<CertificateDer<'static>>,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let config = ClientConfig::builder()
+            .with_root_certificates(root_store(roots)?)
+            .with_client_auth_cert(cert_chain, key)?;
+
+        Ok(Self::from_config(config))
+    }
+
+    /// Use a config built by the caller, for custom verifiers or protocol versions
+    pub fn from_config(config: ClientConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    pub fn config(&self) -> Arc<ClientConfig> {
+        self.config.clone()
+    }
+
+    /// Connect to the node at `addr`, its certificate must be valid for `server_name`
+    ///
+    /// The handshake completes before returning, so certificate errors are reported here
+    pub fn connect(
+        &self,
+        addr: SocketAddr,
+        server_name: &str,
+        timeout: Duration,
+    ) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        let server_name = ServerName::try_from(server_name.to_string())?;
+        let mut connection = ClientConnection::new(self.config.clone(), server_name)?;
+        let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+
+        // bound the handshake by the same timeout
+        stream.set_read_timeout(Some(timeout))?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+        stream.set_read_timeout(None)?;
+
+        stream.set_nodelay(true)?;
+        Ok(NodeConnection::new(StreamOwned::new(connection, stream))?)
+    }
+}
+
+/// Server side of TLS node connections
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    config: Arc<ServerConfig>,
+}
+
+impl TlsAcceptor {
+    /// Present `cert_chain` to the connecting nodes, without verifying them
+    pub fn new(
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(cert_chain, key)?;
+
+        Ok(Self::from_config(config))
+    }
+
+    /// Same as [TlsAcceptor::new], but only accept nodes with a certificate signed by one of
+    /// `roots`
+    pub fn with_client_verification(
+        roots: Vec<CertificateDer<'static>>,
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let verifier = WebPkiClientVerifier::builder(Arc::new(root_store(roots)?)).build()?;
+        let config = ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(cert_chain, key)?;
+
+        Ok(Self::from_config(config))
+    }
+
+    pub fn from_config(config: ServerConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.config.clone()
+    }
+
+    /// Wait for the next node connecting to `listener` and complete the handshake
+    pub fn accept(
+        &self,
+        listener: &TcpListener,
+    ) -> Result<NodeConnection, Box<dyn Error + Send + Sync>> {
+        let (mut stream, _) = listener.accept()?;
+        let mut connection = ServerConnection::new(self.config.clone())?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+
+        stream.set_nodelay(true)?;
+        Ok(NodeConnection::new(StreamOwned::new(connection, stream))?)
+    }
+}
+
+fn root_store(roots: Vec<CertificateDer<'static>>) -> Result<RootCertStore, rustls::Error> {
+    let mut store = RootCertStore::empty();
+    for root in roots {
+        store.add(root)?;
+    }
+
+    Ok(store)
+}
@@ -0,0 +1,99 @@
+//! Executors that keep the last states visited by the machine, attached to the error when it fails
+//!
+//! Only the last `capacity` states are kept, so a long running machine doesn't grow its trace
+//! forever
+
+use std::{collections::VecDeque, error::Error};
+
+use crate::{
+    error::{MachineError, TracedError},
+    event_source::EventSource,
+    external_enum::{process_event, EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    StateName,
+};
+
+/// Same as [crate::internal_enum::internally_driven_executor], but fails with a [TracedError]
+/// holding the last `capacity` states visited
+pub fn internally_driven_executor_with_trace<T>(
+    initial_state: T,
+    capacity: usize,
+) -> Result<T, TracedError>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    let mut current_state = initial_state;
+    let mut trace = Trace::new(capacity);
+
+    while !current_state.is_terminal_state() {
+        trace.visit(current_state.state_name());
+        current_state = current_state.execute().map_err(|err| trace.fail(err))?;
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [crate::external_enum::externally_driven_executor], but fails with a [TracedError]
+/// holding the last `capacity` states visited
+///
+/// A state that executes several events in a row is visited once
+pub fn externally_driven_executor_with_trace<T, S>(
+    initial_state: T,
+    mut events: S,
+    capacity: usize,
+) -> Result<T, TracedError>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: EventSource<T::EventType>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+    let mut trace = Trace::new(capacity);
+
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        trace.visit(current_state.state_name());
+        current_state =
+            process_event(current_state, input, &mut context).map_err(|err| trace.fail(err))?;
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Ring buffer of the last states visited
+struct Trace {
+    states: VecDeque<&'static str>,
+    capacity: usize,
+}
+
+impl Trace {
+    fn new(capacity: usize) -> Self {
+        Self {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    fn visit(&mut self, state: &'static str) {
+        if self.capacity == 0 || self.states.back() == Some(&state) {
+            return;
+        }
+
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+
+    fn fail(&mut self, error: Box<dyn Error>) -> TracedError {
+        TracedError {
+            trace: self.states.drain(..).collect(),
+            error: MachineError::from(error),
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    error::Error,
+    fmt::Debug,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    external_enum::{EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    StateName,
+};
+
+/// A single transition of a machine run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionRecord {
+    pub from: &'static str,
+    /// Debug representation of the event that caused the transition, internally driven machines
+    /// don't have events
+    pub event: Option<String>,
+    pub to: &'static str,
+    pub timestamp: SystemTime,
+}
+
+/// Append only log of transitions
+pub trait TransitionLog {
+    fn append(&mut self, record: TransitionRecord) -> io::Result<()>;
+}
+
+/// Keeps all the records in memory
+#[derive(Debug, Default)]
+pub struct InMemoryLog {
+    records: Vec<TransitionRecord>,
+}
+
+impl InMemoryLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> &[TransitionRecord] {
+        &self.records
+    }
+}
+
+impl TransitionLog for InMemoryLog {
+    fn append(&mut self, record: TransitionRecord) -> io::Result<()> {
+        self.records.push(record);
+        Ok(())
+    }
+}
+
+/// Appends the records to a file, one tab separated line per record:
+/// `timestamp_millis from event to`, with `-` when there is no event
+pub struct FileLog {
+    file: File,
+}
+
+impl FileLog {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file })
+    }
+}
+
+impl TransitionLog for FileLog {
+    fn append(&mut self, record: TransitionRecord) -> io::Result<()> {
+        let timestamp = record
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let event = record.event.as_deref().unwrap_or("-");
+
+        // A single write per record, so a crash can't interleave partial lines
+        let line = format!("{timestamp}\t{}\t{event}\t{}\n", record.from, record.to);
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// Wraps a state machine, appending every transition to a [TransitionLog].
+///
+/// Works with any of the enum executors
+pub struct Logged<T, L> {
+    machine: T,
+    log: L,
+    last_event: Option<String>,
+    error: Option<io::Error>,
+}
+
+impl<T, L> Logged<T, L>
+where
+    T: StateName,
+    L: TransitionLog,
+{
+    pub fn new(machine: T, log: L) -> Self {
+        Self {
+            machine,
+            log,
+            last_event: None,
+            error: None,
+        }
+    }
+
+    /// Unwrap the machine and the log, failing if a record couldn't be appended
+    pub fn finish(self) -> io::Result<(T, L)> {
+        match self.error {
+            Some(err) => Err(err),
+            None => Ok((self.machine, self.log)),
+        }
+    }
+
+    fn append(&mut self, from: &'static str, event: Option<String>) -> io::Result<()> {
+        self.log.append(TransitionRecord {
+            from,
+            event,
+            to: self.machine.state_name(),
+            timestamp: SystemTime::now(),
+        })
+    }
+}
+
+impl<T, L> InternallyDrivenTransition for Logged<T, L>
+where
+    T: InternallyDrivenTransition + StateName,
+    L: TransitionLog,
+{
+    fn execute(self) -> Result<Self, Box<dyn Error>>
+    where
+        Self: Sized,
+    {
+        let from = self.machine.state_name();
+        let mut logged = Self {
+            machine: self.machine.execute()?,
+            ..self
+        };
+
+        logged.append(from, None)?;
+        Ok(logged)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+}
+
+impl<T, L> ExternallyDrivenTransition for Logged<T, L>
+where
+    T: ExternallyDrivenTransition + StateName,
+    T::EventType: Debug,
+    L: TransitionLog,
+{
+    type EventType = T::EventType;
+
+    fn execute(
+        &mut self,
+        input: Self::EventType,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        // transition can't fail, so append errors are reported here
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+
+        self.last_event = Some(format!("{input:?}"));
+        self.machine.execute(input, context)
+    }
+
+    fn accepts_batch(&self) -> bool {
+        self.machine.accepts_batch()
+    }
+
+    fn execute_batch(
+        &mut self,
+        inputs: Vec<Self::EventType>,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(err) = self.error.take() {
+            return Err(err.into());
+        }
+
+        self.last_event = Some(format!("{inputs:?}"));
+        self.machine.execute_batch(inputs, context)
+    }
+
+    fn is_terminal_state(&self) -> bool {
+        self.machine.is_terminal_state()
+    }
+
+    fn on_shutdown(&mut self) -> Result<(), Box<dyn Error>> {
+        self.machine.on_shutdown()
+    }
+
+    fn transition(self) -> Self {
+        let from = self.machine.state_name();
+        let mut logged = Self {
+            machine: self.machine.transition(),
+            ..self
+        };
+
+        let event = logged.last_event.take();
+        if let Err(err) = logged.append(from, event) {
+            logged.error.get_or_insert(err);
+        }
+
+        logged
+    }
+
+    /// Unlike [ExternallyDrivenTransition::transition], a failed append fails right away
+    fn try_transition(self) -> Result<Self, Box<dyn Error>> {
+        let from = self.machine.state_name();
+        let mut logged = Self {
+            machine: self.machine.try_transition()?,
+            ..self
+        };
+
+        let event = logged.last_event.take();
+        logged.append(from, event)?;
+        Ok(logged)
+    }
+
+    fn can_handle(&self, input: &Self::EventType) -> bool {
+        self.machine.can_handle(input)
+    }
+
+    fn should_defer(&self, input: &Self::EventType) -> bool {
+        self.machine.should_defer(input)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.machine.timeout()
+    }
+
+    fn timeout_event(&self) -> Option<Self::EventType> {
+        self.machine.timeout_event()
+    }
+
+    fn on_idle(
+        &mut self,
+        context: &mut EventContext<Self::EventType>,
+    ) -> Result<(), Box<dyn Error>> {
+        self.machine.on_idle(context)
+    }
+}
@@ -0,0 +1,191 @@
+//! Length-prefixed framing used by [crate::NodeConnection]
+//!
+//! Every frame is a big endian `u32` length, a [FrameKind] byte and the message bytes
+
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+/// Port the nodes listen on for connections of the other nodes
+pub const PORT: u16 = 4750;
+
+/// Timeout of [crate::NodeConnection::connect]
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest message accepted by [read_frame], a corrupted length prefix fails instead of
+/// allocating gigabytes
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Heartbeats share the stream with the messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Message,
+    Ping,
+    Pong,
+}
+
+impl FrameKind {
+    fn from_byte(byte: u8) -> io::Result<Self> {
+        match byte {
+            0 => Ok(FrameKind::Message),
+            1 => Ok(FrameKind::Ping),
+            2 => Ok(FrameKind::Pong),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unknown frame kind",
+            )),
+        }
+    }
+}
+
+pub fn write_frame<W: Write>(writer: &mut W, kind: FrameKind, message: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(message.len())
+        .ok()
+        .filter(|len| *len as usize <= MAX_FRAME_LEN)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "message too large"))?;
+
+    let mut header = [0; 5];
+    header[..4].copy_from_slice(&len.to_be_bytes());
+    header[4] = kind as u8;
+
+    writer.write_all(&header)?;
+    writer.write_all(message)?;
+    writer.flush()
+}
+
+/// Read a whole frame, the bytes already read are lost if the read fails or times out midway,
+/// see [FrameReader] to resume it
+pub fn read_frame<R: Read>(reader: &mut R) -> io::Result<(FrameKind, Vec<u8>)> {
+    let mut header = [0; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+
+    let (kind, len) = parse_header(&header)?;
+    let mut message = vec![0; len];
+    reader.read_exact(&mut message)?;
+    Ok((kind, message))
+}
+
+/// Length prefix and [FrameKind] byte
+pub(crate) const HEADER_LEN: usize = 5;
+
+fn parse_header(header: &[u8]) -> io::Result<(FrameKind, usize)> {
+    let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame exceeds the maximum length",
+        ));
+    }
+
+    Ok((FrameKind::from_byte(header[4])?, len))
+}
+
+/// Reads frames keeping the bytes of a partial frame, so a read that times out midway is resumed
+/// by the next one instead of desynchronizing the stream
+#[derive(Debug, Default)]
+pub struct FrameReader {
+    buffer: Vec<u8>,
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Same as [read_frame], but a failed read keeps what was read so far
+    pub fn read_frame<R: Read + ?Sized>(
+        &mut self,
+        reader: &mut R,
+    ) -> io::Result<(FrameKind, Vec<u8>)> {
+        loop {
+            let missing = match self.buffer.get(..HEADER_LEN) {
+                Some(header) => {
+                    let (kind, len) = parse_header(header)?;
+                    if self.buffer.len() >= HEADER_LEN + len {
+                        let message = self.buffer[HEADER_LEN..HEADER_LEN + len].to_vec();
+                        self.buffer.drain(..HEADER_LEN + len);
+                        return Ok((kind, message));
+                    }
+
+                    HEADER_LEN + len - self.buffer.len()
+                }
+                None => HEADER_LEN - self.buffer.len(),
+            };
+
+            let start = self.buffer.len();
+            self.buffer.resize(start + missing, 0);
+            match reader.read(&mut self.buffer[start..]) {
+                Ok(0) => {
+                    self.buffer.truncate(start);
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(read) => self.buffer.truncate(start + read),
+                Err(err) => {
+                    self.buffer.truncate(start);
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Byte stream under a [crate::NodeConnection], messages are framed on top of it
+///
+/// Implemented for TCP, TLS over TCP with the `tls` feature and QUIC streams with the `quic`
+/// feature
+pub trait Transport: Read + Write + Send {
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// `None` blocks reads until data arrives
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Transport for TcpStream {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::peer_addr(self)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        TcpStream::local_addr(self)
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Transport for rustls::StreamOwned<rustls::ClientConnection, TcpStream> {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.peer_addr()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Transport for rustls::StreamOwned<rustls::ServerConnection, TcpStream> {
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.peer_addr()
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sock.local_addr()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+}
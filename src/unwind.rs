@@ -0,0 +1,136 @@
+//! Executors that contain the panics of the states, instead of unwinding through the caller
+//!
+//! A panicking state is consumed, so the machine either fails with [MachineError::StatePanicked] or
+//! continues from a failure state built from the error
+
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+};
+
+use crate::{
+    error::{MachineError, StatePanickedError},
+    event_source::EventSource,
+    external_enum::{process_event, EventContext, ExternallyDrivenTransition},
+    internal_enum::InternallyDrivenTransition,
+    StateName,
+};
+
+/// Same as [crate::internal_enum::internally_driven_executor], but a panic of a state fails with
+/// [MachineError::StatePanicked]
+pub fn internally_driven_executor_catch_unwind<T>(initial_state: T) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition + StateName,
+{
+    run_internally_driven(initial_state, |err| Err(err.into()))
+}
+
+/// Same as [internally_driven_executor_catch_unwind], but the machine continues from the state
+/// returned by `failure_state` after a panic
+pub fn internally_driven_executor_with_failure_state<T, F>(
+    initial_state: T,
+    mut failure_state: F,
+) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition + StateName,
+    F: FnMut(StatePanickedError) -> T,
+{
+    run_internally_driven(initial_state, |err| Ok(failure_state(err)))
+}
+
+fn run_internally_driven<T, F>(initial_state: T, mut on_panic: F) -> Result<T, MachineError>
+where
+    T: InternallyDrivenTransition + StateName,
+    F: FnMut(StatePanickedError) -> Result<T, MachineError>,
+{
+    let mut current_state = initial_state;
+
+    while !current_state.is_terminal_state() {
+        let state = current_state.state_name();
+        current_state = match panic::catch_unwind(AssertUnwindSafe(|| current_state.execute())) {
+            Ok(next_state) => next_state?,
+            Err(payload) => on_panic(StatePanickedError {
+                state,
+                payload: message(payload),
+            })?,
+        };
+    }
+
+    Ok(current_state)
+}
+
+/// Same as [crate::external_enum::externally_driven_executor], but a panic of a state, while
+/// executing an event or transitioning, fails with
+/// [MachineError::StatePanicked]
+pub fn externally_driven_executor_catch_unwind<T, S>(
+    initial_state: T,
+    events: S,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: EventSource<T::EventType>,
+{
+    run_externally_driven(initial_state, events, |err| Err(err.into()))
+}
+
+/// Same as [externally_driven_executor_catch_unwind], but the machine continues from the state
+/// returned by `failure_state` after a panic
+pub fn externally_driven_executor_with_failure_state<T, S, F>(
+    initial_state: T,
+    events: S,
+    mut failure_state: F,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: EventSource<T::EventType>,
+    F: FnMut(StatePanickedError) -> T,
+{
+    run_externally_driven(initial_state, events, |err| Ok(failure_state(err)))
+}
+
+fn run_externally_driven<T, S, F>(
+    initial_state: T,
+    mut events: S,
+    mut on_panic: F,
+) -> Result<T, MachineError>
+where
+    T: ExternallyDrivenTransition + StateName,
+    S: EventSource<T::EventType>,
+    F: FnMut(StatePanickedError) -> Result<T, MachineError>,
+{
+    let mut current_state = initial_state;
+    let mut context = EventContext::new();
+
+    while let Some(input) = context
+        .next_queued()
+        .or_else(|| EventSource::next(&mut events))
+    {
+        let state = current_state.state_name();
+        current_state = match panic::catch_unwind(AssertUnwindSafe(|| {
+            process_event(current_state, input, &mut context)
+        })) {
+            Ok(next_state) => next_state?,
+            Err(payload) => on_panic(StatePanickedError {
+                state,
+                payload: message(payload),
+            })?,
+        };
+
+        if current_state.is_terminal_state() {
+            break;
+        }
+    }
+
+    Ok(current_state)
+}
+
+/// Message of a panic, panics usually carry a `&str` or a `String`
+fn message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "non string payload".to_string(),
+        },
+    }
+}